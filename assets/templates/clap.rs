@@ -0,0 +1,34 @@
+/*[toml]
+[dependencies]
+clap = { version = "4", features = ["derive"] }
+*/
+
+/// {{name}}: a command-line tool using `clap`.
+///
+/// Author: {{author}}, created {{date}}.
+///
+/// E.g. `thag {{name}}.rs -- --name World --count 2`
+//# Purpose: Describe what `{{name}}` does.
+//# Categories: CLI
+//# Sample arguments: `-- --name World --count 2`
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+#[command(name = "{{name}}", version, about, long_about = None)]
+struct Cli {
+    /// Name of the person to greet
+    #[arg(short, long, default_value = "World")]
+    name: String,
+
+    /// Number of times to greet
+    #[arg(short, long, default_value_t = 1)]
+    count: u8,
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    for _ in 0..cli.count {
+        println!("Hello, {}!", cli.name);
+    }
+}
@@ -0,0 +1,14 @@
+/*[toml]
+[dependencies]
+*/
+
+/// {{name}}: a new script.
+///
+/// Author: {{author}}, created {{date}}.
+///
+/// E.g. `thag {{name}}.rs`
+//# Purpose: Describe what `{{name}}` does.
+//# Categories: prototype
+fn main() {
+    println!("Hello from {{name}}!");
+}
@@ -0,0 +1,25 @@
+/*[toml]
+[dependencies]
+thag_profiler = { version = "1, thag-auto", features = ["time_profiling"] }
+*/
+
+/// {{name}}: a program instrumented for profiling with `thag_profiler`.
+///
+/// Author: {{author}}, created {{date}}.
+///
+/// E.g. `THAG_PROFILER=time,,announce thag {{name}}.rs`, then analyze the output with `thag_profile`.
+//# Purpose: Describe what `{{name}}` does.
+//# Categories: profiling
+use thag_profiler::{enable_profiling, profiled};
+
+#[profiled]
+fn fibonacci(n: u64) -> u64 {
+    (1..n).fold((0, 1), |(a, b), _| (b, a + b)).1
+}
+
+#[enable_profiling(runtime)]
+fn main() {
+    for n in [10, 20, 40, 80] {
+        println!("fibonacci({n}) = {}", fibonacci(n));
+    }
+}
@@ -0,0 +1,47 @@
+/*[toml]
+[dependencies]
+ratatui = "0.29"
+*/
+
+/// {{name}}: a terminal user interface using `ratatui`.
+///
+/// Author: {{author}}, created {{date}}.
+///
+/// E.g. `thag {{name}}.rs`, then press `q` or `Esc` to quit.
+//# Purpose: Describe what `{{name}}` does.
+//# Categories: tui
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::{
+    layout::Alignment,
+    widgets::{Block, Borders, Paragraph},
+    DefaultTerminal, Frame,
+};
+use std::io;
+
+fn main() -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+fn run(terminal: &mut DefaultTerminal) -> io::Result<()> {
+    loop {
+        terminal.draw(draw)?;
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn draw(frame: &mut Frame) {
+    let block = Block::default()
+        .title(" {{name}} ")
+        .borders(Borders::ALL);
+    let paragraph = Paragraph::new("Hello from {{name}}! Press q to quit.")
+        .alignment(Alignment::Center)
+        .block(block);
+    frame.render_widget(paragraph, frame.area());
+}
@@ -0,0 +1,27 @@
+/*[toml]
+[dependencies]
+thag_styling = { version = "1, thag-auto" }
+*/
+
+/// {{name}}: a program with output styled by `thag_styling` to suit the terminal theme.
+///
+/// Author: {{author}}, created {{date}}.
+///
+/// E.g. `thag {{name}}.rs`
+//# Purpose: Describe what `{{name}}` does.
+//# Categories: styling
+use thag_styling::{Role, Style, TermAttributes};
+
+fn main() {
+    let attrs = TermAttributes::get_or_init();
+    println!(
+        "{}",
+        Style::for_role(Role::Heading1).paint("Hello from {{name}}!")
+    );
+    println!(
+        "{} {}",
+        Style::for_role(Role::Info).paint("Theme:"),
+        attrs.theme.name
+    );
+    println!("{}", Style::for_role(Role::Success).paint("All good"));
+}
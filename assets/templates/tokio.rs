@@ -0,0 +1,31 @@
+/*[toml]
+[dependencies]
+tokio = { version = "1", features = ["full"] }
+*/
+
+/// {{name}}: an async program using `tokio`.
+///
+/// Author: {{author}}, created {{date}}.
+///
+/// E.g. `thag {{name}}.rs`
+//# Purpose: Describe what `{{name}}` does.
+//# Categories: async
+use std::time::Duration;
+use tokio::time::sleep;
+
+async fn task(id: u64) -> u64 {
+    sleep(Duration::from_millis(100 * id)).await;
+    id * id
+}
+
+#[tokio::main]
+async fn main() {
+    let handles: Vec<_> = (1..=3).map(|id| tokio::spawn(task(id))).collect();
+
+    for handle in handles {
+        match handle.await {
+            Ok(result) => println!("Task finished with {result}"),
+            Err(e) => eprintln!("Task failed: {e}"),
+        }
+    }
+}
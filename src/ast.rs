@@ -542,6 +542,54 @@ pub fn find_metadata(syntax_tree: &Ast) -> MetadataFinder {
     metadata_finder
 }
 
+/// Determine whether a snippet uses `.await` or an `async` block outside of any function or
+/// async closure of its own, so that it must be wrapped in an async main method.
+///
+/// Macro arguments are not parsed by `syn`, so they are checked for `.await` and `async` blocks
+/// in their tokens.
+#[must_use]
+#[profiled]
+pub fn needs_async_main(syntax_tree: &Ast) -> bool {
+    #[derive(Default)]
+    struct AsyncFinder {
+        found: bool,
+    }
+
+    impl<'ast> Visit<'ast> for AsyncFinder {
+        fn visit_expr_await(&mut self, _node: &'ast syn::ExprAwait) {
+            self.found = true;
+        }
+
+        fn visit_expr_async(&mut self, _node: &'ast syn::ExprAsync) {
+            self.found = true;
+        }
+
+        // Functions and other items have a context of their own
+        fn visit_item(&mut self, _node: &'ast Item) {}
+
+        fn visit_expr_closure(&mut self, node: &'ast syn::ExprClosure) {
+            if node.asyncness.is_none() {
+                syn::visit::visit_expr_closure(self, node);
+            }
+        }
+
+        fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+            let tokens = mac.tokens.to_string();
+            if re!(r"\.\s*await\b|\basync\s+(move\s+)?\{").is_match(&tokens) {
+                self.found = true;
+            }
+        }
+    }
+
+    let mut async_finder = AsyncFinder::default();
+    match syntax_tree {
+        // A file has no statements outside of its items
+        Ast::File(_) => {}
+        Ast::Expr(expr) => async_finder.visit_expr(expr),
+    }
+    async_finder.found
+}
+
 /// Determines whether a dependency name should be filtered out from the dependency list.
 ///
 /// This function filters out dependency names that are:
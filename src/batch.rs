@@ -0,0 +1,421 @@
+//!
+//! Batch checking or building of every script in a directory, invoked by the `--check-all` and
+//! `--build-all` options.
+//!
+//! Each script is checked or built by a separate `thag --check` or `thag --build` process, up to
+//! `--jobs` of them at a time, so that the scripts share the usual target directory and executable
+//! cache and benefit from the usual staleness checks. Any script-level settings in a script's toml
+//! block apply as they would for the script on its own.
+//!
+//! The results are reported as a styled table of passes and failures with their durations and
+//! the first error line of each failure, or with `--report json` or `--report junit` as a JSON
+//! document or a JUnit XML test report on stdout for a CI system to consume.
+//!
+use crate::cmd_args::ReportFormat;
+use crate::{Cli, Role, ThagError, ThagResult, RS_SUFFIX};
+use serde::Serialize;
+use std::{
+    env,
+    fmt::{self, Write as _},
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use thag_common::{debug_log, re, set_messages_to_stderr, vprtln, V};
+use thag_profiler::profiled;
+use thag_styling::paint_for_role;
+
+/// Whether a batch checks or builds its scripts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BatchMode {
+    /// Just cargo check each script, as with `--check`
+    Check,
+    /// Build each script, as with `--build`
+    Build,
+}
+
+impl BatchMode {
+    /// The `thag` option that processes a single script in this mode.
+    #[must_use]
+    pub const fn option(self) -> &'static str {
+        match self {
+            Self::Check => "--check",
+            Self::Build => "--build",
+        }
+    }
+}
+
+impl fmt::Display for BatchMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Check => write!(f, "check"),
+            Self::Build => write!(f, "build"),
+        }
+    }
+}
+
+/// The outcome of checking or building one script of a batch.
+#[derive(Clone, Debug, Serialize)]
+pub struct BatchResult {
+    /// The path of the script
+    pub script: PathBuf,
+    /// Whether the script checked or built successfully
+    pub passed: bool,
+    /// How long the check or build took
+    #[serde(rename = "duration_ms", serialize_with = "serialize_millis")]
+    pub duration: Duration,
+    /// The first error line reported for a failure
+    pub error: Option<String>,
+}
+
+impl BatchResult {
+    /// The file name of the script.
+    #[must_use]
+    pub fn name(&self) -> String {
+        self.script
+            .file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+    }
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn serialize_millis<S: serde::Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(u64::try_from(duration.as_millis()).unwrap_or(u64::MAX))
+}
+
+/// List the scripts in `dir`, in name order. Subdirectories are not searched.
+///
+/// # Errors
+///
+/// Will return `Err` if `dir` is not a directory or cannot be read.
+#[profiled]
+pub fn find_scripts(dir: &Path) -> ThagResult<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Err(format!("Directory `{}` does not exist", dir.display()).into());
+    }
+    let mut scripts = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.to_string_lossy().ends_with(RS_SUFFIX) {
+            scripts.push(path);
+        }
+    }
+    scripts.sort();
+    Ok(scripts)
+}
+
+/// Check or build each of `scripts` by running the `thag` executable at `thag` on it with the
+/// `mode` option and `options`, at most `jobs` at a time. `on_result` is called as each script
+/// finishes. Returns the results in the order of `scripts`.
+#[profiled]
+pub fn run_all(
+    thag: &Path,
+    scripts: &[PathBuf],
+    mode: BatchMode,
+    options: &[String],
+    jobs: usize,
+    on_result: impl Fn(&BatchResult) + Sync,
+) -> Vec<BatchResult> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(scripts.len()));
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, scripts.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(script) = scripts.get(index) else {
+                    break;
+                };
+                let result = run_one(thag, script, mode, options);
+                on_result(&result);
+                if let Ok(mut results) = results.lock() {
+                    results.push((index, result));
+                }
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap_or_default();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[profiled]
+fn run_one(thag: &Path, script: &Path, mode: BatchMode, options: &[String]) -> BatchResult {
+    let start = Instant::now();
+    debug_log!("Batch: {} {}", mode, script.display());
+    let output = Command::new(thag)
+        .arg(mode.option())
+        // Normal verbosity, so that thag's own error message is reported
+        .arg("--normal")
+        .args(options)
+        .arg(script)
+        // No prompting, and no terminal queries from processes running side by side
+        .stdin(Stdio::null())
+        .output();
+    let duration = start.elapsed();
+    let (passed, error) = match output {
+        Ok(output) if output.status.success() => (true, None),
+        Ok(output) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let error =
+                first_error(&stderr, &stdout).unwrap_or_else(|| match output.status.code() {
+                    Some(code) => format!("thag exited with code {code}"),
+                    None => "thag was terminated by a signal".to_string(),
+                });
+            (false, Some(error))
+        }
+        Err(e) => (
+            false,
+            Some(format!("Could not run {}: {e}", thag.display())),
+        ),
+    };
+    BatchResult {
+        script: script.to_path_buf(),
+        passed,
+        duration,
+        error,
+    }
+}
+
+/// The first error line reported by a failed check or build, without any styling: the first
+/// compiler error on standard error, or else `thag`'s own error message on standard output, or
+/// else the last non-blank line of standard error.
+#[must_use]
+pub fn first_error(stderr: &str, stdout: &str) -> Option<String> {
+    let unstyled = |output: &str| -> Vec<String> {
+        output
+            .lines()
+            .map(|line| {
+                re!(r"\x1b\[[0-9;]*m")
+                    .replace_all(line, "")
+                    .trim()
+                    .to_string()
+            })
+            .filter(|line| !line.is_empty())
+            .collect()
+    };
+    let stderr_lines = unstyled(stderr);
+    stderr_lines
+        .iter()
+        .find(|line| line.starts_with("error"))
+        .cloned()
+        .or_else(|| {
+            unstyled(stdout).into_iter().find_map(|line| {
+                line.strip_prefix("Error running thag: ")
+                    .map(ToString::to_string)
+            })
+        })
+        .or_else(|| stderr_lines.last().cloned())
+}
+
+/// Format the results of a batch as a JSON document.
+///
+/// # Errors
+///
+/// Will return `Err` if the results cannot be serialized.
+#[profiled]
+pub fn to_json(dir: &Path, mode: BatchMode, results: &[BatchResult]) -> ThagResult<String> {
+    #[derive(Serialize)]
+    struct Report<'a> {
+        dir: &'a Path,
+        mode: String,
+        passed: usize,
+        failed: usize,
+        results: &'a [BatchResult],
+    }
+
+    let passed = results.iter().filter(|result| result.passed).count();
+    let report = Report {
+        dir,
+        mode: mode.to_string(),
+        passed,
+        failed: results.len() - passed,
+        results,
+    };
+    serde_json::to_string_pretty(&report).map_err(|e| ThagError::FromStr(e.to_string().into()))
+}
+
+/// Format the results of a batch as a `JUnit` XML test report, with a test case for each script.
+#[must_use]
+#[profiled]
+pub fn to_junit(dir: &Path, mode: BatchMode, results: &[BatchResult]) -> String {
+    let failures = results.iter().filter(|result| !result.passed).count();
+    let total: Duration = results.iter().map(|result| result.duration).sum();
+    let suite = xml_escape(&format!("thag {mode} {}", dir.display()));
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    // Writing to a String can't fail
+    let _ = writeln!(
+        xml,
+        "<testsuite name=\"{suite}\" tests=\"{}\" failures=\"{failures}\" errors=\"0\" time=\"{:.3}\">",
+        results.len(),
+        total.as_secs_f64()
+    );
+    for result in results {
+        let name = xml_escape(&result.name());
+        let time = result.duration.as_secs_f64();
+        let _ = if let Some(ref error) = result.error {
+            let error = xml_escape(error);
+            writeln!(
+                xml,
+                "  <testcase classname=\"{suite}\" name=\"{name}\" time=\"{time:.3}\">\n    <failure message=\"{error}\">{error}</failure>\n  </testcase>"
+            )
+        } else {
+            writeln!(
+                xml,
+                "  <testcase classname=\"{suite}\" name=\"{name}\" time=\"{time:.3}\"/>"
+            )
+        };
+    }
+    xml.push_str("</testsuite>\n");
+    xml
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[profiled]
+fn display_table(results: &[BatchResult]) {
+    let name_width = results
+        .iter()
+        .map(|result| result.name().len())
+        .max()
+        .unwrap_or_default()
+        .max("SCRIPT".len());
+    println!("{:<name_width$}  RESULT  {:>8}  ERROR", "SCRIPT", "TIME");
+    for result in results {
+        let outcome = if result.passed {
+            paint_for_role(Role::Success, "pass  ")
+        } else {
+            paint_for_role(Role::Error, "FAIL  ")
+        };
+        let error = result
+            .error
+            .as_deref()
+            .map_or_else(String::new, |error| paint_for_role(Role::Subtle, error));
+        println!(
+            "{:<name_width$}  {outcome}  {:>7.1}s  {error}",
+            result.name(),
+            result.duration.as_secs_f64()
+        );
+    }
+}
+
+/// The options of a batch command line to pass on to the `thag` process for each script: all
+/// those that affect how a script is generated or built.
+#[must_use]
+pub fn script_options(cli: &Cli) -> Vec<String> {
+    let mut options = vec![];
+    for (set, option) in [
+        (cli.force, "--force"),
+        (cli.multimain, "--multimain"),
+        (cli.offline, "--offline"),
+        (cli.release, "--release"),
+        (cli.lock, "--lock"),
+        (cli.update_lock, "--update-lock"),
+    ] {
+        if set {
+            options.push(option.to_string());
+        }
+    }
+    let infer = cli.infer.as_ref().map(ToString::to_string);
+    for (value, option) in [
+        (&cli.features, "--features"),
+        (&infer, "--infer"),
+        (&cli.profile, "--profile"),
+        (&cli.edition, "--edition"),
+    ] {
+        if let Some(value) = value {
+            options.push(option.to_string());
+            options.push(value.clone());
+        }
+    }
+    options
+}
+
+/// Carry out a `--check-all` or `--build-all` batch, reporting the results in the requested
+/// format.
+///
+/// # Errors
+///
+/// Will return `Err` if the directory cannot be read or the `thag` executable cannot be found,
+/// or if any of the scripts failed.
+#[profiled]
+pub fn execute(cli: &Cli) -> ThagResult<()> {
+    let (dir, mode) = match (&cli.check_all, &cli.build_all) {
+        (Some(dir), _) => (Path::new(dir), BatchMode::Check),
+        (None, Some(dir)) => (Path::new(dir), BatchMode::Build),
+        (None, None) => return Err("Missing --check-all or --build-all directory".into()),
+    };
+    let scripts = find_scripts(dir)?;
+    let jobs = cli.jobs.map_or_else(
+        || thread::available_parallelism().map_or(1, std::num::NonZero::get),
+        usize::from,
+    );
+    let report = cli.report.unwrap_or_default();
+    let is_human = report == ReportFormat::Human;
+    if !is_human {
+        // Keep stdout for the report, including when the batch fails
+        set_messages_to_stderr(true);
+    }
+    if is_human {
+        vprtln!(
+            V::N,
+            "Running {mode} on {} script(s) in {} with up to {jobs} job(s)",
+            scripts.len(),
+            dir.display()
+        );
+    }
+
+    let thag = env::current_exe()?;
+    let start = Instant::now();
+    let results = run_all(
+        &thag,
+        &scripts,
+        mode,
+        &script_options(cli),
+        jobs,
+        |result| {
+            if is_human {
+                vprtln!(
+                    V::V,
+                    "{} {}",
+                    if result.passed { "passed" } else { "FAILED" },
+                    result.script.display()
+                );
+            }
+        },
+    );
+
+    let failed = results.iter().filter(|result| !result.passed).count();
+    match report {
+        ReportFormat::Human => {
+            display_table(&results);
+            vprtln!(
+                V::N,
+                "{} passed, {failed} failed in {:.1}s",
+                results.len() - failed,
+                start.elapsed().as_secs_f64()
+            );
+        }
+        ReportFormat::Json => println!("{}", to_json(dir, mode, &results)?),
+        ReportFormat::Junit => print!("{}", to_junit(dir, mode, &results)),
+    }
+    if failed > 0 {
+        return Err(format!("{failed} of {} script(s) failed to {mode}", results.len()).into());
+    }
+    Ok(())
+}
@@ -34,7 +34,7 @@ pub fn main() {
         if let Err(e) = result {
             svprtln!(Role::ERR, V::N, "Error running thag: {e}");
 
-            std::process::exit(1);
+            std::process::exit(e.exit_code());
         }
     }
 
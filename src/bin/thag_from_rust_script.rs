@@ -1,4 +1,7 @@
-/// Converts embedded manifest format from `rust-script` to `thag`.
+/// Converts embedded manifest format from `rust-script` or Cargo script to `thag`.
+///
+/// Either a `rust-script` ```` //! ```cargo ```` doc comment block or a Cargo script `---cargo`
+/// frontmatter block at the start of the script is converted to a `/*[toml]` block.
 ///
 /// E.g. `cat <path_to_rust_script_file> | thag -qq demo/thag_from_rust_script.rs | thag -s [-- [options] [args] ...]`
 ///
@@ -31,11 +34,44 @@ fn safe_println(line: &str) -> Result<(), io::Error> {
     Ok(())
 }
 
+// Return the fence of a Cargo script frontmatter opening line such as `---cargo`, if it is one.
+fn frontmatter_fence(line: &str) -> Option<&str> {
+    let dashes = line.chars().take_while(|&c| c == '-').count();
+    let infostring = line[dashes..].trim();
+    (dashes >= 3
+        && infostring
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ',')))
+    .then(|| &line[..dashes])
+}
+
 fn main() -> Result<(), io::Error> {
     let content = read_stdin().expect("Problem reading input");
     let mut is_cargo = false;
+    // Frontmatter may only be preceded by a shebang and blank lines
+    let mut may_open_frontmatter = true;
+    let mut frontmatter_fence_dashes: Option<&str> = None;
 
-    for line in content.lines() {
+    for (i, line) in content.lines().enumerate() {
+        if let Some(fence) = frontmatter_fence_dashes {
+            if line.trim_end() == fence {
+                frontmatter_fence_dashes = None;
+                safe_println(&format!("{}/", '*'))?;
+            } else {
+                safe_println(line)?;
+            }
+            continue;
+        }
+        if may_open_frontmatter {
+            if let Some(fence) = frontmatter_fence(line) {
+                may_open_frontmatter = false;
+                frontmatter_fence_dashes = Some(fence);
+                safe_println("/*[toml]")?;
+                continue;
+            }
+            let is_shebang = i == 0 && line.starts_with("#!") && !line.starts_with("#![");
+            may_open_frontmatter = is_shebang || line.trim().is_empty();
+        }
         if line.trim().starts_with("//!") {
             if line.contains("```cargo") {
                 // Flag cargo section
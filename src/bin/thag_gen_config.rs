@@ -32,7 +32,7 @@ use thag_common::{
     auto_help,
     config::{
         maybe_config, Config, Context, Dependencies, DependencyInference, FeatureOverride, Logging,
-        Misc, ProcMacros, RealContext, Styling,
+        Misc, ProcMacros, RealContext, Run, Styling,
     },
     help_system::check_help_and_exit,
     ColorSupport, TermBgLuma, Verbosity,
@@ -547,6 +547,9 @@ fn add_doc_comments(toml_str: &str, doc_comments: Vec<(String, String)>) -> Stri
                 "misc" => {
                     let _ = writeln!(result, "# {}\n", Misc::DOCS);
                 }
+                "run" => {
+                    let _ = writeln!(result, "# {}\n", Run::DOCS);
+                }
                 _ => {}
             }
         }
@@ -921,7 +924,14 @@ fn prompt_misc_config(current: &Misc) -> Result<Option<Misc>, Box<dyn std::error
         return Ok(None);
     };
 
-    Ok(Some(Misc { unquote }))
+    Ok(Some(Misc {
+        unquote,
+        edition: current.edition.clone(),
+        max_cache_size_mb: current.max_cache_size_mb,
+        max_dynamic_cache_entries: current.max_dynamic_cache_entries,
+        author: current.author.clone(),
+        templates_dir: current.templates_dir.clone(),
+    }))
 }
 
 fn prompt_proc_macros_config(
@@ -1,4 +1,10 @@
-/// Converts embedded manifest format from `thag` to `rust-script`.
+/// Converts embedded manifest format from `thag` to `rust-script`, or with `--cargo-script`
+/// to a Cargo script `---cargo` frontmatter block.
+///
+/// E.g. `cat <path_to_thag_script_file> | thag -qq src/bin/thag_to_rust_script.rs -- --cargo-script`
+///
+/// A Cargo script manifest must come first, so the `/*[toml]` block is moved to the top of the
+/// script, after any shebang line.
 //# Purpose: Convenience for any `thag` user who wants to try out `rust-script`.
 //# Categories: crates, tools
 use std::io::{self, Read, Write};
@@ -31,7 +37,12 @@ fn main() -> Result<(), io::Error> {
     let help = auto_help!();
     check_help_and_exit(&help);
 
+    let cargo_script = std::env::args().skip(1).any(|arg| arg == "--cargo-script");
+
     let content = read_stdin().expect("Problem reading input");
+    if cargo_script {
+        return to_cargo_script(&content);
+    }
     let mut is_cargo = false;
 
     for line in content.lines() {
@@ -57,3 +68,47 @@ fn main() -> Result<(), io::Error> {
     }
     Ok(())
 }
+
+fn to_cargo_script(content: &str) -> Result<(), io::Error> {
+    let mut lines = content.lines().peekable();
+    let shebang = lines.next_if(|line| line.starts_with("#!") && !line.starts_with("#!["));
+
+    let mut is_cargo = false;
+    let mut toml = vec![];
+    let mut rust = vec![];
+    for line in lines {
+        if !is_cargo
+            && toml.is_empty()
+            && line.trim().starts_with(format!("/{}[toml]", '*').as_str())
+        {
+            // Flag cargo section
+            is_cargo = true;
+            continue;
+        }
+        if is_cargo && line.trim() == format!("{}/", '*') {
+            // Flag end of cargo section
+            is_cargo = false;
+            continue;
+        }
+        if is_cargo {
+            toml.push(line);
+        } else {
+            rust.push(line);
+        }
+    }
+
+    if let Some(shebang) = shebang {
+        safe_println(shebang)?;
+    }
+    if !toml.is_empty() {
+        safe_println("---cargo")?;
+        for line in toml {
+            safe_println(line)?;
+        }
+        safe_println("---")?;
+    }
+    for line in rust {
+        safe_println(line)?;
+    }
+    Ok(())
+}
@@ -32,39 +32,57 @@
 //! if `force (-f)` was specified. If the `--executable (-x)` option was specified, a Cargo release build
 //! is invoked and the executable output is moved to the user's `.cargo/bin` directory, which the user
 //! should ensure is in the execution path so that it can be run as a command without further ado.
+//! Otherwise the executable is cached, separately for each Cargo profile selected by `--release` or
+//! `--profile`, so that for instance debug and release builds don't invalidate each other.
 //!
 //! Finally, if a conflicting option is not specified, the `run` function invokes `cargo run` to run the
-//! built output. Note that because of the staleness checks, a normal script that has not been modified
+//! built output. Note that because of the staleness checks, a normal script whose content has not changed
 //! since it was last built (and not been cleared from `temp_dir()` by the operating system) will skip the
 //! generation and build steps and execute almost immediately, similarly to a Cargo `run`. In this case
 //! the `build` module  will display an informational message to this effect at normal verbosity levels.
 //!
 use crate::ast::{self, is_unit_return_type};
+use crate::batch;
+use crate::cmd_args::{MessageFormat, OutputFormat};
 use crate::code_utils::{
-    self, build_loop, create_temp_source_file, extract_ast_expr, get_source_path,
-    read_file_contents, remove_inner_attributes, strip_curly_braces, to_ast, wrap_snippet,
-    write_source,
+    self, build_loop, contains_verbatim, create_temp_source_file, escape_keywords, expr_prelude,
+    extract_ast_expr, extract_frontmatter, get_source_path, print_value, read_file_contents,
+    remove_file_modules, remove_inner_attributes, strip_curly_braces, strip_frontmatter, to_ast,
+    wrap_async_snippet, wrap_snippet, write_source, EDITION_2024_KEYWORDS,
 };
 use crate::config::{self, DependencyInference, RealContext};
 use crate::crossterm::terminal;
+use crate::events::{self, Event};
+use crate::exec_cache;
+use crate::export;
+use crate::fingerprint;
+use crate::inline;
+use crate::limits::ResourceLimits;
+use crate::line_map::LineMap;
+use crate::lockfile::{self, LockedVersions};
+use crate::lookup_cache;
 use crate::manifest::extract;
+use crate::modules::{self, ScriptModule};
+use crate::offline;
+use crate::templates;
+use crate::watch::watch;
 use crate::Verbosity::{Debug as Dbug, Verbose};
 use crate::{
-    get_home_dir, get_proc_flags, get_verbosity, manifest, maybe_config, modified_since_compiled,
-    repeat_dash, validate_args, Ast, Cli, ColorSupport, Dependencies, ProcFlags, Role, ThagError,
+    get_home_dir, get_proc_flags, get_verbosity, manifest, maybe_config, repeat_dash,
+    set_verbosity, validate_args, Ast, Cli, ColorSupport, Dependencies, ProcFlags, Role, ThagError,
     ThagResult, DYNAMIC_SUBDIR, EXECUTABLE_CACHE_SUBDIR, FLOWER_BOX_LEN, ITER_SCRIPT_NAME,
     ITER_SUBDIR, PACKAGE_NAME, RS_SUFFIX, SHARED_TARGET_SUBDIR, TEMP_DIR_NAME, TEMP_SCRIPT_NAME,
     TMPDIR, TOML_NAME,
 };
-use cargo_toml::Manifest;
+use cargo_toml::{Edition, Manifest};
 use regex::Regex;
 use side_by_side_diff::create_side_by_side_diff;
 use std::env;
 use std::{
     fs::{self, OpenOptions},
-    io::Write,
+    io::{self, BufRead, IsTerminal, Write},
     path::{Path, PathBuf},
-    process::Command,
+    process::{Command, Output},
     string::ToString,
     time::Instant,
 };
@@ -172,6 +190,29 @@ pub struct BuildState {
     pub features: Option<String>,
     /// Command-line arguments to pass to the built program
     pub args: Vec<String>,
+    /// The path of the script's sidecar lockfile, if `--lock` or `--update-lock` is in effect
+    pub lock_path: Option<PathBuf>,
+    /// The package versions recorded in the script's lockfile, if it is to be restored
+    pub locked_versions: Option<LockedVersions>,
+    /// The module files declared by the script, directly or indirectly
+    pub modules: Vec<ScriptModule>,
+    /// The Cargo profile to build with, if not the default `dev` profile
+    pub profile: Option<String>,
+    /// The Rust edition specified by `--edition`, overriding the script's toml block and the configured default
+    pub edition: Option<Edition>,
+    /// The fingerprint of the script's inputs, used to detect changes since the last build
+    pub fingerprint: Option<String>,
+    /// Flag indicating whether the script is an expression, loop filter or stdin snippet, whose
+    /// executable is cached by a hash of its generated source and manifest
+    pub is_dynamic: bool,
+    /// The mapping of the generated source back to the script, for reporting compiler diagnostics
+    pub line_map: Option<LineMap>,
+    /// The resource limits on running the script
+    pub limits: ResourceLimits,
+    /// Flag recording that `gen_build` performed the generation step
+    pub generated: bool,
+    /// Flag recording that `gen_build` performed the build step
+    pub built: bool,
 }
 
 #[cfg(feature = "build")]
@@ -183,7 +224,8 @@ impl BuildState {
     /// 2. Determining execution mode flags
     /// 3. Setting up all required directory paths
     /// 4. Creating the initial build state
-    /// 5. Determining build requirements
+    /// 5. Locating any lockfile and module files and fingerprinting the script's inputs
+    /// 6. Determining build requirements
     ///
     /// # Arguments
     /// * `proc_flags` - Processing flags that control build and execution behavior
@@ -224,15 +266,31 @@ impl BuildState {
         let execution_flags = ExecutionFlags::new(proc_flags, cli);
 
         // 3. Set up directory paths
-        let paths = Self::set_up_paths(&execution_flags, script_state, &source_name, &source_stem)?;
+        let build_profile = cargo_profile(cli);
+        let paths = Self::set_up_paths(
+            &execution_flags,
+            script_state,
+            &source_name,
+            &source_stem,
+            build_profile.as_deref(),
+        )?;
 
         // 4. Create initial build state
         let mut build_state = Self::create_initial_state(paths, source_name, source_stem, cli);
 
-        // 5. Determine build requirements
+        // 5. Locate any lockfile to restore and any module files of a multi-file script,
+        // and fingerprint the script's inputs
+        build_state.configure_lock(proc_flags)?;
+        build_state.is_dynamic = execution_flags.is_dynamic;
+        if !execution_flags.is_dynamic {
+            build_state.modules = modules::find_modules(&build_state.source_path);
+            build_state.fingerprint = Some(fingerprint::inputs(&build_state)?);
+        }
+
+        // 6. Determine build requirements
         build_state.determine_build_requirements(proc_flags, script_state, &execution_flags)?;
 
-        // 6. Validate state (debug only)
+        // 7. Validate state (debug only)
         #[cfg(debug_assertions)]
         build_state.validate_state(proc_flags);
 
@@ -273,6 +331,7 @@ impl BuildState {
         script_state: &ScriptState,
         source_name: &str,
         source_stem: &str,
+        build_profile: Option<&str>,
     ) -> ThagResult<BuildPaths> {
         // Working directory setup
         let working_dir_path = if flags.is_repl {
@@ -338,8 +397,12 @@ impl BuildState {
             TMPDIR.join(PACKAGE_NAME).join(source_stem)
         };
 
-        // Target path setup - point to cached executable location
+        // Target path setup - point to cached executable location, with a subdirectory for
+        // each non-default profile so that builds with different profiles don't replace each other.
         let mut target_path = TMPDIR.join(EXECUTABLE_CACHE_SUBDIR);
+        if let Some(build_profile) = build_profile {
+            target_path = target_path.join(build_profile);
+        }
         #[cfg(target_os = "windows")]
         {
             target_path = target_path.join(format!("{source_stem}.exe"));
@@ -395,10 +458,26 @@ impl BuildState {
             ),
             args: cli.args.clone(),
             features: cli.features.clone(),
+            profile: cargo_profile(cli),
+            edition: cli.edition.as_deref().and_then(manifest::parse_edition),
+            limits: ResourceLimits::resolve(cli),
             ..Default::default()
         }
     }
 
+    #[profiled]
+    fn configure_lock(&mut self, proc_flags: &ProcFlags) -> ThagResult<()> {
+        if !proc_flags.contains(ProcFlags::LOCK) {
+            return Ok(());
+        }
+        let lock_path = lockfile::lock_path(&self.source_path);
+        if lock_path.exists() && !proc_flags.contains(ProcFlags::UPDATE_LOCK) {
+            self.locked_versions = Some(lockfile::read_locked_versions(&lock_path)?);
+        }
+        self.lock_path = Some(lock_path);
+        Ok(())
+    }
+
     #[profiled]
     fn determine_build_requirements(
         &mut self,
@@ -431,10 +510,12 @@ impl BuildState {
             return Ok(());
         }
 
-        // Case 3: Check if build is needed due to state or modifications
+        // Case 3: Check if build is needed due to state or modifications, or to create
+        // or update the script's lockfile
         if matches!(script_state, ScriptState::NamedEmpty { .. })
             || !self.target_path.exists()
-            || modified_since_compiled(self)?.is_some()
+            || (self.lock_path.is_some() && self.locked_versions.is_none())
+            || fingerprint::is_stale(self)
         {
             self.must_gen = true;
             self.must_build = true;
@@ -525,23 +606,56 @@ impl ScriptState {
     }
 }
 
+/// The Cargo profile requested by `--release` or `--profile`, if not the default `dev` profile.
+#[must_use]
+pub fn cargo_profile(cli: &Cli) -> Option<String> {
+    if cli.release {
+        return Some("release".to_string());
+    }
+    cli.profile
+        .as_ref()
+        .filter(|profile| profile.as_str() != "dev")
+        .cloned()
+}
+
+/// The subdirectory of the Cargo target directory that holds the output of a profile.
+/// Cargo's built-in profiles share `debug` and `release`, while custom profiles have their own.
+#[must_use]
+pub fn profile_dir(build_profile: Option<&str>) -> &str {
+    match build_profile {
+        None | Some("dev" | "test") => "debug",
+        Some("release" | "bench") => "release",
+        Some(custom) => custom,
+    }
+}
+
 /// Clean cached build artifacts based on the specified option.
 ///
 /// # Arguments
-/// * `what` - What to clean: "bins" (executables only), "target" (shared build cache), or "all" (both)
+/// * `what` - What to clean: "bins" (executables only, including those of expressions, filters
+///   and snippets), "target" (shared build cache), "lookups" (crate lookup cache) or "all" (all
+///   of these)
 ///
 /// # Errors
 /// Returns `ThagError` if cleanup fails
 #[profiled]
 fn clean_cache(what: &str) -> ThagResult<()> {
     let bins_dir = TMPDIR.join(EXECUTABLE_CACHE_SUBDIR);
+    let dynamic_dir = exec_cache::dynamic_cache_dir();
     let target_dir = TMPDIR.join(SHARED_TARGET_SUBDIR);
+    let lookups_dir = lookup_cache::cache_dir();
 
     match what {
         "bins" => {
-            if bins_dir.exists() {
-                vprtln!(V::N, "Cleaning executable cache: {}", bins_dir.display());
-                fs::remove_dir_all(&bins_dir)?;
+            let mut cleaned = false;
+            for dir in [&bins_dir, &dynamic_dir] {
+                if dir.exists() {
+                    vprtln!(V::N, "Cleaning executable cache: {}", dir.display());
+                    fs::remove_dir_all(dir)?;
+                    cleaned = true;
+                }
+            }
+            if cleaned {
                 vprtln!(V::N, "✓ Executable cache cleaned");
             } else {
                 vprtln!(V::N, "Executable cache does not exist");
@@ -560,22 +674,33 @@ fn clean_cache(what: &str) -> ThagResult<()> {
                 vprtln!(V::N, "Shared build cache does not exist");
             }
         }
-        "all" => {
-            let cleaned = if bins_dir.exists() {
-                vprtln!(V::N, "Cleaning executable cache: {}", bins_dir.display());
-                fs::remove_dir_all(&bins_dir)?;
-                true
-            } else if target_dir.exists() {
+        "lookups" => {
+            if lookups_dir.exists() {
                 vprtln!(
                     V::N,
-                    "Cleaning shared build cache: {}",
-                    target_dir.display()
+                    "Cleaning crate lookup cache: {}",
+                    lookups_dir.display()
                 );
-                fs::remove_dir_all(&target_dir)?;
-                true
+                fs::remove_dir_all(&lookups_dir)?;
+                vprtln!(V::N, "✓ Crate lookup cache cleaned");
             } else {
-                false
-            };
+                vprtln!(V::N, "Crate lookup cache does not exist");
+            }
+        }
+        "all" => {
+            let mut cleaned = false;
+            for (dir, description) in [
+                (&bins_dir, "executable cache"),
+                (&dynamic_dir, "dynamic executable cache"),
+                (&target_dir, "shared build cache"),
+                (&lookups_dir, "crate lookup cache"),
+            ] {
+                if dir.exists() {
+                    vprtln!(V::N, "Cleaning {description}: {}", dir.display());
+                    fs::remove_dir_all(dir)?;
+                    cleaned = true;
+                }
+            }
             if cleaned {
                 vprtln!(V::N, "✓ All caches cleaned");
             } else {
@@ -583,9 +708,10 @@ fn clean_cache(what: &str) -> ThagResult<()> {
             }
         }
         _ => {
-            return Err(
-                format!("Invalid clean option: '{what}'. Use 'bins', 'target', or 'all'").into(),
-            );
+            return Err(format!(
+                "Invalid clean option: '{what}'. Use 'bins', 'target', 'lookups' or 'all'"
+            )
+            .into());
         }
     }
 
@@ -605,6 +731,11 @@ pub fn execute(args: &mut Cli) -> ThagResult<()> {
     // Initialize TermAttributes for message styling
     TermAttributes::get_or_init();
 
+    // Script-level settings fill in options not given on the command line
+    if manifest::apply_script_settings(args)? {
+        set_verbosity(args)?;
+    }
+
     let proc_flags = get_proc_flags(args)?;
 
     #[cfg(debug_assertions)]
@@ -621,8 +752,27 @@ pub fn execute(args: &mut Cli) -> ThagResult<()> {
         return clean_cache(what);
     }
 
+    if let Some(action) = args.cache {
+        return exec_cache::manage(action, args.older_than);
+    }
+
+    if args.new.is_some() {
+        return templates::execute(args);
+    }
+
+    if args.inline.is_some() {
+        return inline::execute(args);
+    }
+
     let is_repl = args.iter;
     validate_args(args, &proc_flags)?;
+    if args.check_all.is_some() || args.build_all.is_some() {
+        return batch::execute(args);
+    }
+    if proc_flags.contains(ProcFlags::OFFLINE) {
+        offline::set_offline(true);
+    }
+    events::set_enabled(args.message_format == MessageFormat::Json);
     let repl_source_path = if is_repl {
         let gen_iter_temp_dir_path = TMPDIR.join(ITER_SUBDIR);
         debug_log!("repl_temp_dir = std::env::temp_dir() = {gen_iter_temp_dir_path:?}");
@@ -665,7 +815,7 @@ As an alternative, consider using the `edit` + `run` functions of `--iter (-r)`.
 }
 
 #[inline]
-fn resolve_script_dir_path(
+pub(crate) fn resolve_script_dir_path(
     is_repl: bool,
     args: &Cli,
     repl_source_path: Option<&PathBuf>,
@@ -704,7 +854,7 @@ fn resolve_script_dir_path(
 
 #[inline]
 #[profiled]
-fn set_script_state(
+pub(crate) fn set_script_state(
     args: &Cli,
     script_dir_path: PathBuf,
     is_repl: bool,
@@ -765,12 +915,14 @@ fn process(
             let Some(rs_source) = args.expression.take() else {
                 return Err("Missing expression for --expr option".into());
             };
+            build_state.line_map = Some(LineMap::new(Path::new("<expr>"), &rs_source));
             rs_source
         } else if is_loop {
             // Consumes the filter argument
             let Some(filter) = args.filter.take() else {
                 return Err("Missing expression for --loop option".into());
             };
+            build_state.line_map = Some(LineMap::new(Path::new("<loop>"), &filter));
             build_loop(args, filter)
         } else {
             #[cfg(not(feature = "tui"))]
@@ -796,6 +948,10 @@ fn process(
         };
 
         vprtln!(V::V, "rs_source={rs_source}");
+        if build_state.line_map.is_none() {
+            let label = if is_edit { "<edit>" } else { "<stdin>" };
+            build_state.line_map = Some(LineMap::new(Path::new(label), &rs_source));
+        }
 
         let rs_manifest = extract(&rs_source, Instant::now())
             // .map_err(|_err| "Error parsing rs_source")
@@ -811,6 +967,12 @@ fn process(
 
         debug_log!("expr_ast={expr_ast:#?}");
         process_expr(&mut build_state, &rs_source, args, proc_flags, &start)
+    } else if proc_flags.contains(ProcFlags::WATCH) {
+        watch(args, proc_flags, script_state, build_state, start)
+    } else if let Some(ref dir) = args.export {
+        export::check_destination(Path::new(dir))?;
+        gen_build(args, proc_flags, &mut build_state)?;
+        export::export(&build_state, Path::new(dir))
     } else {
         gen_build_run(args, proc_flags, &mut build_state, &start)
     }
@@ -831,6 +993,10 @@ pub fn process_expr(
     write_source(&build_state.source_path, rs_source)?;
     let result = gen_build_run(args, proc_flags, build_state, start);
     vprtln!(V::V, "{result:?}");
+    // A script stopped by a resource limit must still be reported with its distinct exit code
+    if let Err(ThagError::LimitExceeded(_)) = result {
+        return result;
+    }
     Ok(())
 }
 
@@ -864,19 +1030,46 @@ fn debug_log_config() {
 ///
 /// # Panics
 /// Will panic if it fails to parse the shebang, if any.
-#[allow(clippy::too_many_lines)]
-#[allow(clippy::cognitive_complexity)]
 #[profiled]
 pub fn gen_build_run(
     args: &Cli,
     proc_flags: &ProcFlags,
     build_state: &mut BuildState,
     start: &Instant,
+) -> ThagResult<()> {
+    gen_build(args, proc_flags, build_state)?;
+    if proc_flags.contains(ProcFlags::RUN) {
+        run(proc_flags, &args.args, build_state)?;
+    }
+    let process = &format!(
+        "{PACKAGE_NAME} completed processing script {}",
+        paint_for_role(Role::EMPH, &build_state.source_name)
+    );
+    display_timings(start, process, proc_flags);
+    Ok(())
+}
+
+/// Generate and build the script or expression, skipping either step if it is up to date.
+/// # Errors
+///
+/// Will return `Err` if there is an error returned by either the generate or the build function.
+///
+/// # Panics
+/// Will panic if it fails to parse the shebang, if any.
+#[allow(clippy::too_many_lines)]
+#[allow(clippy::cognitive_complexity)]
+#[profiled]
+pub fn gen_build(
+    args: &Cli,
+    proc_flags: &ProcFlags,
+    build_state: &mut BuildState,
 ) -> ThagResult<()> {
     if build_state.must_gen {
         let source_path: &Path = &build_state.source_path;
         let start_parsing_rs = Instant::now();
         let mut rs_source = read_file_contents(source_path)?;
+        // A dynamic script's source file may be overwritten by its generated source
+        let script_source = rs_source.clone();
 
         // Strip off any shebang: it may have got us here but we don't want or need it
         // in the gen_build_run process.
@@ -893,6 +1086,20 @@ pub fn gen_build_run(
             rs_source
         };
 
+        if build_state.rs_manifest.is_none() {
+            let rs_manifest: Manifest = { extract(&rs_source, start_parsing_rs) }?;
+            // debug_log!("rs_manifest={rs_manifest:#?}");
+
+            build_state.rs_manifest = Some(rs_manifest);
+        }
+
+        // The manifest has been extracted, so replace any Cargo script frontmatter with blank
+        // lines, which the stable compiler can't handle but which keep the line numbers intact.
+        let has_frontmatter = extract_frontmatter(&rs_source).is_some();
+        if has_frontmatter {
+            rs_source = strip_frontmatter(&rs_source).into_owned();
+        }
+
         // let sourch_path_string = source_path.display().to_string();
         let sourch_path_string = source_path.to_string_lossy();
         // let mut rs_source = read_file_contents(&build_state.source_path)?;
@@ -900,8 +1107,16 @@ pub fn gen_build_run(
             build_state.ast = to_ast(&sourch_path_string, &rs_source);
         }
         if let Some(ref ast) = build_state.ast {
-            build_state.crates_finder = Some(ast::find_crates(ast));
-            build_state.metadata_finder = Some(ast::find_metadata(ast));
+            let mut crates_finder = ast::find_crates(ast);
+            let mut metadata_finder = ast::find_metadata(ast);
+            // Imports in the script's module files are dependencies of the script too.
+            modules::infer_module_crates(
+                &build_state.modules,
+                &mut crates_finder,
+                &mut metadata_finder,
+            );
+            build_state.crates_finder = Some(crates_finder);
+            build_state.metadata_finder = Some(metadata_finder);
         }
 
         let test_only = proc_flags.contains(ProcFlags::TEST_ONLY);
@@ -942,22 +1157,54 @@ pub fn gen_build_run(
         // Fun fact: Rust compiler will ignore shebangs:
         // https://neosmart.net/blog/self-compiling-rust-code/
         let is_file = build_state.ast.as_ref().is_some_and(Ast::is_file);
-        build_state.build_from_orig_source =
-            (test_only || has_main == Some(true)) && args.script.is_some() && is_file;
+        build_state.build_from_orig_source = (test_only || has_main == Some(true))
+            && args.script.is_some()
+            && is_file
+            && !has_frontmatter;
 
         debug_log!(
             "has_main={has_main:#?}; build_state.build_from_orig_source={}",
             build_state.build_from_orig_source
         );
 
-        debug_log!("rs_source={rs_source}");
-        if build_state.rs_manifest.is_none() {
-            let rs_manifest: Manifest = { extract(&rs_source, start_parsing_rs) }?;
-            // debug_log!("rs_manifest={rs_manifest:#?}");
+        // A snippet that uses `.await` is wrapped in an async main method, so it depends on the
+        // configured async runtime.
+        let async_runtime = (has_main == Some(false)
+            && build_state.ast.as_ref().is_some_and(ast::needs_async_main))
+        .then(|| {
+            maybe_config()
+                .map(|config| config.dependencies.async_runtime)
+                .unwrap_or_default()
+        });
+        if let (Some(runtime), Some(ref mut crates_finder)) =
+            (async_runtime, build_state.crates_finder.as_mut())
+        {
+            let crate_name = runtime.crate_name().to_string();
+            if !crates_finder.crates.contains(&crate_name) {
+                debug_log!("Snippet uses .await, so adding dependency on {crate_name}");
+                crates_finder.crates.push(crate_name);
+            }
+        }
 
-            build_state.rs_manifest = Some(rs_manifest);
+        // A snippet that returns a value to be printed as JSON, YAML or a table depends on the
+        // crate that serializes it.
+        let format_crate = args.format.crate_name().filter(|_| {
+            has_main == Some(false)
+                && matches!(build_state.ast, Some(Ast::Expr(ref expr)) if !is_unit_return_type(expr))
+        });
+        if let (Some(crate_name), Some(ref mut crates_finder)) =
+            (format_crate, build_state.crates_finder.as_mut())
+        {
+            if !crates_finder.crates.iter().any(|name| name == crate_name) {
+                debug_log!(
+                    "Snippet output is formatted as {:?}, so adding dependency on {crate_name}",
+                    args.format
+                );
+                crates_finder.crates.push(crate_name.to_string());
+            }
         }
 
+        debug_log!("rs_source={rs_source}");
         // debug_log!("syntax_tree={syntax_tree:#?}");
 
         if build_state.rs_manifest.is_some() {
@@ -965,6 +1212,14 @@ pub fn gen_build_run(
             manifest::process_thag_auto_dependencies(build_state)?;
             manifest::merge(build_state, &rs_source)?;
         }
+        // A table's columns follow the order of the struct fields rather than sorted field names
+        if let (Some(_), OutputFormat::Table, Some(ref mut cargo_manifest)) = (
+            format_crate,
+            args.format,
+            build_state.cargo_manifest.as_mut(),
+        ) {
+            manifest::enable_feature(cargo_manifest, "serde_json", "preserve_order");
+        }
 
         // println!("build_state={build_state:#?}");
         rs_source = if test_only || has_main == Some(true) {
@@ -992,6 +1247,23 @@ pub fn gen_build_run(
                 (String::new(), rs_source)
             };
 
+            // Escape identifiers such as `gen` that are reserved keywords in edition 2024
+            let is_2024 = build_state
+                .cargo_manifest
+                .as_ref()
+                .is_some_and(|manifest| manifest::edition(manifest) >= Edition::E2024);
+            if let (true, Some(ref mut ast)) = (is_2024, build_state.ast.as_mut()) {
+                let count = escape_keywords(ast, EDITION_2024_KEYWORDS);
+                debug_log!("Escaped {count} identifiers reserved in edition 2024");
+            }
+
+            // Promote any `mod foo;` declarations to the crate level along with the inner attributes
+            let mod_decls = build_state
+                .ast
+                .as_mut()
+                .map(remove_file_modules)
+                .unwrap_or_default();
+
             let rust_code = build_state.ast.as_ref().map_or(body, |syntax_tree_ref| {
                 let returns_unit = match syntax_tree_ref {
                     Ast::Expr(expr) => is_unit_return_type(expr),
@@ -1010,25 +1282,27 @@ pub fn gen_build_run(
                         args.unquote,
                         maybe_config()
                     );
-
-                    if proc_flags.contains(ProcFlags::UNQUOTE) {
-                        debug_log!("\nIn unquote leg\n");
-                        quote::quote!(
-                            println!("{}", format!("{:?}", #syntax_tree_ref).trim_matches('"'));
-                        )
-                        .to_string()
-                    } else {
-                        debug_log!("\nIn quote leg\n");
-                        quote::quote!(
-                            println!("{}", format!("{:?}", #syntax_tree_ref));
-                        )
-                        .to_string()
-                    }
+                    print_value(
+                        syntax_tree_ref,
+                        args.format,
+                        proc_flags.contains(ProcFlags::UNQUOTE),
+                    )
                 }
             });
 
+            // An expression may use the bindings of its prelude for its arguments and stdin
+            let rust_code = if proc_flags.contains(ProcFlags::EXPR) {
+                format!("{}{rust_code}", expr_prelude(&rust_code))
+            } else {
+                rust_code
+            };
+
             // display_timings(&start_quote, "Completed quote", proc_flags);
-            wrap_snippet(&inner_attribs, &rust_code)
+            let inner_attribs = format!("{inner_attribs}\n{mod_decls}");
+            match async_runtime {
+                Some(runtime) => wrap_async_snippet(&inner_attribs, &rust_code, runtime),
+                None => wrap_snippet(&inner_attribs, &rust_code),
+            }
         };
 
         let maybe_rs_source =
@@ -1038,18 +1312,65 @@ pub fn gen_build_run(
                 Some(rs_source.as_str())
             };
 
-        generate(build_state, maybe_rs_source, proc_flags)?;
+        // An expression, loop filter or stdin snippet is cached by the hash of its generated
+        // source and manifest, so that running the same one again needs no generation or build.
+        if build_state.is_dynamic && !proc_flags.contains(ProcFlags::NORUN) {
+            let hash = fingerprint::dynamic_key(build_state, &rs_source)?;
+            build_state.target_path = exec_cache::dynamic_target_path(&hash);
+            build_state.fingerprint = Some(hash);
+            if build_state.target_path.exists() && !proc_flags.contains(ProcFlags::FORCE) {
+                build_state.must_build = false;
+            }
+        }
+
+        if build_state.is_dynamic && !build_state.must_build {
+            debug_log!(
+                "Found dynamic script in cache as {:?}",
+                build_state.target_path
+            );
+        } else {
+            generate(build_state, maybe_rs_source, proc_flags)?;
+            build_state.generated = true;
+
+            // Compiler diagnostics must refer to the script rather than the generated source.
+            // A dynamic script's line map has already been started from the user's input.
+            if build_state.build_from_orig_source {
+                build_state.line_map = None;
+            } else {
+                let generated_path = build_state.target_dir_path.join(&build_state.source_name);
+                let line_map = build_state
+                    .line_map
+                    .get_or_insert_with(|| LineMap::new(&build_state.source_path, &script_source));
+                line_map.align(&generated_path, &read_file_contents(&generated_path)?);
+            }
+        }
     } else {
         svprtln!(
             Role::EMPH,
             V::N,
             "Skipping unnecessary generation step.  Use --force (-f) to override."
         );
+        events::emit(&Event::GenerationSkipped {
+            script: &build_state.source_name,
+        });
         // build_state.cargo_manifest = Some(default_manifest(build_state)?);
         build_state.cargo_manifest = None; // Don't need it in memory, build will find it on disk
     }
     if build_state.must_build {
         build(proc_flags, build_state)?;
+        build_state.built = true;
+    } else if args.export.is_some() {
+        debug_log!("Skipping cargo build step for --export");
+    } else if build_state.is_dynamic && !proc_flags.contains(ProcFlags::NORUN) {
+        svprtln!(
+            Role::INFO,
+            V::V,
+            "Running cached executable {}. Use --force (-f) to rebuild.",
+            build_state.target_path.display()
+        );
+        events::emit(&Event::BuildSkipped {
+            script: &build_state.source_name,
+        });
     } else {
         let build_qualifier =
             if proc_flags.contains(ProcFlags::NORUN) && !proc_flags.contains(ProcFlags::BUILD) {
@@ -1058,15 +1379,10 @@ pub fn gen_build_run(
                 "Skipping unnecessary cargo build step. Use --force (-f) to override."
             };
         svprtln!(Role::EMPH, V::N, "{build_qualifier}");
+        events::emit(&Event::BuildSkipped {
+            script: &build_state.source_name,
+        });
     }
-    if proc_flags.contains(ProcFlags::RUN) {
-        run(proc_flags, &args.args, build_state)?;
-    }
-    let process = &format!(
-        "{PACKAGE_NAME} completed processing script {}",
-        paint_for_role(Role::EMPH, &build_state.source_name)
-    );
-    display_timings(start, process, proc_flags);
     Ok(())
 }
 
@@ -1109,7 +1425,7 @@ pub fn generate(
             #[cfg(not(feature = "no_format_snippet"))]
             {
                 let syntax_tree = syn_parse_file(rs_source)?;
-                &prettyplease_unparse(&syntax_tree)
+                &prettyplease_unparse(&syntax_tree, rs_source)
             }
             #[cfg(feature = "no_format_snippet")]
             {
@@ -1118,7 +1434,7 @@ pub fn generate(
                     || proc_flags.contains(ProcFlags::TEST_ONLY)
                 {
                     let syntax_tree = syn_parse_file(rs_source)?;
-                    &prettyplease_unparse(&syntax_tree)
+                    &prettyplease_unparse(&syntax_tree, rs_source)
                 } else {
                     rs_source.expect("Logic error retrieving rs_source")
                 }
@@ -1126,19 +1442,30 @@ pub fn generate(
         };
 
         write_source(&target_rs_path, rs_source)?;
-    }
 
-    // Remove any existing Cargo.lock as this may raise spurious compatibility issues with new dependency versions.
-    let lock_path = &build_state.target_dir_path.join("Cargo.lock");
-    // eprintln!("Lock path {lock_path:?} exists? - {}", lock_path.exists());
-    if lock_path.exists() {
-        fs::remove_file(lock_path)?;
+        // The wrapped source must find the script's module files where the script would.
+        modules::mirror_modules(&build_state.modules, &build_state.target_dir_path)?;
     }
 
     let manifest = &build_state
         .cargo_manifest
         .as_ref()
         .ok_or("Could not unwrap BuildState.cargo_manifest")?;
+
+    if let (Some(script_lock_path), Some(locked_versions)) =
+        (&build_state.lock_path, &build_state.locked_versions)
+    {
+        // Build against the script's lockfile, provided the dependencies still agree with it.
+        lockfile::check(manifest, locked_versions, script_lock_path)?;
+        lockfile::restore(script_lock_path, build_state)?;
+    } else {
+        // Remove any existing Cargo.lock as this may raise spurious compatibility issues with new dependency versions.
+        let lock_path = &build_state.target_dir_path.join("Cargo.lock");
+        // eprintln!("Lock path {lock_path:?} exists? - {}", lock_path.exists());
+        if lock_path.exists() {
+            fs::remove_file(lock_path)?;
+        }
+    }
     let cargo_manifest_str: &str = &toml::to_string(manifest)?;
 
     debug_log!(
@@ -1146,15 +1473,27 @@ pub fn generate(
         thag_common::disentangle(cargo_manifest_str)
     );
 
-    // Create or truncate the Cargo.toml file and write the content
-    let mut toml_file = OpenOptions::new()
-        .write(true)
-        .create(true) // Creates the file if it doesn't exist
-        .truncate(true) // Ensures the file is emptied if it exists
-        .open(&build_state.cargo_toml_path)?;
-
-    toml_file.write_all(cargo_manifest_str.as_bytes())?;
+    // Leave an unchanged Cargo.toml alone, so that rewriting it doesn't make the cached
+    // executables of other build profiles look stale.
+    if fs::read_to_string(&build_state.cargo_toml_path)
+        .is_ok_and(|existing| existing == cargo_manifest_str)
+    {
+        debug_log!("Cargo.toml is unchanged");
+    } else {
+        // Create or truncate the Cargo.toml file and write the content
+        let mut toml_file = OpenOptions::new()
+            .write(true)
+            .create(true) // Creates the file if it doesn't exist
+            .truncate(true) // Ensures the file is emptied if it exists
+            .open(&build_state.cargo_toml_path)?;
+
+        toml_file.write_all(cargo_manifest_str.as_bytes())?;
+    }
     display_timings(&start_gen, "Completed generation", proc_flags);
+    events::emit(&Event::GenerationDone {
+        script: &build_state.source_name,
+        elapsed_ms: events::elapsed_ms(&start_gen),
+    });
 
     Ok(())
 }
@@ -1166,10 +1505,15 @@ fn syn_parse_file(rs_source: Option<&str>) -> ThagResult<syn::File> {
     Ok(syntax_tree)
 }
 
+/// Format the generated source, unless it contains syntax that `prettyplease` can't print,
+/// in which case it is left as is.
 #[inline]
 #[profiled]
-fn prettyplease_unparse(syntax_tree: &syn::File) -> String {
-    prettyplease::unparse(syntax_tree)
+fn prettyplease_unparse(syntax_tree: &syn::File, rs_source: Option<&str>) -> String {
+    match rs_source {
+        Some(rs_source) if contains_verbatim(syntax_tree) => rs_source.to_string(),
+        _ => prettyplease::unparse(syntax_tree),
+    }
 }
 
 /// Call Cargo to build, check or expand the prepared script.
@@ -1182,11 +1526,17 @@ pub fn build(proc_flags: &ProcFlags, build_state: &BuildState) -> ThagResult<()>
     let start_build = Instant::now();
     vprtln!(V::V, "BBBBBBBB In build");
 
-    if proc_flags.contains(ProcFlags::EXPAND) {
+    let result = if proc_flags.contains(ProcFlags::EXPAND) {
         handle_expand(proc_flags, build_state)
     } else {
         handle_build_or_check(proc_flags, build_state)
-    }?;
+    };
+    events::emit(&Event::BuildFinished {
+        script: &build_state.source_name,
+        success: result.is_ok(),
+        elapsed_ms: events::elapsed_ms(&start_build),
+    });
+    result?;
 
     display_timings(&start_build, "Completed build", proc_flags);
     Ok(())
@@ -1230,9 +1580,38 @@ fn build_command_args(
         args.push(features.clone());
     }
 
-    if proc_flags.contains(ProcFlags::EXECUTABLE) {
+    if offline::is_offline() {
+        args.push("--offline".to_string());
+    }
+
+    // Have Cargo report compiler messages as JSON for the event stream, or for mapping them
+    // back from the generated source to the script
+    if !proc_flags.contains(ProcFlags::EXPAND) && !proc_flags.contains(ProcFlags::CARGO) {
+        if events::is_enabled() {
+            args.push("--message-format=json".to_string());
+        } else if build_state.line_map.is_some() {
+            args.push(if io::stderr().is_terminal() {
+                "--message-format=json-diagnostic-rendered-ansi".to_string()
+            } else {
+                "--message-format=json".to_string()
+            });
+        }
+    }
+
+    // Don't let Cargo update a restored script lockfile
+    if build_state.locked_versions.is_some() && !proc_flags.contains(ProcFlags::EXPAND) {
+        args.push("--locked".to_string());
+    }
+
+    // --executable builds with the release profile unless another profile is specified
+    if let Some(ref build_profile) = build_state.profile {
+        args.push("--profile".to_string());
+        args.push(build_profile.clone());
+    } else if proc_flags.contains(ProcFlags::EXECUTABLE) {
         args.push("--release".to_string());
-    } else if proc_flags.contains(ProcFlags::EXPAND) {
+    }
+
+    if proc_flags.contains(ProcFlags::EXPAND) {
         args.extend_from_slice(&[
             "--bin".to_string(),
             build_state.source_stem.clone(),
@@ -1304,7 +1683,26 @@ fn handle_build_or_check(proc_flags: &ProcFlags, build_state: &BuildState) -> Th
 
     svprtln!(Role::INFO, V::VV, "cargo_command={cargo_command:#?}");
 
-    let status = cargo_command.spawn()?.wait()?;
+    let status = if (events::is_enabled() || build_state.line_map.is_some())
+        && !proc_flags.contains(ProcFlags::CARGO)
+    {
+        // Pass Cargo's JSON compiler messages through as events or display them, in terms of the script
+        let quieter = proc_flags.contains(ProcFlags::QUIETER);
+        cargo_command.stdout(std::process::Stdio::piped());
+        let mut child = cargo_command.spawn()?;
+        if let Some(stdout) = child.stdout.take() {
+            for line in io::BufReader::new(stdout).lines() {
+                let line = line?;
+                if !handle_cargo_message(&line, build_state.line_map.as_ref(), !quieter) {
+                    // Not Cargo's, e.g. the output of tests run by `cargo test`
+                    eprintln!("{line}");
+                }
+            }
+        }
+        child.wait()?
+    } else {
+        cargo_command.spawn()?.wait()?
+    };
 
     if !status.success() {
         let verbosity = get_verbosity();
@@ -1319,6 +1717,10 @@ fn handle_build_or_check(proc_flags: &ProcFlags, build_state: &BuildState) -> Th
         return Err(err_msg.into());
     }
 
+    if let Some(ref lock_path) = build_state.lock_path {
+        lockfile::save(lock_path, build_state)?;
+    }
+
     if proc_flags.contains(ProcFlags::EXECUTABLE) {
         deploy_executable(build_state)?;
     } else if !proc_flags.contains(ProcFlags::CHECK) && !proc_flags.contains(ProcFlags::CARGO) {
@@ -1326,10 +1728,45 @@ fn handle_build_or_check(proc_flags: &ProcFlags, build_state: &BuildState) -> Th
         // CARGO mode runs user-specified subcommands (e.g. expand, clippy, tree) that don't
         // necessarily produce a build artifact, so caching would fail.
         cache_executable(build_state)?;
+        fingerprint::save(build_state)?;
+        if build_state.is_dynamic {
+            exec_cache::enforce_dynamic_limits(&build_state.target_path)?;
+        } else {
+            exec_cache::enforce_max_size(&build_state.target_path)?;
+        }
     }
     Ok(())
 }
 
+/// Handle a line of Cargo's JSON output. A compiler message is mapped back from the generated
+/// source to the script, then emitted as an event if the event stream is on, or else displayed
+/// if `display` is true. Returns `false` if the line is not valid JSON.
+#[profiled]
+fn handle_cargo_message(line: &str, line_map: Option<&LineMap>, display: bool) -> bool {
+    let Some(line_map) = line_map else {
+        return events::emit_cargo_message(line);
+    };
+    let Ok(mut message) = serde_json::from_str::<serde_json::Value>(line) else {
+        return false;
+    };
+    if message.get("reason").and_then(serde_json::Value::as_str) != Some("compiler-message") {
+        return true;
+    }
+    if let Some(diagnostic) = message.get_mut("message") {
+        line_map.rewrite_diagnostic(diagnostic);
+    }
+    if events::is_enabled() {
+        events::emit(&Event::CompilerMessage { message });
+    } else if let Some(rendered) = message
+        .pointer("/message/rendered")
+        .and_then(serde_json::Value::as_str)
+        .filter(|_| display)
+    {
+        eprint!("{rendered}");
+    }
+    true
+}
+
 /// Copy the built executable from the shared target directory to the executable cache.
 ///
 /// # Errors
@@ -1337,10 +1774,13 @@ fn handle_build_or_check(proc_flags: &ProcFlags, build_state: &BuildState) -> Th
 #[profiled]
 fn cache_executable(build_state: &BuildState) -> ThagResult<()> {
     let shared_target_dir = TMPDIR.join(SHARED_TARGET_SUBDIR);
-    let cache_dir = TMPDIR.join(EXECUTABLE_CACHE_SUBDIR);
 
-    // Create cache directory if it doesn't exist
-    fs::create_dir_all(&cache_dir)?;
+    // Destination: executable cache, in a subdirectory for any non-default profile
+    let dest_exe = &build_state.target_path;
+    if let Some(cache_dir) = dest_exe.parent() {
+        // Create cache directory if it doesn't exist
+        fs::create_dir_all(cache_dir)?;
+    }
 
     // Determine executable name with platform-specific extension
     #[cfg(target_os = "windows")]
@@ -1348,15 +1788,14 @@ fn cache_executable(build_state: &BuildState) -> ThagResult<()> {
     #[cfg(not(target_os = "windows"))]
     let exe_name = build_state.source_stem.clone();
 
-    // Source: shared target debug directory
-    let source_exe = shared_target_dir.join("debug").join(&exe_name);
-
-    // Destination: executable cache
-    let dest_exe = cache_dir.join(&exe_name);
+    // Source: the shared target directory for the build profile
+    let source_exe = shared_target_dir
+        .join(profile_dir(build_state.profile.as_deref()))
+        .join(&exe_name);
 
     // Copy executable to cache
     if source_exe.exists() {
-        fs::copy(&source_exe, &dest_exe)?;
+        fs::copy(&source_exe, dest_exe)?;
         svprtln!(
             Role::INFO,
             V::VV,
@@ -1452,7 +1891,9 @@ fn deploy_executable(build_state: &BuildState) -> ThagResult<()> {
     };
 
     let shared_target_dir = TMPDIR.join(SHARED_TARGET_SUBDIR);
-    let release_path = shared_target_dir.join("release");
+    let release_path = shared_target_dir.join(profile_dir(
+        build_state.profile.as_deref().or(Some("release")),
+    ));
     let output_path = cargo_bin_path.join(&build_state.source_stem);
 
     #[cfg(not(target_os = "windows"))]
@@ -1497,46 +1938,88 @@ fn deploy_executable(build_state: &BuildState) -> ThagResult<()> {
 /// # Errors
 ///
 /// Will return `Err` if there is an error waiting for the spawned command
-/// that runs the user script.
+/// that runs the user script, or if the script exits unsuccessfully.
 #[profiled]
 pub fn run(proc_flags: &ProcFlags, args: &[String], build_state: &BuildState) -> ThagResult<()> {
+    let output = run_script(proc_flags, args, build_state, false)?;
+    if !output.status.success() {
+        return Err(ThagError::Command("Script execution was unsuccessful"));
+    }
+
+    Ok(())
+}
+
+/// Run the built program and return its exit status, together with its standard output and
+/// standard error if `capture_output` is true. Otherwise the program inherits the terminal and
+/// the returned output is empty.
+///
+/// Unlike `run`, an unsuccessful exit of the program is not treated as an error.
+/// # Errors
+///
+/// Will return `Err` if there is an error waiting for the spawned command
+/// that runs the user script, or if the script was stopped for exceeding a resource limit.
+#[profiled]
+pub fn run_script(
+    proc_flags: &ProcFlags,
+    args: &[String],
+    build_state: &BuildState,
+    capture_output: bool,
+) -> ThagResult<Output> {
     let start_run = Instant::now();
     #[cfg(debug_assertions)]
     debug_log!("RRRRRRRR In run");
 
     // #[cfg(debug_assertions)]
     // debug_log!("BuildState={build_state:#?}");
-    let target_path: &Path = build_state.target_path.as_ref();
-    // #[cfg(debug_assertions)]
-    // debug_log!("Absolute path of generated program: {absolute_path:?}");
-
-    let mut run_command = Command::new(format!("{}", target_path.display()));
+    let mut run_command = create_run_command(args, build_state);
 
-    run_command.args(args);
+    let limits = &build_state.limits;
+    let (output, usage) = if capture_output {
+        limits.run(&mut run_command, true)?
+    } else {
+        // Sandwich command between two lines of dashes in the terminal
+        let dash_line = "─".repeat(FLOWER_BOX_LEN);
+        svprtln!(Role::EMPH, V::Q, "{dash_line}");
 
-    // #[cfg(debug_assertions)]
-    debug_log!("Run command is {run_command:?}");
+        let result = limits.run(&mut run_command, false)?;
+        // svprtln!(Role::EMPH, V::N, "Exit status={exit_status:#?}");
 
-    // Sandwich command between two lines of dashes in the terminal
+        svprtln!(Role::EMPH, V::Q, "{dash_line}");
+        result
+    };
+    if let Err(e) = fingerprint::record_run(&build_state.target_path) {
+        debug_log!("Could not record run of {:?}: {e}", build_state.target_path);
+    }
+    events::emit(&Event::RunFinished {
+        script: &build_state.source_name,
+        exit_code: output.status.code(),
+        success: output.status.success(),
+        elapsed_ms: events::elapsed_ms(&start_run),
+    });
 
-    let dash_line = "─".repeat(FLOWER_BOX_LEN);
-    svprtln!(Role::EMPH, V::Q, "{dash_line}");
+    // #[cfg(debug_assertions)]
+    // debug_log!("Exit status={exit_status:#?}");
 
-    let exit_status = run_command.status()?;
-    // svprtln!(Role::EMPH, V::N, "Exit status={exit_status:#?}");
+    display_timings(&start_run, "Completed run", proc_flags);
+    limits.check(output.status, &usage)?;
 
-    svprtln!(Role::EMPH, V::Q, "{dash_line}");
+    Ok(output)
+}
 
+/// Prepare the command to run the built program from the executable cache.
+#[profiled]
+pub(crate) fn create_run_command(args: &[String], build_state: &BuildState) -> Command {
+    let target_path: &Path = build_state.target_path.as_ref();
     // #[cfg(debug_assertions)]
-    // debug_log!("Exit status={exit_status:#?}");
+    // debug_log!("Absolute path of generated program: {absolute_path:?}");
 
-    display_timings(&start_run, "Completed run", proc_flags);
+    let mut run_command = Command::new(format!("{}", target_path.display()));
 
-    if !exit_status.success() {
-        return Err(ThagError::Command("Script execution was unsuccessful"));
-    }
+    run_command.args(args);
 
-    Ok(())
+    // #[cfg(debug_assertions)]
+    debug_log!("Run command is {run_command:?}");
+    run_command
 }
 
 /// Display method timings when either the --verbose or --timings option is chosen.
@@ -1,11 +1,14 @@
 use crate::{
+    code_utils::csv_delimiter,
     config::{maybe_config, DependencyInference},
+    exec_cache::parse_age,
+    limits::{parse_duration, parse_size},
     ThagError, ThagResult, RS_SUFFIX,
 };
 use bitflags::bitflags;
-use clap::{ArgGroup /*, ColorChoice */, Parser};
-use std::{fmt, str};
-use thag_common::{set_global_verbosity, Verbosity, V};
+use clap::{ArgGroup /*, ColorChoice */, Parser, ValueEnum};
+use std::{fmt, str, time::Duration};
+use thag_common::{set_global_verbosity, set_messages_to_stderr, Verbosity, V};
 use thag_profiler::{end, profile, profiled};
 
 /// The `clap` command-line interface for the `thag_rs` script runner and ITER.
@@ -15,7 +18,7 @@ use thag_profiler::{end, profile, profiled};
 #[command(group(
             ArgGroup::new("commands")
                 .required(true)
-                .args(&["script", "expression", "iter", "filter", "stdin", "edit", "config", "clean"]),
+                .args(&["script", "expression", "iter", "filter", "stdin", "edit", "config", "clean", "cache", "check_all", "build_all", "new", "inline"]),
    ))]
 #[command(group(
             ArgGroup::new("verbosity")
@@ -25,7 +28,7 @@ use thag_profiler::{end, profile, profiled};
 #[command(group(
             ArgGroup::new("norun_options")
                 .required(false)
-                .args(&["generate", "build", "check", "executable", "expand", "cargo"]),
+                .args(&["generate", "build", "check", "executable", "expand", "cargo", "export"]),
         ))]
 // #[command(group(
 //             ArgGroup::new("dep_in")
@@ -38,8 +41,9 @@ pub struct Cli {
     /// Features to enable when building the script (comma separated)
     #[arg(long, help_heading = Some("Processing Options"))]
     pub features: Option<String>,
-    /// Any arguments for the script
-    #[arg(last = true, requires = "script")]
+    /// Any arguments for the script or --expr, or for --loop, the files to read in turn instead of
+    /// stdin. An --expr can use them as `args`, or one by one as `arg1`, `arg2` etc.
+    #[arg(last = true)]
     pub args: Vec<String>,
     /// Force the generation and build steps, even if the script is unchanged since a previous build. Required if there are updates to dependencies.
     #[arg(short, long, requires = "script", help_heading = Some("Processing Options"))]
@@ -47,7 +51,8 @@ pub struct Cli {
     // /// Don't run the script after generating and building
     // #[arg(short, long, conflicts_with_all(["edit", "expression", "filter", "iter", "stdin"]))]
     // pub norun: bool,
-    /// Evaluate a quoted Rust expression on the fly
+    /// Evaluate a quoted Rust expression on the fly. The expression can use the arguments after `--`
+    /// as `args` or `arg1`, `arg2` etc., and piped stdin as `input` or `lines()`
     #[arg(short, long = "expr", help_heading = Some("Dynamic Options (no script)"), conflicts_with_all(["generate", "build"]))]
     pub expression: Option<String>,
     /// Rapid iteration mode for Rust expressions, or for dynamic scripts using TUI or external editors.
@@ -75,6 +80,29 @@ pub struct Cli {
     //  clap issue 4707 may prevent `requires` from working, as I've experienced.
     #[arg(short = 'E', long, help_heading = Some("Filter Options"), requires = "filter", value_name = "POST-LOOP")]
     pub end: Option<String>,
+    /// Split each --loop record into `fields: Vec<&str>` on this separator, which may be a regex.
+    /// A single space splits on runs of whitespace, like awk. With --csv, the single-character
+    /// delimiter of the CSV input instead, e.g. ';' or '\t'.
+    #[arg(short = 'F', long, help_heading = Some("Filter Options"), requires = "filter", value_name = "SEP", conflicts_with = "jsonl")]
+    pub field_separator: Option<String>,
+    /// Read --loop input as CSV with a header row, giving each record as `row`, with its fields by
+    /// column name `row["col"]` or index `row[0]`, and the header row as `headers`.
+    #[arg(long, help_heading = Some("Filter Options"), requires = "filter", conflicts_with_all(["jsonl", "null_data"]))]
+    pub csv: bool,
+    /// Read --loop input as JSON lines, giving each record as `record: serde_json::Value`.
+    /// Blank lines are skipped.
+    #[arg(long, help_heading = Some("Filter Options"), requires = "filter", conflicts_with = "null_data")]
+    pub jsonl: bool,
+    /// Read --loop input as NUL-delimited records, e.g. from `find -print0`, giving each record
+    /// as `line`.
+    #[arg(short = '0', long = "null", help_heading = Some("Filter Options"), requires = "filter")]
+    pub null_data: bool,
+    /// Edit the --loop files given after `--` in place, replacing each file with the output of the
+    /// filter for it: what it prints, or the value it returns, for each record. Each file is only
+    /// replaced once the filter has finished with it. With a SUFFIX, e.g. -I.bak, a copy of the
+    /// original is kept as a backup with the suffix appended to its name.
+    #[arg(short = 'I', long, help_heading = Some("Filter Options"), requires = "filter", value_name = "SUFFIX", num_args = 0..=1, default_missing_value = "")]
+    pub in_place: Option<String>,
     /// Allow multiple main methods for the current script
     #[arg(short, long, help_heading = Some("Processing Options"))]
     pub multimain: bool,
@@ -84,6 +112,10 @@ pub struct Cli {
     /// Set verbose mode. Double up for debug mode with destination app.log.
     #[arg(short, long, help_heading = Some("Output Options"), action = clap::ArgAction::Count)]
     pub verbose: u8,
+    /// Format of the messages reporting what `thag` is doing: human (default) or json. json writes a
+    /// stream of JSON events to stdout, one per line, in place of the usual styled output.
+    #[arg(long, value_enum, value_name = "FMT", default_value_t, help_heading = Some("Output Options"))]
+    pub message_format: MessageFormat,
     /// Set normal verbosity. Only needed in the case of overriding a different configured value
     #[arg(short = 'N', long = "normal", help_heading = Some("Output Options"))]
     pub normal_verbosity: bool,
@@ -128,6 +160,11 @@ pub struct Cli {
         conflicts_with("multimain")
     )]
     pub unquote: Option<bool>,
+    /// Format of the value returned by an expression or snippet: debug (default), pretty (`{:#?}`),
+    /// display, json, yaml or table. json, yaml and table require the value to implement
+    /// `serde::Serialize`, and table also to be an iterator of structs or tuples.
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t, help_heading = Some("Output Options"), conflicts_with = "filter")]
+    pub format: OutputFormat,
     /// Edit the configuration file
     #[arg(short = 'C', long, help_heading = Some("Dynamic Options (no script)"), conflicts_with_all(["generate", "build", "executable"]))]
     pub config: bool,
@@ -143,7 +180,57 @@ pub struct Cli {
     /// wrapping or modifying the source code.
     #[arg(short = 'T', long, requires = "script", help_heading = Some("No-run Options"))]
     pub test_only: bool,
-    /// Clean cached build artifacts. Options: 'bins' (executables only), 'target' (shared build cache), 'all' (both). Default: 'all'
+    /// Just generate script and export it to the directory as a standalone Cargo project, with its
+    /// merged Cargo.toml, `src/main.rs`, any module files and a `.gitignore`. With --lock, the script's
+    /// lockfile is exported as Cargo.lock.
+    #[arg(long, value_name = "DIR", requires = "script", help_heading = Some("No-run Options"), conflicts_with = "watch")]
+    pub export: Option<String>,
+    /// Watch the script, its generated Cargo.toml, any `mod` files and the user config, and regenerate,
+    /// rebuild and rerun it whenever any of them changes. Combine with --check (-c), --test-only (-T)
+    /// or --cargo (-A) to keep re-checking instead of running.
+    #[arg(short = 'w', long, requires = "script", help_heading = Some("Processing Options"), conflicts_with_all(["executable", "expand"]))]
+    pub watch: bool,
+    /// Build with the dependency versions recorded in the script's `<stem>.thag.lock` sidecar lockfile,
+    /// or create the lockfile from the first successful build. Fails if the script's dependencies no
+    /// longer agree with the lockfile.
+    #[arg(long, help_heading = Some("Processing Options"))]
+    pub lock: bool,
+    /// Re-resolve the script's dependencies to their latest compatible versions and overwrite its
+    /// `<stem>.thag.lock` sidecar lockfile. Implies --lock.
+    #[arg(long, help_heading = Some("Processing Options"))]
+    pub update_lock: bool,
+    /// Look up inferred dependencies in the local Cargo registry index cache and any configured vendored
+    /// directory instead of the network, and pass --offline to Cargo. This happens automatically if the
+    /// crates.io index is unreachable.
+    #[arg(long, help_heading = Some("Processing Options"))]
+    pub offline: bool,
+    /// Build and run the script with Cargo's release profile. The executable is cached separately from
+    /// the debug build, so switching between the two doesn't force a rebuild.
+    #[arg(long, help_heading = Some("Processing Options"), conflicts_with = "profile")]
+    pub release: bool,
+    /// Build and run the script with the named Cargo profile, e.g. one declared under `[profile.<name>]`
+    /// in the script's toml block. The executable is cached separately for each profile.
+    #[arg(long, value_name = "NAME", help_heading = Some("Processing Options"))]
+    pub profile: Option<String>,
+    /// Rust edition to build the script with: 2015, 2018, 2021 or 2024. Overrides any edition in the
+    /// script's toml block and the configured default, which is 2021 unless specified.
+    #[arg(long, value_parser = ["2015", "2018", "2021", "2024"], help_heading = Some("Processing Options"))]
+    pub edition: Option<String>,
+    /// Stop the script if it is still running after this long, e.g. 30s, 5m or 1h. A number without
+    /// a unit is in seconds. Overrides `timeout_secs` in the `[run]` section of the configuration.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, help_heading = Some("Processing Options"))]
+    pub timeout: Option<Duration>,
+    /// Limit the virtual memory of the script, e.g. 512M or 2G. A number without a unit is in bytes.
+    /// Overrides `max_memory_mb` in the `[run]` section of the configuration. Unix only.
+    #[arg(long, value_name = "BYTES", value_parser = parse_size, help_heading = Some("Processing Options"))]
+    pub max_memory: Option<u64>,
+    /// Limit the CPU time of the script in seconds. Overrides `max_cpu_secs` in the `[run]` section
+    /// of the configuration. Unix only.
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..), help_heading = Some("Processing Options"))]
+    pub max_cpu: Option<u64>,
+    /// Clean cached build artifacts. Options: 'bins' (executables only, including those cached for
+    /// expressions and loop filters), 'target' (shared build cache),
+    /// 'lookups' (cached crate versions and features), 'all' (all of these). Default: 'all'
     #[arg(
         long,
         help_heading = Some("Maintenance Options"),
@@ -152,6 +239,115 @@ pub struct Cli {
         num_args = 0..=1,
     )]
     pub clean: Option<String>,
+    /// Manage the executable cache: 'list' shows the cached script executables, 'prune' removes
+    /// those not run within --older-than, or else the least recently run beyond the configured
+    /// maximum cache size
+    #[arg(
+        long,
+        value_enum,
+        value_name = "ACTION",
+        conflicts_with = "clean",
+        help_heading = Some("Maintenance Options")
+    )]
+    pub cache: Option<CacheAction>,
+    /// With `--cache prune`, the age beyond which unused executables are removed, e.g. 30d, 12h, 2w
+    #[arg(
+        long,
+        value_name = "AGE",
+        requires = "cache",
+        value_parser = parse_age,
+        help_heading = Some("Maintenance Options")
+    )]
+    pub older_than: Option<Duration>,
+    /// Just cargo check every script in the directory, in parallel, and report which ones pass or fail.
+    /// Like --check (-c), each script is skipped if unchanged from a previous build.
+    #[arg(long, value_name = "DIR", help_heading = Some("Batch Options"), conflicts_with = "build_all")]
+    pub check_all: Option<String>,
+    /// Just build every script in the directory, in parallel, and report which ones pass or fail.
+    /// Like --build (-b), each script is skipped if unchanged from a previous build.
+    #[arg(long, value_name = "DIR", help_heading = Some("Batch Options"))]
+    pub build_all: Option<String>,
+    /// With --check-all or --build-all, the maximum number of scripts to process at once.
+    /// Default: the available parallelism of the machine
+    #[arg(short = 'j', long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..), help_heading = Some("Batch Options"))]
+    pub jobs: Option<u16>,
+    /// With --check-all or --build-all, the format of the report: human (default), json or junit
+    #[arg(long, value_enum, value_name = "FMT", help_heading = Some("Batch Options"))]
+    pub report: Option<ReportFormat>,
+    /// Create a new script `<NAME>.rs` from a template, with a toml block, doc-comment header and any
+    /// boilerplate that the kind of script needs
+    #[arg(long, value_name = "NAME", help_heading = Some("New Script Options"))]
+    pub new: Option<String>,
+    /// With --new, the kind of template: plain (default), clap, tokio, ratatui, profiled, styled,
+    /// or a `<kind>.rs` template in the configured `templates_dir`
+    #[arg(long, value_name = "KIND", help_heading = Some("New Script Options"))]
+    pub template: Option<String>,
+    /// Inline the Cargo project in the directory into a single-file script `<package>.rs`, with its
+    /// manifest as a toml block and its module files as inline `mod` blocks. Reports anything that a
+    /// script can't represent, such as a build script, multiple binaries or workspace inheritance.
+    #[arg(long, value_name = "CRATE-DIR", help_heading = Some("New Script Options"))]
+    pub inline: Option<String>,
+}
+
+/// An action on the executable cache.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum CacheAction {
+    /// List the cached script executables
+    List,
+    /// Remove unused or least recently used script executables
+    Prune,
+}
+
+/// The format of the report of a `--check-all` or `--build-all` batch.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    /// A styled table for a human at a terminal
+    #[default]
+    Human,
+    /// A single JSON document on stdout
+    Json,
+    /// A `JUnit` XML test report on stdout, for CI systems
+    Junit,
+}
+
+/// The format of the messages reporting what `thag` is doing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum MessageFormat {
+    /// Styled output for a human at a terminal
+    #[default]
+    Human,
+    /// A stream of JSON events on stdout, one per line
+    Json,
+}
+
+/// The format in which the value returned by an expression or snippet is printed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// `Debug` formatting, as `{:?}`
+    #[default]
+    Debug,
+    /// Pretty-printed `Debug` formatting, as `{:#?}`
+    Pretty,
+    /// `Display` formatting, as `{}`
+    Display,
+    /// Pretty-printed JSON, by `serde_json`
+    Json,
+    /// YAML, by `serde_yaml_ok`
+    Yaml,
+    /// A table with a column for each field of a struct or tuple and a row for each item
+    Table,
+}
+
+impl OutputFormat {
+    /// The name of the crate that the snippet needs to print its value in this format, if any.
+    #[must_use]
+    pub const fn crate_name(self) -> Option<&'static str> {
+        match self {
+            Self::Debug | Self::Pretty | Self::Display => None,
+            Self::Json | Self::Table => Some("serde_json"),
+            Self::Yaml => Some("serde_yaml_ok"),
+        }
+    }
 }
 
 /// Getter for clap command-line arguments
@@ -163,14 +359,70 @@ pub fn get_args() -> Cli {
 
 /// Validates the command-line arguments
 /// # Errors
-/// Will return `Err` if there is a missing script name or missing .rs suffix.
+/// Will return `Err` if:
+/// - the script name is missing or does not end in `.rs`,
+/// - `--older-than` is given without `--cache`,
+/// - `--export` is given without a script,
+/// - `--template` is given without `--new`,
+/// - `--lock` or `--update-lock` is given without a script, `--check-all` or `--build-all`,
+/// - `--timeout`, `--max-memory` or `--max-cpu` is given with `--check-all` or `--build-all`,
+/// - `--jobs` or `--report` is given without `--check-all` or `--build-all`,
+/// - `--in-place` is given without files to edit,
+/// - arguments are given after `--` without a script, `--expr` or `--loop`, or
+/// - the `--csv` delimiter is not a single character.
 #[profiled]
 pub fn validate_args(args: &Cli, proc_flags: &ProcFlags) -> ThagResult<()> {
+    // Enforce `requires = "cache"` in case clap issue 4707 lets it through
+    if args.older_than.is_some() && args.cache.is_none() {
+        return Err("Option --older-than requires --cache prune".into());
+    }
+    if args.export.is_some() && args.script.is_none() {
+        return Err("Option --export requires a script".into());
+    }
+    if args.template.is_some() && args.new.is_none() {
+        return Err("Option --template requires --new".into());
+    }
+    let is_batch = args.check_all.is_some() || args.build_all.is_some();
+    if (args.lock || args.update_lock) && args.script.is_none() && !is_batch {
+        return Err(
+            "Options --lock and --update-lock require a script, --check-all or --build-all".into(),
+        );
+    }
+    if is_batch && (args.timeout.is_some() || args.max_memory.is_some() || args.max_cpu.is_some()) {
+        return Err("Options --timeout, --max-memory and --max-cpu don't apply to --check-all or --build-all, which don't run the scripts".into());
+    }
+    if !is_batch {
+        if args.jobs.is_some() {
+            return Err("Option --jobs (-j) requires --check-all or --build-all".into());
+        }
+        if args.report.is_some() {
+            return Err("Option --report requires --check-all or --build-all".into());
+        }
+    }
+    if args.in_place.is_some() && args.args.is_empty() {
+        return Err("Option --in-place (-I) requires the files to edit, after `--`".into());
+    }
+    if !args.args.is_empty()
+        && args.script.is_none()
+        && args.expression.is_none()
+        && args.filter.is_none()
+    {
+        return Err("Arguments after `--` require a script, --expr (-e) or --loop (-l)".into());
+    }
+    if let (true, Some(separator)) = (args.csv, args.field_separator.as_deref()) {
+        if csv_delimiter(separator).is_none() {
+            return Err(format!(
+                "The --csv delimiter given by --field-separator (-F) must be a single character, not {separator:?}"
+            )
+            .into());
+        }
+    }
     if let Some(ref script) = args.script {
         if !script.ends_with(RS_SUFFIX) && script != "t" && script != "tools" {
             return Err(format!("Script name {script} must end in {RS_SUFFIX}").into());
         }
-    } else if !proc_flags.contains(ProcFlags::EXPR)
+    } else if !is_batch
+        && !proc_flags.contains(ProcFlags::EXPR)
         && !proc_flags.contains(ProcFlags::ITER)
         && !proc_flags.contains(ProcFlags::STDIN)
         && !proc_flags.contains(ProcFlags::EDIT)
@@ -189,7 +441,12 @@ pub fn validate_args(args: &Cli, proc_flags: &ProcFlags) -> ThagResult<()> {
 /// Will return `Err` if the logger mutex cannot be locked.
 #[profiled]
 pub fn set_verbosity(args: &Cli) -> ThagResult<()> {
-    let verbosity = if args.verbose >= 2 {
+    let is_json = args.message_format == MessageFormat::Json;
+    // Keep stdout for the event stream
+    set_messages_to_stderr(is_json);
+    let verbosity = if is_json {
+        V::Quieter
+    } else if args.verbose >= 2 {
         Verbosity::Debug
     } else if args.verbose == 1 {
         Verbosity::Verbose
@@ -274,6 +531,14 @@ bitflags! {
         const FEATURES      = 67_108_864;
         /// Clean flag
         const CLEAN         = 134_217_728;
+        /// Watch flag
+        const WATCH         = 268_435_456;
+        /// Lock flag
+        const LOCK          = 536_870_912;
+        /// Update lock flag
+        const UPDATE_LOCK   = 1_073_741_824;
+        /// Offline flag
+        const OFFLINE       = 2_147_483_648;
     }
 }
 
@@ -319,7 +584,7 @@ pub fn get_proc_flags(args: &Cli) -> ThagResult<ProcFlags> {
     let proc_flags = {
         let mut proc_flags = ProcFlags::empty();
         // eprintln!("args={args:#?}");
-        proc_flags.set(ProcFlags::GENERATE, args.generate);
+        proc_flags.set(ProcFlags::GENERATE, args.generate || args.export.is_some());
         proc_flags.set(ProcFlags::BUILD, args.build);
         proc_flags.set(ProcFlags::CHECK, args.check);
         proc_flags.set(ProcFlags::FORCE, args.force);
@@ -342,7 +607,8 @@ pub fn get_proc_flags(args: &Cli) -> ThagResult<ProcFlags> {
                 | args.executable
                 | args.expand
                 | args.cargo
-                | args.test_only,
+                | args.test_only
+                | args.export.is_some(),
         );
         proc_flags.set(ProcFlags::NORMAL, args.normal_verbosity);
         proc_flags.set(ProcFlags::RUN, !proc_flags.contains(ProcFlags::NORUN));
@@ -362,6 +628,10 @@ pub fn get_proc_flags(args: &Cli) -> ThagResult<ProcFlags> {
             args.script.as_ref().is_some_and(|script| script == "tools"),
         );
         proc_flags.set(ProcFlags::CLEAN, args.clean.is_some());
+        proc_flags.set(ProcFlags::WATCH, args.watch);
+        proc_flags.set(ProcFlags::LOCK, args.lock || args.update_lock);
+        proc_flags.set(ProcFlags::UPDATE_LOCK, args.update_lock);
+        proc_flags.set(ProcFlags::OFFLINE, args.offline);
         end!(init_config_loop_assert);
 
         profile!(config_loop_assert, time);
@@ -373,7 +643,14 @@ pub fn get_proc_flags(args: &Cli) -> ThagResult<ProcFlags> {
         end!(config_loop_assert);
 
         profile!(loop_assert, time);
-        if !is_loop && (args.toml.is_some() || args.begin.is_some() || args.end.is_some()) {
+        let is_loop_mode = args.field_separator.is_some()
+            || args.csv
+            || args.jsonl
+            || args.null_data
+            || args.in_place.is_some();
+        if !is_loop
+            && (args.toml.is_some() || args.begin.is_some() || args.end.is_some() || is_loop_mode)
+        {
             if args.toml.is_some() {
                 eprintln!("Option --toml (-M) requires --loop (-l)");
             }
@@ -383,6 +660,11 @@ pub fn get_proc_flags(args: &Cli) -> ThagResult<ProcFlags> {
             if args.end.is_some() {
                 eprintln!("Option --end (-E) requires --loop (-l)");
             }
+            if is_loop_mode {
+                eprintln!(
+                    "Options --field-separator (-F), --csv, --jsonl, --null (-0) and --in-place (-I) require --loop (-l)"
+                );
+            }
             return Err("Missing --loop option".into());
         }
         end!(loop_assert);
@@ -5,18 +5,22 @@
 )]
 
 use crate::{Ast, ThagError, ThagResult, DYNAMIC_SUBDIR, TEMP_SCRIPT_NAME, TMPDIR};
+use proc_macro2::{Group, Ident, TokenStream, TokenTree};
 use regex::Regex;
 use std::{
+    borrow::Cow,
     fs::{self, remove_dir_all, remove_file, OpenOptions},
     io::{self, BufRead, Write},
+    ops::Range,
     option::Option,
     path::{Path, PathBuf},
     process::{Command, Output},
 };
 use syn::{
     self,
+    visit::{self, Visit},
     visit_mut::{self, VisitMut},
-    AttrStyle, Expr, ExprBlock,
+    AttrStyle, Expr, ExprBlock, ForeignItem, ImplItem, Item, Pat, Stmt, TraitItem, Type,
 };
 use thag_common::{debug_log, re, vprtln, V};
 use thag_profiler::profiled;
@@ -27,8 +31,8 @@ use crate::debug_timings;
 
 #[cfg(feature = "build")]
 use {
-    crate::{BuildState, Cli},
-    std::{any::Any, time::SystemTime},
+    crate::{cmd_args::OutputFormat, config::AsyncRuntime, maybe_config, BuildState, Cli},
+    std::{any::Any, collections::BTreeSet, fmt::Write as _},
 };
 
 #[cfg(target_os = "windows")]
@@ -73,6 +77,146 @@ pub fn remove_inner_attributes(expr: &mut syn::ExprBlock) -> bool {
     remove_inner_attributes.found
 }
 
+// To escape identifiers that are keywords in a later edition than the one they were written for.
+struct EscapeKeywords<'a> {
+    keywords: &'a [&'a str],
+    count: usize,
+}
+
+impl EscapeKeywords<'_> {
+    // Macro arguments are only tokens to `syn`, so escape them token by token.
+    fn escape_tokens(&mut self, tokens: TokenStream) -> TokenStream {
+        tokens
+            .into_iter()
+            .map(|token| match token {
+                TokenTree::Ident(mut ident) => {
+                    self.visit_ident_mut(&mut ident);
+                    TokenTree::Ident(ident)
+                }
+                TokenTree::Group(group) => {
+                    let mut escaped =
+                        Group::new(group.delimiter(), self.escape_tokens(group.stream()));
+                    escaped.set_span(group.span());
+                    TokenTree::Group(escaped)
+                }
+                other => other,
+            })
+            .collect()
+    }
+}
+
+impl VisitMut for EscapeKeywords<'_> {
+    fn visit_ident_mut(&mut self, ident: &mut Ident) {
+        if self.keywords.iter().any(|keyword| ident == keyword) {
+            *ident = Ident::new_raw(&ident.to_string(), ident.span());
+            self.count += 1;
+        }
+    }
+
+    fn visit_macro_mut(&mut self, mac: &mut syn::Macro) {
+        mac.tokens = self.escape_tokens(std::mem::take(&mut mac.tokens));
+        visit_mut::visit_macro_mut(self, mac);
+    }
+}
+
+/// Keywords reserved by edition 2024 that are commonly used as identifiers, e.g. `rng.gen()`.
+pub const EDITION_2024_KEYWORDS: &[&str] = &["gen"];
+
+/// Escape any identifiers in the AST of a snippet that clash with the given keywords as raw
+/// identifiers (e.g. `r#gen`), so that the snippet still compiles in a later edition.
+/// Returns the number of identifiers escaped.
+#[profiled]
+pub fn escape_keywords(syntax_tree: &mut Ast, keywords: &[&str]) -> usize {
+    let escape_keywords = &mut EscapeKeywords { keywords, count: 0 };
+    match syntax_tree {
+        Ast::File(file) => escape_keywords.visit_file_mut(file),
+        Ast::Expr(expr) => escape_keywords.visit_expr_mut(expr),
+    }
+    escape_keywords.count
+}
+
+// To detect syntax that `syn` doesn't model and holds as verbatim tokens.
+struct VerbatimFinder {
+    found: bool,
+}
+
+impl<'ast> Visit<'ast> for VerbatimFinder {
+    fn visit_item(&mut self, item: &'ast Item) {
+        self.found |= matches!(item, Item::Verbatim(_));
+        visit::visit_item(self, item);
+    }
+
+    fn visit_foreign_item(&mut self, foreign_item: &'ast ForeignItem) {
+        self.found |= matches!(foreign_item, ForeignItem::Verbatim(_));
+        visit::visit_foreign_item(self, foreign_item);
+    }
+
+    fn visit_impl_item(&mut self, impl_item: &'ast ImplItem) {
+        self.found |= matches!(impl_item, ImplItem::Verbatim(_));
+        visit::visit_impl_item(self, impl_item);
+    }
+
+    fn visit_trait_item(&mut self, trait_item: &'ast TraitItem) {
+        self.found |= matches!(trait_item, TraitItem::Verbatim(_));
+        visit::visit_trait_item(self, trait_item);
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        self.found |= matches!(expr, Expr::Verbatim(_));
+        visit::visit_expr(self, expr);
+    }
+
+    fn visit_pat(&mut self, pat: &'ast Pat) {
+        self.found |= matches!(pat, Pat::Verbatim(_));
+        visit::visit_pat(self, pat);
+    }
+
+    fn visit_type(&mut self, ty: &'ast Type) {
+        self.found |= matches!(ty, Type::Verbatim(_));
+        visit::visit_type(self, ty);
+    }
+}
+
+/// Check whether a syntax tree contains any syntax that `syn` holds as verbatim tokens, such as
+/// the `safe` items of an edition 2024 `unsafe extern` block. `prettyplease` can't print these.
+#[must_use]
+#[profiled]
+pub fn contains_verbatim(syntax_tree: &syn::File) -> bool {
+    let verbatim_finder = &mut VerbatimFinder { found: false };
+    verbatim_finder.visit_file(syntax_tree);
+    verbatim_finder.found
+}
+
+/// Remove any file module declarations (`mod foo;`) from the AST of a snippet and return them
+/// as source code, as they need to be promoted to the crate level: the compiler does not
+/// allow them in the body of `fn main`.
+#[profiled]
+pub fn remove_file_modules(syntax_tree: &mut Ast) -> String {
+    let is_file_module =
+        |item: &Item| matches!(item, Item::Mod(item_mod) if item_mod.content.is_none());
+    let mut mod_decls = vec![];
+    match syntax_tree {
+        Ast::File(file) => file.items.retain(|item| {
+            if is_file_module(item) {
+                mod_decls.push(quote::quote!(#item).to_string());
+                return false;
+            }
+            true
+        }),
+        Ast::Expr(Expr::Block(expr_block)) => expr_block.block.stmts.retain(|stmt| {
+            if let Stmt::Item(item) = stmt {
+                if is_file_module(item) {
+                    mod_decls.push(quote::quote!(#item).to_string());
+                    return false;
+                }
+            }
+            true
+        }),
+        Ast::Expr(_) => {}
+    }
+    mod_decls.join("\n")
+}
+
 /// Read the contents of a file. For reading the Rust script.
 /// # Errors
 /// Will return `Err` if there is any file system error reading from the file path.
@@ -141,59 +285,73 @@ pub fn display_output(output: &Output) -> ThagResult<()> {
     Ok(())
 }
 
-/// Check if executable is stale, i.e. if raw source script or individual Cargo.toml
-/// has a more recent modification date and time
-/// # Errors
-/// Will return `Err` if either the executable or the Cargo.toml for the script is missing,
-/// or if there is a logic error wrapping the path and modified time.
-#[cfg(feature = "build")]
+/// Locate a Cargo script frontmatter block, which is a manifest between two fences of three or
+/// more dashes at the start of the script, optionally after a shebang line and blank lines:
+///
+/// ```text
+/// #!/usr/bin/env -S cargo +nightly -Zscript
+/// ---cargo
+/// [dependencies]
+/// regex = "1"
+/// ---
+/// ```
+///
+/// Returns the byte ranges of the whole block including its fences, and of the manifest inside it.
 #[profiled]
-pub fn modified_since_compiled(
-    build_state: &BuildState,
-) -> ThagResult<Option<(&PathBuf, SystemTime)>> {
-    let executable = &build_state.target_path;
-    executable.try_exists()?;
-    let Ok(metadata) = fs::metadata(executable) else {
-        return Ok(None);
-    };
-
-    let baseline_modified = metadata.modified()?;
-
-    let files = [&build_state.source_path, &build_state.cargo_toml_path];
-    let mut most_recent: Option<(&PathBuf, SystemTime)> = None;
-    for &file in &files {
-        let Ok(metadata) = fs::metadata(file) else {
-            continue;
-        };
-
-        let modified_time = metadata.modified()?;
-
-        debug_log!("File: {file:?} modified time is {modified_time:#?}");
-
-        if modified_time < baseline_modified {
-            continue;
-        }
+fn frontmatter_ranges(source: &str) -> Option<(Range<usize>, Range<usize>)> {
+    let mut lines = source.split_inclusive('\n').peekable();
+    let mut offset = 0;
+    if let Some(shebang) = lines.next_if(|line| line.starts_with("#!") && !line.starts_with("#!["))
+    {
+        offset += shebang.len();
+    }
+    while let Some(blank) = lines.next_if(|line| line.trim().is_empty()) {
+        offset += blank.len();
+    }
 
-        if most_recent.is_none()
-            || modified_time
-                > most_recent
-                    .ok_or("Logic error unwrapping what we wrapped ourselves")?
-                    .1
-        {
-            most_recent = Some((file, modified_time));
+    let opening = lines.next()?;
+    let dashes = opening.chars().take_while(|&c| c == '-').count();
+    let infostring = opening[dashes..].trim();
+    if dashes < 3
+        || !infostring
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ','))
+    {
+        return None;
+    }
+    let start = offset;
+    offset += opening.len();
+    let manifest_start = offset;
+    for line in lines {
+        if line.trim_end() == &opening[..dashes] {
+            return Some((start..offset + line.len(), manifest_start..offset));
         }
+        offset += line.len();
     }
-    if let Some(file) = most_recent {
-        vprtln!(
-            V::V,
-            "The most recently modified file compared to {executable:#?} is: {file:#?}"
-        );
+    // Unclosed, so leave it for the compiler to report
+    None
+}
 
-        debug_log!("Executable modified time is{baseline_modified:#?}");
-    } else {
-        debug_log!("Neither file was modified more recently than {executable:#?}");
-    }
-    Ok(most_recent)
+/// Extract the manifest from any Cargo script frontmatter block at the start of a script.
+#[must_use]
+#[profiled]
+pub fn extract_frontmatter(source: &str) -> Option<&str> {
+    frontmatter_ranges(source).map(|(_, manifest)| &source[manifest])
+}
+
+/// Replace any Cargo script frontmatter block at the start of a script with blank lines, so
+/// that the remaining source can be parsed and compiled as Rust with its line numbers intact.
+#[must_use]
+#[profiled]
+pub fn strip_frontmatter(source: &str) -> Cow<'_, str> {
+    frontmatter_ranges(source).map_or(Cow::Borrowed(source), |(block, _)| {
+        let blank_lines = "\n".repeat(source[block.clone()].matches('\n').count());
+        Cow::Owned(format!(
+            "{}{blank_lines}{}",
+            &source[..block.start],
+            &source[block.end..]
+        ))
+    })
 }
 
 /// Parse the code into an abstract syntax tree for inspection
@@ -203,6 +361,7 @@ pub fn modified_since_compiled(
 pub fn to_ast(sourch_path_string: &str, source_code: &str) -> Option<Ast> {
     #[cfg(debug_assertions)]
     let start_ast = Instant::now();
+    let source_code: &str = &strip_frontmatter(source_code);
     #[allow(clippy::option_if_let_else)]
     if let Ok(tree) = { syn::parse_file(source_code) } {
         #[cfg(debug_assertions)]
@@ -222,7 +381,7 @@ pub fn to_ast(sourch_path_string: &str, source_code: &str) -> Option<Ast> {
         svprtln!(Role::Warning, V::V,
             "Error parsing syntax tree for `{sourch_path_string}`. Using `rustfmt` to help you debug the script."
         );
-        rustfmt(sourch_path_string);
+        rustfmt(sourch_path_string, source_code);
 
         #[cfg(debug_assertions)]
         debug_timings(&start_ast, "Completed unsuccessful AST parse");
@@ -296,6 +455,380 @@ Ok(())
     wrapped_snippet
 }
 
+/// Convert a Rust code snippet that uses `.await` into a program by wrapping it in an async main
+/// method for the async runtime, and other scaffolding.
+#[must_use]
+#[cfg(feature = "build")]
+#[profiled]
+pub fn wrap_async_snippet(inner_attribs: &str, body: &str, runtime: AsyncRuntime) -> String {
+    debug_log!("In wrap_async_snippet: runtime={runtime}");
+    let main_method = async_main_method(
+        "Result<(), Box<dyn Error>>",
+        &format!("{body}\nOk(())"),
+        runtime,
+    );
+    format!(
+        r#"#![allow(unused_imports,unused_macros,unused_variables,dead_code)]
+{inner_attribs}
+use std::error::Error;
+use std::io;
+use std::io::prelude::*;
+
+#[doc = "Wrapped snippet in async main method to make it a program."]
+#[allow(clippy::unnecessary_wraps)]
+{main_method}
+"#
+    )
+}
+
+/// An async main method with this return type and body for the async runtime. `smol` has no
+/// attribute macro for its main method, so a synchronous main method drives an async one.
+#[cfg(feature = "build")]
+fn async_main_method(return_type: &str, body: &str, runtime: AsyncRuntime) -> String {
+    match runtime {
+        AsyncRuntime::Tokio => {
+            format!("#[tokio::main]\nasync fn main() -> {return_type} {{\n{body}\n}}")
+        }
+        AsyncRuntime::AsyncStd => {
+            format!("#[async_std::main]\nasync fn main() -> {return_type} {{\n{body}\n}}")
+        }
+        AsyncRuntime::Smol => format!(
+            "fn main() -> {return_type} {{\n    smol::block_on(async_main())\n}}\n\nasync fn async_main() -> {return_type} {{\n{body}\n}}"
+        ),
+    }
+}
+
+/// The function that prints a `--format table` value, serialized to a JSON value per item, as a
+/// table with a column for each field of a struct or tuple.
+#[cfg(feature = "build")]
+const PRINT_TABLE: &str = r#"
+fn print_table(rows: &[serde_json::Value]) {
+    use serde_json::Value;
+
+    fn cell(value: &Value) -> String {
+        match value {
+            Value::String(s) => s.clone(),
+            Value::Null => String::new(),
+            other => other.to_string(),
+        }
+    }
+
+    // Struct fields become named columns and tuple fields numbered ones
+    let mut headers: Vec<String> = vec![];
+    for row in rows {
+        match row {
+            Value::Object(fields) => {
+                for key in fields.keys() {
+                    if !headers.contains(key) {
+                        headers.push(key.clone());
+                    }
+                }
+            }
+            Value::Array(fields) => {
+                for i in headers.len()..fields.len() {
+                    headers.push(i.to_string());
+                }
+            }
+            _ if headers.is_empty() => headers.push("value".to_string()),
+            _ => {}
+        }
+    }
+    let table: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| match row {
+            Value::Object(fields) => headers
+                .iter()
+                .map(|key| fields.get(key).map(cell).unwrap_or_default())
+                .collect(),
+            Value::Array(fields) => (0..headers.len())
+                .map(|i| fields.get(i).map(cell).unwrap_or_default())
+                .collect(),
+            other => vec![cell(other)],
+        })
+        .collect();
+    let widths: Vec<usize> = (0..headers.len())
+        .map(|i| {
+            table
+                .iter()
+                .filter_map(|row| row.get(i))
+                .chain([&headers[i]])
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    let print_row = |row: &[String]| {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect();
+        println!("{}", cells.join("  ").trim_end());
+    };
+    print_row(&headers);
+    print_row(&widths.iter().map(|width| "-".repeat(*width)).collect::<Vec<_>>());
+    for row in &table {
+        print_row(row);
+    }
+}
+"#;
+
+/// The statements that print the value returned by a snippet in the `--format` requested.
+/// `unquote` strips the quotes from a `Debug`-formatted string.
+#[must_use]
+#[cfg(feature = "build")]
+#[profiled]
+pub fn print_value(syntax_tree: &Ast, format: OutputFormat, unquote: bool) -> String {
+    debug_log!("In print_value: format={format:?}, unquote={unquote}");
+    match format {
+        OutputFormat::Debug if unquote => quote::quote!(
+            println!("{}", format!("{:?}", #syntax_tree).trim_matches('"'));
+        )
+        .to_string(),
+        OutputFormat::Debug => quote::quote!(
+            println!("{}", format!("{:?}", #syntax_tree));
+        )
+        .to_string(),
+        OutputFormat::Pretty => quote::quote!(
+            println!("{:#?}", #syntax_tree);
+        )
+        .to_string(),
+        OutputFormat::Display => quote::quote!(
+            println!("{}", #syntax_tree);
+        )
+        .to_string(),
+        OutputFormat::Json => quote::quote!(
+            println!("{}", serde_json::to_string_pretty(&(#syntax_tree))?);
+        )
+        .to_string(),
+        OutputFormat::Yaml => quote::quote!(
+            print!("{}", serde_yaml_ok::to_string(&(#syntax_tree))?);
+        )
+        .to_string(),
+        OutputFormat::Table => {
+            let rows = quote::quote!(
+                let rows = (#syntax_tree)
+                    .into_iter()
+                    .map(serde_json::to_value)
+                    .collect::<Result<Vec<_>, _>>()?;
+                print_table(&rows);
+            );
+            format!("{PRINT_TABLE}\n{rows}")
+        }
+    }
+}
+
+/// The names used by an expression that may be bound by its prelude: those it doesn't bind itself
+/// in a pattern in scope where they are used.
+#[cfg(feature = "build")]
+#[derive(Debug, Default)]
+struct PreludeNames {
+    used: BTreeSet<String>,
+    /// The names bound by the expression in each enclosing scope, innermost last
+    scopes: Vec<BTreeSet<String>>,
+}
+
+#[cfg(feature = "build")]
+impl PreludeNames {
+    fn is_bound(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.contains(name))
+    }
+
+    fn use_name(&mut self, name: String) {
+        if !self.is_bound(&name) {
+            self.used.insert(name);
+        }
+    }
+
+    // Bind the names of a pattern in the innermost scope.
+    fn bind(&mut self, pat: &Pat) {
+        let mut bindings = PatBindings::default();
+        bindings.visit_pat(pat);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.extend(bindings.0);
+        }
+    }
+
+    fn in_scope(&mut self, f: impl FnOnce(&mut Self)) {
+        self.scopes.push(BTreeSet::new());
+        f(self);
+        self.scopes.pop();
+    }
+
+    // Walk the tokens of a macro invocation, noting unqualified identifiers and any inline format
+    // arguments in string literals.
+    fn scan(&mut self, tokens: TokenStream) {
+        let format_arg: &Regex = re!(r"\{(args|input|arg[1-9]\d*)[}:]");
+        let mut prev: Option<TokenTree> = None;
+        for token in tokens {
+            match token {
+                TokenTree::Group(ref group) => self.scan(group.stream()),
+                TokenTree::Ident(ref ident) => match prev {
+                    Some(TokenTree::Ident(ref prev)) if prev == "let" || prev == "mut" => {}
+                    Some(TokenTree::Punct(ref punct)) if matches!(punct.as_char(), '.' | ':') => {}
+                    _ => self.use_name(ident.to_string()),
+                },
+                TokenTree::Literal(ref literal) => {
+                    for captures in format_arg.captures_iter(&literal.to_string()) {
+                        self.use_name(captures[1].to_string());
+                    }
+                }
+                TokenTree::Punct(_) => {}
+            }
+            prev = Some(token);
+        }
+    }
+
+    fn binds(&self, name: &str) -> bool {
+        self.used.contains(name)
+    }
+}
+
+#[cfg(feature = "build")]
+impl<'ast> Visit<'ast> for PreludeNames {
+    fn visit_block(&mut self, block: &'ast syn::Block) {
+        self.in_scope(|names| visit::visit_block(names, block));
+    }
+
+    fn visit_local(&mut self, local: &'ast syn::Local) {
+        if let Some(ref init) = local.init {
+            self.visit_expr(&init.expr);
+            if let Some((_, ref diverge)) = init.diverge {
+                self.visit_expr(diverge);
+            }
+        }
+        self.bind(&local.pat);
+    }
+
+    fn visit_expr_closure(&mut self, closure: &'ast syn::ExprClosure) {
+        self.in_scope(|names| {
+            for input in &closure.inputs {
+                names.bind(input);
+            }
+            names.visit_expr(&closure.body);
+        });
+    }
+
+    fn visit_expr_for_loop(&mut self, for_loop: &'ast syn::ExprForLoop) {
+        self.visit_expr(&for_loop.expr);
+        self.in_scope(|names| {
+            names.bind(&for_loop.pat);
+            names.visit_block(&for_loop.body);
+        });
+    }
+
+    fn visit_expr_if(&mut self, expr_if: &'ast syn::ExprIf) {
+        // Any `if let` bindings are in scope in the `then` branch only
+        self.in_scope(|names| {
+            names.visit_expr(&expr_if.cond);
+            names.visit_block(&expr_if.then_branch);
+        });
+        if let Some((_, ref else_branch)) = expr_if.else_branch {
+            self.visit_expr(else_branch);
+        }
+    }
+
+    fn visit_expr_while(&mut self, expr_while: &'ast syn::ExprWhile) {
+        self.in_scope(|names| {
+            names.visit_expr(&expr_while.cond);
+            names.visit_block(&expr_while.body);
+        });
+    }
+
+    fn visit_expr_let(&mut self, expr_let: &'ast syn::ExprLet) {
+        self.visit_expr(&expr_let.expr);
+        self.bind(&expr_let.pat);
+    }
+
+    fn visit_arm(&mut self, arm: &'ast syn::Arm) {
+        self.in_scope(|names| {
+            names.bind(&arm.pat);
+            if let Some((_, ref guard)) = arm.guard {
+                names.visit_expr(guard);
+            }
+            names.visit_expr(&arm.body);
+        });
+    }
+
+    // Nested items such as functions can't see the prelude's bindings
+    fn visit_item(&mut self, _item: &'ast Item) {}
+
+    fn visit_expr_path(&mut self, expr_path: &'ast syn::ExprPath) {
+        if let (None, Some(ident)) = (&expr_path.qself, expr_path.path.get_ident()) {
+            self.use_name(ident.to_string());
+        }
+    }
+
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        self.scan(mac.tokens.clone());
+    }
+}
+
+/// The names bound by a pattern.
+#[cfg(feature = "build")]
+#[derive(Default)]
+struct PatBindings(BTreeSet<String>);
+
+#[cfg(feature = "build")]
+impl<'ast> Visit<'ast> for PatBindings {
+    fn visit_pat_ident(&mut self, pat_ident: &'ast syn::PatIdent) {
+        self.0.insert(pat_ident.ident.to_string());
+        visit::visit_pat_ident(self, pat_ident);
+    }
+}
+
+/// The prelude of an `--expr` program, binding the names it uses that it doesn't declare itself:
+///
+/// - `args`, the arguments after `--`, as a `Vec<String>`,
+/// - `arg1`, `arg2` etc., each of these arguments as a `&str`, or empty if not given,
+/// - `input`, all of stdin as a `String`, or empty if stdin is a terminal, and
+/// - `lines()`, an iterator over the lines of `input`.
+///
+/// Stdin is only read if the expression uses `input` or `lines()`.
+#[must_use]
+#[cfg(feature = "build")]
+#[profiled]
+pub fn expr_prelude(rust_code: &str) -> String {
+    let mut names = PreludeNames::default();
+    if let Ok(block) = syn::parse_str::<syn::Block>(&format!("{{\n{rust_code}\n}}")) {
+        names.visit_block(&block);
+    } else if let Ok(tokens) = rust_code.parse::<TokenStream>() {
+        names.scan(tokens);
+    } else {
+        return String::new();
+    }
+    debug_log!("In expr_prelude: names={names:?}");
+
+    let mut prelude = String::new();
+    let arg_numbers: BTreeSet<usize> = names
+        .used
+        .iter()
+        .filter(|name| names.binds(name))
+        .filter_map(|name| name.strip_prefix("arg")?.parse().ok())
+        .filter(|number| *number > 0)
+        .collect();
+    if names.binds("args") || !arg_numbers.is_empty() {
+        prelude.push_str("let args: Vec<String> = std::env::args().skip(1).collect();\n");
+    }
+    for number in arg_numbers {
+        let _ = writeln!(
+            prelude,
+            "let arg{number}: &str = args.get({}).map_or(\"\", String::as_str);",
+            number - 1
+        );
+    }
+    let binds_lines = names.binds("lines");
+    if names.binds("input") || binds_lines {
+        prelude.push_str(
+            "let input: String = if std::io::IsTerminal::is_terminal(&std::io::stdin()) { String::new() } else { std::io::read_to_string(std::io::stdin())? };\n",
+        );
+    }
+    if binds_lines {
+        prelude.push_str("let lines = || input.lines();\n");
+    }
+    prelude
+}
+
 /// Write the source to the destination source-code path.
 /// # Errors
 /// Will return `Err` if there is any error encountered opening or writing to the file.
@@ -342,6 +875,247 @@ pub fn create_temp_source_file() -> ThagResult<PathBuf> {
     Ok(path)
 }
 
+/// The type of a `--csv` loop record, giving access to its fields by column name or index.
+#[cfg(feature = "build")]
+const CSV_ROW: &str = r#"
+/// A CSV record, with its fields by column name `row["col"]` or index `row[0]`.
+#[derive(Debug)]
+struct Row<'a> {
+    headers: &'a csv::StringRecord,
+    record: csv::StringRecord,
+}
+
+#[allow(dead_code)]
+impl Row<'_> {
+    /// The field in the named column, if there is one.
+    fn get(&self, column: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .position(|header| header == column)
+            .and_then(|n| self.record.get(n))
+    }
+}
+
+impl std::ops::Index<&str> for Row<'_> {
+    type Output = str;
+
+    fn index(&self, column: &str) -> &str {
+        self.get(column)
+            .unwrap_or_else(|| panic!("No column named {column:?}"))
+    }
+}
+
+impl std::ops::Index<usize> for Row<'_> {
+    type Output = str;
+
+    fn index(&self, n: usize) -> &str {
+        &self.record[n]
+    }
+}
+"#;
+
+/// The single-byte delimiter of `--csv` input specified by `--field-separator`, which may be `\t`
+/// for a tab.
+#[must_use]
+pub fn csv_delimiter(separator: &str) -> Option<u8> {
+    match separator {
+        "\\t" => Some(b'\t'),
+        _ => match separator.as_bytes() {
+            [byte] => Some(*byte),
+            _ => None,
+        },
+    }
+}
+
+/// The type of the output of a `--loop` that edits files in place, which is written to a temporary
+/// file in the same directory that atomically replaces the file once the filter has finished with it.
+#[cfg(feature = "build")]
+const IN_PLACE: &str = r#"
+/// The output of editing a file in place, written to a temporary file that replaces the file when
+/// committed, or is removed if the filter fails first.
+struct InPlace {
+    path: std::path::PathBuf,
+    temp_path: std::path::PathBuf,
+    committed: bool,
+}
+
+impl InPlace {
+    fn new(filename: &str) -> io::Result<(Self, io::BufWriter<std::fs::File>)> {
+        let path = std::path::PathBuf::from(filename);
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let temp_path = path.with_file_name(format!(".{name}.thag-in-place"));
+        let temp_file = std::fs::File::create(&temp_path)?;
+        let in_place = Self {
+            path,
+            temp_path,
+            committed: false,
+        };
+        Ok((in_place, io::BufWriter::new(temp_file)))
+    }
+
+    /// Replace the file with the output, first copying it to a backup if the suffix is not empty.
+    fn commit(
+        mut self,
+        out: io::BufWriter<std::fs::File>,
+        backup_suffix: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let temp_file = out.into_inner().map_err(io::IntoInnerError::into_error)?;
+        temp_file.sync_all()?;
+        std::fs::set_permissions(&self.temp_path, std::fs::metadata(&self.path)?.permissions())?;
+        if !backup_suffix.is_empty() {
+            let mut backup = self.path.clone().into_os_string();
+            backup.push(backup_suffix);
+            std::fs::copy(&self.path, backup)?;
+        }
+        std::fs::rename(&self.temp_path, &self.path)?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for InPlace {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = std::fs::remove_file(&self.temp_path);
+        }
+    }
+}
+"#;
+
+/// The main loop of a `--loop` program: with file arguments, a loop over the files, each one named
+/// by `filename`, or else over stdin, named `-`. With `--in-place`, the filter's output for each file
+/// replaces it.
+///
+/// The files are passed to the program as its arguments rather than written into it, so that the
+/// same program serves for any list of files.
+#[cfg(feature = "build")]
+fn loop_input(args: &Cli, filter: &str) -> String {
+    if args.args.is_empty() {
+        let records = loop_records(args, filter, "io::stdin().lock()");
+        return format!("    let filename = \"-\";\n{records}");
+    }
+
+    let files = "std::env::args().skip(1)";
+    let records = loop_records(args, filter, "io::BufReader::new(file)");
+    let open = r#"let file = std::fs::File::open(filename)
+            .map_err(|e| format!("Can't open {filename}: {e}"))?;"#;
+    match args.in_place {
+        None => format!(
+            r"    // Execute main loop for each file in turn
+    for filename in {files} {{
+        let filename = filename.as_str();
+        {open}
+{records}
+    }}"
+        ),
+        Some(ref backup_suffix) => format!(
+            r"    // Execute main loop for each file in turn, replacing it with the output
+    for filename in {files} {{
+        let filename = filename.as_str();
+        {open}
+        let (in_place, mut out) = InPlace::new(filename)?;
+        #[allow(unused_macros)]
+        macro_rules! print {{
+            ($($arg:tt)*) => {{ write!(out, $($arg)*)? }};
+        }}
+        #[allow(unused_macros)]
+        macro_rules! println {{
+            ($($arg:tt)*) => {{ writeln!(out, $($arg)*)? }};
+        }}
+{records}
+        in_place.commit(out, {backup_suffix:?})?;
+    }}"
+        ),
+    }
+}
+
+/// The loop over the records read from `input` in the chosen mode, which executes the filter for
+/// each one, with the record number in `i`.
+#[cfg(feature = "build")]
+fn loop_records(args: &Cli, filter: &str, input: &str) -> String {
+    if args.csv {
+        let reader = args
+            .field_separator
+            .as_deref()
+            .and_then(csv_delimiter)
+            .map_or_else(
+                || format!("csv::Reader::from_reader({input})"),
+                |delimiter| {
+                    format!("csv::ReaderBuilder::new().delimiter({delimiter}).from_reader({input})")
+                },
+            );
+        return format!(
+            r"    // Read CSV records and execute main loop for each row
+    let mut i = 0;
+    let mut reader = {reader};
+    let headers = reader.headers()?.clone();
+    for record in reader.records() {{
+        let row = Row {{ headers: &headers, record: record? }};
+        i += 1;
+        {filter}
+    }}"
+        );
+    }
+    if args.jsonl {
+        return format!(
+            r#"    // Read JSON lines and execute main loop for each record
+    let mut i = 0;
+    for line in {input}.lines() {{
+        let line = line?;
+        i += 1;
+        if line.trim().is_empty() {{
+            continue;
+        }}
+        let record: serde_json::Value = serde_json::from_str(&line)
+            .map_err(|e| format!("Invalid JSON on line {{i}} of {{filename}}: {{e}}"))?;
+        {filter}
+    }}"#
+        );
+    }
+
+    let (split_setup, fields) = match args.field_separator.as_deref() {
+        None => (String::new(), String::new()),
+        Some(" ") => (
+            String::new(),
+            "let fields: Vec<&str> = line.split_whitespace().collect();".to_string(),
+        ),
+        Some(separator) if regex::escape(separator) == separator => (
+            String::new(),
+            format!("let fields: Vec<&str> = line.split({separator:?}).collect();"),
+        ),
+        Some(separator) => (
+            format!("let field_separator = regex::Regex::new({separator:?})?;\n    "),
+            "let fields: Vec<&str> = field_separator.split(&line).collect();".to_string(),
+        ),
+    };
+    if args.null_data {
+        format!(
+            r"    // Read NUL-delimited records and execute main loop for each one
+    let mut i = 0;
+    {split_setup}for record in {input}.split(b'\0') {{
+        let line = String::from_utf8(record?)?;
+        i += 1;
+        {fields}
+        {filter}
+    }}"
+        )
+    } else {
+        format!(
+            r"    // Read lines and execute main loop for each one
+    let mut i = 0;
+    {split_setup}for line in {input}.lines() {{
+        let line = line?;
+        i += 1;
+        {fields}
+        {filter}
+    }}"
+        )
+    }
+}
+
 /// Combine the elements of a loop filter into a well-formed program.
 #[must_use]
 #[cfg(feature = "build")]
@@ -363,29 +1137,57 @@ pub fn build_loop(args: &Cli, filter: String) -> String {
     #[allow(clippy::literal_string_with_formatting_args)]
     let filter = if returns_unit {
         filter
+    } else if args.in_place.is_some() {
+        // The value replaces the record in the file
+        format!(r#"writeln!(out, "{{}}", {filter})?;"#)
     } else {
         format!(r#"let _ = writeln!(io::stdout(), "{{:?}}", {filter});"#)
     };
     // dbg!(&filter);
 
+    let loop_begin = loop_begin.as_ref().map_or("", |prelude| {
+        vprtln!(V::V, "prelude={prelude}");
+        prelude
+    });
+    let loop_end = loop_end.as_ref().map_or("", |postlude| {
+        vprtln!(V::V, "postlude={postlude}");
+        postlude
+    });
+    let main_loop = loop_input(args, &filter);
+    let body = format!(
+        r"    {loop_begin}
+{main_loop}
+    {loop_end}
+    Ok(())"
+    );
+
+    // A loop that uses `.await` needs an async main method
+    let return_type = "Result<(), Box<dyn std::error::Error>>";
+    let is_async = extract_ast_expr(&format!("{{{loop_begin}\n{filter}\n{loop_end}}}"))
+        .is_ok_and(|expr| crate::ast::needs_async_main(&Ast::Expr(expr)));
+    let main_method = if is_async {
+        let runtime = maybe_config()
+            .map(|config| config.dependencies.async_runtime)
+            .unwrap_or_default();
+        debug_log!("Loop uses .await, so wrapping it in an async main for {runtime}");
+        async_main_method(return_type, &body, runtime)
+    } else {
+        format!("fn main() -> {return_type} {{\n{body}\n}}")
+    };
+
+    let csv_row = if args.csv { CSV_ROW } else { "" };
+    let in_place = if args.in_place.is_some() {
+        IN_PLACE
+    } else {
+        ""
+    };
     format!(
         r"{}
 #[allow(unused_imports)]
 use std::io::{{self, BufRead, Write as _}};
+{csv_row}{in_place}
 #[allow(unused_assignments, unused_variables)]
-fn main() -> Result<(), Box<dyn std::error::Error>> {{
-    {}
-    // Read from stdin and execute main loop for each line
-    let mut i = 0;
-    let stdin = io::stdin();
-    for line in stdin.lock().lines() {{
-        let line = line?;
-        i += 1;
-        {filter}
-    }}
-    {}
-    Ok(())
-}}
+{main_method}
 ",
         loop_toml.as_ref().map_or_else(String::new, |toml| {
             vprtln!(V::V, "toml={toml}");
@@ -394,14 +1196,6 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {{
 {toml}
 */"
             )
-        }),
-        loop_begin.as_ref().map_or("", |prelude| {
-            vprtln!(V::V, "prelude={prelude}");
-            prelude
-        }),
-        loop_end.as_ref().map_or("", |postlude| {
-            vprtln!(V::V, "postlude={postlude}");
-            postlude
         })
     )
 }
@@ -483,12 +1277,16 @@ pub fn get_source_path(build_state: &BuildState) -> String {
 /// # Panics
 /// Will panic if the `rustfmt` failed.
 #[profiled]
-fn rustfmt(source_path_str: &str) {
+fn rustfmt(source_path_str: &str, source_code: &str) {
     if Command::new("rustfmt").arg("--version").output().is_ok() {
-        // Run rustfmt on the source file
+        // Run rustfmt on the source file with the edition specified by any toml block
+        let edition_regex: &Regex = re!(r#"(?m)^\s*edition\s*=\s*"(\d{4})""#);
+        let edition = edition_regex
+            .captures(source_code)
+            .map_or("2021", |caps| caps.get(1).map_or("2021", |m| m.as_str()));
         let mut command = Command::new("rustfmt");
         command.arg("--edition");
-        command.arg("2021");
+        command.arg(edition);
         command.arg(source_path_str);
         command
             .stdout(std::process::Stdio::inherit())
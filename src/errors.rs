@@ -63,6 +63,8 @@ pub enum ThagError {
     FromUtf8(FromUtf8Error), // For simple errors from a utf8 array
     /// I/O operation error
     Io(std::io::Error), // For I/O errors
+    /// A script was stopped for exceeding a resource limit
+    LimitExceeded(String), // For --timeout, --max-memory and --max-cpu
     /// Mutex guard lock error
     LockMutexGuard(&'static str), // For lock errors with MutexGuard
     /// Logic error in program flow
@@ -109,6 +111,20 @@ pub enum ThagError {
     VarError(std::env::VarError), // For std::env::var errors
 }
 
+/// Exit code of `thag` when a script is stopped for exceeding a resource limit, as for `timeout(1)`.
+pub const LIMIT_EXCEEDED_EXIT_CODE: i32 = 124;
+
+impl ThagError {
+    /// The exit code with which `thag` should exit for this error.
+    #[must_use]
+    pub const fn exit_code(&self) -> i32 {
+        match self {
+            Self::LimitExceeded(_) => LIMIT_EXCEEDED_EXIT_CODE,
+            _ => 1,
+        }
+    }
+}
+
 impl From<FromUtf8Error> for ThagError {
     fn from(err: FromUtf8Error) -> Self {
         Self::FromUtf8(err)
@@ -280,6 +296,7 @@ impl std::fmt::Display for ThagError {
             }
             Self::FromUtf8(e) => write!(f, "{e}"),
             Self::Io(e) => write!(f, "{e}"),
+            Self::LimitExceeded(e) => write!(f, "{e}"),
             Self::LockMutexGuard(e) => write!(f, "{e}"),
             Self::OsString(o) => writeln!(f, "<invalid UTF-8: {o:?}>"),
             Self::Parse => write!(f, "Error parsing source data"),
@@ -345,6 +362,7 @@ impl Error for ThagError {
             Self::FromStr(_) => None,
             Self::FromUtf8(e) => Some(e),
             Self::Io(e) => Some(e),
+            Self::LimitExceeded(_) => None,
             Self::LockMutexGuard(_) => None,
             Self::Logic(_) => None,
             Self::NoneOption(_) => None,
@@ -0,0 +1,133 @@
+//!
+//! Machine-readable event stream for `thag_rs`, invoked by the `--message-format json` option.
+//!
+//! Each event is written to stdout as a single line of JSON, with an `event` field naming the kind
+//! of event, so that editor integrations and other tools can follow what `thag` is doing without
+//! scraping its styled terminal output. While the event stream is on, only warnings and errors
+//! are displayed for a human, and they are written to stderr so that every line `thag` itself
+//! writes to stdout is an event.
+//!
+//! Compiler messages are Cargo's own JSON messages, passed through in the `message` field of a
+//! `compiler-message` event. Their spans are unchanged for a script built as it stands, but for a
+//! snippet or expression they are mapped back from the generated source to the script by the
+//! `line_map` module. The output of the script itself is not captured, so when a script is run
+//! its output may be interleaved with the events.
+//!
+use serde::Serialize;
+use std::{
+    io::{self, Write},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Instant,
+};
+use thag_common::debug_log;
+use thag_profiler::profiled;
+
+/// Whether events are to be emitted for the rest of this run.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// An event in the processing of a script.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event<'a> {
+    /// The generation step was skipped because the generated project is up to date
+    GenerationSkipped {
+        /// The script name
+        script: &'a str,
+    },
+    /// The Cargo project for the script was generated
+    GenerationDone {
+        /// The script name
+        script: &'a str,
+        /// How long generation took in milliseconds
+        elapsed_ms: u64,
+    },
+    /// A dependency was inferred from the script's code rather than declared in its toml block
+    DependencyInferred {
+        /// The name of the crate
+        name: &'a str,
+        /// The version requirement selected for the crate
+        version: &'a str,
+        /// The features selected for the crate
+        features: &'a [String],
+    },
+    /// A compiler message from Cargo's own JSON output
+    CompilerMessage {
+        /// The message as emitted by Cargo
+        message: serde_json::Value,
+    },
+    /// The build step was skipped because the cached executable is up to date
+    BuildSkipped {
+        /// The script name
+        script: &'a str,
+    },
+    /// The Cargo build (or other Cargo subcommand) finished
+    BuildFinished {
+        /// The script name
+        script: &'a str,
+        /// Whether the build succeeded
+        success: bool,
+        /// How long the build took in milliseconds
+        elapsed_ms: u64,
+    },
+    /// The script finished running
+    RunFinished {
+        /// The script name
+        script: &'a str,
+        /// The exit code of the script, if it was not terminated by a signal
+        exit_code: Option<i32>,
+        /// Whether the script exited successfully
+        success: bool,
+        /// How long the script ran in milliseconds
+        elapsed_ms: u64,
+    },
+}
+
+/// Turn the event stream on or off for the rest of this run.
+#[profiled]
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Return whether the event stream is on.
+#[must_use]
+#[profiled]
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Write an event to stdout as a line of JSON, if the event stream is on.
+#[profiled]
+pub fn emit(event: &Event) {
+    if !is_enabled() {
+        return;
+    }
+    match serde_json::to_string(event) {
+        Ok(json) => {
+            let mut stdout = io::stdout().lock();
+            // A consumer that has gone away is no reason to fail the build or run
+            let _ = writeln!(stdout, "{json}").and_then(|()| stdout.flush());
+        }
+        Err(e) => {
+            debug_log!("Could not serialize event {event:?}: {e}");
+        }
+    }
+}
+
+/// Pass a line of Cargo's JSON output through as an event if it is a compiler message.
+/// Returns `false` if the line is not valid JSON.
+#[profiled]
+pub fn emit_cargo_message(line: &str) -> bool {
+    let Ok(message) = serde_json::from_str::<serde_json::Value>(line) else {
+        return false;
+    };
+    if message.get("reason").and_then(serde_json::Value::as_str) == Some("compiler-message") {
+        emit(&Event::CompilerMessage { message });
+    }
+    true
+}
+
+/// The time elapsed since `start` in milliseconds, for event timings.
+#[must_use]
+pub fn elapsed_ms(start: &Instant) -> u64 {
+    u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX)
+}
@@ -0,0 +1,423 @@
+//!
+//! Management of the cache of built script executables, invoked by the `--cache` option and
+//! after each build.
+//!
+//! `--cache list` shows each cached executable with the script it was built from, its size,
+//! when it was last run and its build fingerprint. `--cache prune --older-than <AGE>` removes
+//! the executables that have not been run within that age, while `--cache prune` on its own
+//! applies the `max_cache_size_mb` limit configured in the `[misc]` section.
+//!
+//! With that limit configured, the least recently run executables are also evicted after each
+//! build that takes the cache over it. The shared Cargo target directory is not limited, and is
+//! still only cleaned by `--clean`.
+//!
+//! Executables built from expressions, loop filters and stdin snippets are kept apart in the
+//! dynamic cache, each named for a hash of its generated source and manifest, so that repeating
+//! the same one runs the cached executable without calling Cargo. The dynamic cache is limited to
+//! the `max_dynamic_cache_entries` configured in the `[misc]` section, or 50 by default, as well
+//! as to `max_cache_size_mb`, and is cleaned by `--clean bins`.
+//!
+use crate::cmd_args::CacheAction;
+use crate::fingerprint::{self, fingerprint_path, Fingerprint, FINGERPRINT_SUFFIX};
+use crate::{maybe_config, ThagResult, DYNAMIC_CACHE_SUBDIR, EXECUTABLE_CACHE_SUBDIR, TMPDIR};
+use chrono::{DateTime, Local};
+use std::cmp::Reverse;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use thag_common::{debug_log, vprtln, V};
+use thag_profiler::profiled;
+
+/// An executable in the cache.
+#[derive(Clone, Debug)]
+pub struct CachedExecutable {
+    /// The path of the executable
+    pub path: PathBuf,
+    /// The Cargo profile directory it was built in, if not the default `debug`
+    pub profile: Option<String>,
+    /// The size of the executable in bytes
+    pub size: u64,
+    /// When the executable was last run, or if later, when it was built
+    pub last_used: SystemTime,
+    /// The fingerprint recorded when it was built, if any
+    pub fingerprint: Option<Fingerprint>,
+}
+
+impl CachedExecutable {
+    /// The name of the executable, which is the script's file stem.
+    #[must_use]
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+    }
+}
+
+/// The directory of the executable cache.
+#[must_use]
+pub fn cache_dir() -> PathBuf {
+    TMPDIR.join(EXECUTABLE_CACHE_SUBDIR)
+}
+
+/// The default maximum number of executables in the dynamic cache.
+pub const DEFAULT_MAX_DYNAMIC_ENTRIES: usize = 50;
+
+/// The directory of the dynamic cache of executables built from expressions, loop filters and
+/// stdin snippets.
+#[must_use]
+pub fn dynamic_cache_dir() -> PathBuf {
+    TMPDIR.join(DYNAMIC_CACHE_SUBDIR)
+}
+
+/// The path in the dynamic cache of the executable whose generated source and manifest have
+/// this hash.
+#[must_use]
+pub fn dynamic_target_path(hash: &str) -> PathBuf {
+    #[cfg(target_os = "windows")]
+    return dynamic_cache_dir().join(format!("{hash}.exe"));
+    #[cfg(not(target_os = "windows"))]
+    dynamic_cache_dir().join(hash)
+}
+
+/// List the executables in the cache at `dir`, most recently used first.
+///
+/// Executables built with the default profile are at the top level, and those built with
+/// other profiles are in a subdirectory named for the profile.
+///
+/// # Errors
+///
+/// Will return `Err` if the cache directory or an executable's metadata cannot be read.
+#[profiled]
+pub fn list(dir: &Path) -> ThagResult<Vec<CachedExecutable>> {
+    let mut executables = vec![];
+    if !dir.exists() {
+        return Ok(executables);
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            let build_profile = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned());
+            for entry in fs::read_dir(&path)? {
+                if let Some(executable) = cached_executable(entry?.path(), build_profile.clone())? {
+                    executables.push(executable);
+                }
+            }
+        } else if let Some(executable) = cached_executable(path, None)? {
+            executables.push(executable);
+        }
+    }
+    executables.sort_by_key(|executable| Reverse(executable.last_used));
+    Ok(executables)
+}
+
+#[profiled]
+fn cached_executable(
+    path: PathBuf,
+    build_profile: Option<String>,
+) -> ThagResult<Option<CachedExecutable>> {
+    if !path.is_file() || path.to_string_lossy().ends_with(FINGERPRINT_SUFFIX) {
+        return Ok(None);
+    }
+    let metadata = fs::metadata(&path)?;
+    let fingerprint = fingerprint::read(&path);
+    let built = metadata.modified()?;
+    let last_run = fingerprint
+        .as_ref()
+        .and_then(|fingerprint| fingerprint.last_run)
+        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+    Ok(Some(CachedExecutable {
+        path,
+        profile: build_profile,
+        size: metadata.len(),
+        last_used: last_run.map_or(built, |last_run| last_run.max(built)),
+        fingerprint,
+    }))
+}
+
+/// Remove a cached executable and its fingerprint.
+///
+/// # Errors
+///
+/// Will return `Err` if the executable cannot be removed.
+#[profiled]
+pub fn remove(executable: &CachedExecutable) -> ThagResult<()> {
+    fs::remove_file(&executable.path)?;
+    let fingerprint_path = fingerprint_path(&executable.path);
+    if fingerprint_path.exists() {
+        fs::remove_file(fingerprint_path)?;
+    }
+    debug_log!("Removed cached executable {:?}", executable.path);
+    Ok(())
+}
+
+/// Remove the executables in the cache at `dir` that have not been used within `age`.
+/// Returns the executables removed.
+///
+/// # Errors
+///
+/// Will return `Err` if the cache cannot be listed or an executable cannot be removed.
+#[profiled]
+pub fn prune_older_than(dir: &Path, age: Duration) -> ThagResult<Vec<CachedExecutable>> {
+    let cutoff = SystemTime::now().checked_sub(age).unwrap_or(UNIX_EPOCH);
+    let mut removed = vec![];
+    for executable in list(dir)? {
+        if executable.last_used < cutoff {
+            remove(&executable)?;
+            removed.push(executable);
+        }
+    }
+    Ok(removed)
+}
+
+/// Evict the least recently used executables from the cache at `dir` until its total size is
+/// no more than `max_bytes`, sparing the executable at `keep` if specified.
+/// Returns the executables removed.
+///
+/// # Errors
+///
+/// Will return `Err` if the cache cannot be listed or an executable cannot be removed.
+#[profiled]
+pub fn evict_to_size(
+    dir: &Path,
+    max_bytes: u64,
+    keep: Option<&Path>,
+) -> ThagResult<Vec<CachedExecutable>> {
+    let executables = list(dir)?;
+    let mut total: u64 = executables.iter().map(|executable| executable.size).sum();
+    let mut removed = vec![];
+    // Least recently used first
+    for executable in executables.into_iter().rev() {
+        if total <= max_bytes {
+            break;
+        }
+        if keep == Some(executable.path.as_path()) {
+            continue;
+        }
+        remove(&executable)?;
+        total = total.saturating_sub(executable.size);
+        removed.push(executable);
+    }
+    Ok(removed)
+}
+
+/// Evict the least recently used executables from the cache at `dir` until it holds no more than
+/// `max_count` of them, sparing the executable at `keep` if specified.
+/// Returns the executables removed.
+///
+/// # Errors
+///
+/// Will return `Err` if the cache cannot be listed or an executable cannot be removed.
+#[profiled]
+pub fn evict_to_count(
+    dir: &Path,
+    max_count: usize,
+    keep: Option<&Path>,
+) -> ThagResult<Vec<CachedExecutable>> {
+    let executables = list(dir)?;
+    let mut count = executables.len();
+    let mut removed = vec![];
+    // Least recently used first
+    for executable in executables.into_iter().rev() {
+        if count <= max_count {
+            break;
+        }
+        if keep == Some(executable.path.as_path()) {
+            continue;
+        }
+        remove(&executable)?;
+        count -= 1;
+        removed.push(executable);
+    }
+    Ok(removed)
+}
+
+/// The configured maximum size of the executable cache in bytes, if any.
+#[must_use]
+pub fn max_cache_bytes() -> Option<u64> {
+    maybe_config()
+        .and_then(|config| config.misc.max_cache_size_mb)
+        .map(|mb| mb.saturating_mul(1024 * 1024))
+}
+
+/// After a build, evict the least recently used executables if the cache has outgrown its
+/// configured maximum size, sparing the executable just built at `target_path`.
+///
+/// # Errors
+///
+/// Will return `Err` if the cache cannot be listed or an executable cannot be removed.
+#[profiled]
+pub fn enforce_max_size(target_path: &Path) -> ThagResult<()> {
+    let Some(max_bytes) = max_cache_bytes() else {
+        return Ok(());
+    };
+    for executable in evict_to_size(&cache_dir(), max_bytes, Some(target_path))? {
+        vprtln!(
+            V::V,
+            "Evicted {} from the executable cache to keep it within {}",
+            executable.name(),
+            format_size(max_bytes)
+        );
+    }
+    Ok(())
+}
+
+/// The configured maximum number of executables in the dynamic cache, or the default.
+#[must_use]
+pub fn max_dynamic_entries() -> usize {
+    maybe_config()
+        .and_then(|config| config.misc.max_dynamic_cache_entries)
+        .unwrap_or(DEFAULT_MAX_DYNAMIC_ENTRIES)
+}
+
+/// After a dynamic build, evict the least recently used executables if the dynamic cache has
+/// outgrown its maximum number of entries or the configured maximum size, sparing the executable
+/// just built at `target_path`.
+///
+/// # Errors
+///
+/// Will return `Err` if the cache cannot be listed or an executable cannot be removed.
+#[profiled]
+pub fn enforce_dynamic_limits(target_path: &Path) -> ThagResult<()> {
+    let dir = dynamic_cache_dir();
+    let mut removed = evict_to_count(&dir, max_dynamic_entries(), Some(target_path))?;
+    if let Some(max_bytes) = max_cache_bytes() {
+        removed.extend(evict_to_size(&dir, max_bytes, Some(target_path))?);
+    }
+    for executable in removed {
+        debug_log!("Evicted {} from the dynamic cache", executable.name());
+    }
+    Ok(())
+}
+
+/// Parse an age such as `30d`, `12h`, `90m`, `45s` or `2w`. A number without a unit is in days.
+///
+/// # Errors
+///
+/// Will return `Err` if the age is not a whole number followed by an optional unit.
+pub fn parse_age(age: &str) -> Result<Duration, String> {
+    let age = age.trim();
+    let split = age.find(|c: char| !c.is_ascii_digit()).unwrap_or(age.len());
+    let (number, unit) = age.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("Invalid age '{age}': expected a number and unit, e.g. 30d"))?;
+    let secs_per_unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "" | "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("Invalid age unit '{unit}': use s, m, h, d or w")),
+    };
+    Ok(Duration::from_secs(number.saturating_mul(secs_per_unit)))
+}
+
+/// Format a size in bytes for display.
+#[must_use]
+#[allow(clippy::cast_precision_loss)]
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next_unit in &UNITS[1..] {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next_unit;
+    }
+    format!("{size:.1} {unit}")
+}
+
+/// Carry out a `--cache` action on the executable cache.
+///
+/// # Errors
+///
+/// Will return `Err` if the cache cannot be listed or an executable cannot be removed, or if
+/// `prune` is requested with neither `--older-than` nor a configured maximum cache size.
+#[profiled]
+pub fn manage(action: CacheAction, older_than: Option<Duration>) -> ThagResult<()> {
+    let dir = cache_dir();
+    match action {
+        CacheAction::List if older_than.is_some() => {
+            return Err("Option --older-than only applies to --cache prune".into());
+        }
+        CacheAction::List => display_list(&list(&dir)?),
+        CacheAction::Prune => {
+            let removed = if let Some(age) = older_than {
+                prune_older_than(&dir, age)?
+            } else if let Some(max_bytes) = max_cache_bytes() {
+                evict_to_size(&dir, max_bytes, None)?
+            } else {
+                return Err("Specify --older-than <AGE> for --cache prune, or configure max_cache_size_mb in the [misc] section".into());
+            };
+            for executable in &removed {
+                vprtln!(V::V, "Removed {}", executable.path.display());
+            }
+            let freed = removed.iter().map(|executable| executable.size).sum();
+            vprtln!(
+                V::N,
+                "✓ Removed {} cached executable(s), freeing {}",
+                removed.len(),
+                format_size(freed)
+            );
+        }
+    }
+    Ok(())
+}
+
+#[profiled]
+fn display_list(executables: &[CachedExecutable]) {
+    if executables.is_empty() {
+        vprtln!(V::N, "Executable cache {} is empty", cache_dir().display());
+        return;
+    }
+    let name_width = executables
+        .iter()
+        .map(|executable| executable.name().len())
+        .max()
+        .unwrap_or_default()
+        .max("NAME".len());
+    println!(
+        "{:<name_width$}  {:<10}  {:>9}  {:<16}  {:<12}  SOURCE",
+        "NAME", "PROFILE", "SIZE", "LAST RUN", "FINGERPRINT"
+    );
+    for executable in executables {
+        let last_used: DateTime<Local> = executable.last_used.into();
+        let (fingerprint, source) = executable.fingerprint.as_ref().map_or_else(
+            || ("-".to_string(), "-".to_string()),
+            |fingerprint| {
+                (
+                    fingerprint.inputs.chars().take(12).collect(),
+                    fingerprint
+                        .source_path
+                        .as_ref()
+                        .map_or_else(|| "-".to_string(), |path| path.display().to_string()),
+                )
+            },
+        );
+        println!(
+            "{:<name_width$}  {:<10}  {:>9}  {:<16}  {:<12}  {source}",
+            executable.name(),
+            executable.profile.as_deref().unwrap_or("debug"),
+            format_size(executable.size),
+            last_used.format("%Y-%m-%d %H:%M"),
+            fingerprint,
+        );
+    }
+    let total: u64 = executables.iter().map(|executable| executable.size).sum();
+    vprtln!(
+        V::N,
+        "{} cached executable(s), {} in total, in {}",
+        executables.len(),
+        format_size(total),
+        cache_dir().display()
+    );
+}
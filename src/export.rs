@@ -0,0 +1,119 @@
+//!
+//! Export of a script as a standalone Cargo project, invoked by the `--export` option.
+//!
+//! `thag --export <dir> script.rs` generates the script as for `--gen`, then writes the generated
+//! project to `<dir>` in the standard Cargo layout:
+//!
+//! - `Cargo.toml`: the merged manifest, with the inferred dependencies and features, and its binary
+//!   pointing to `src/main.rs`,
+//! - `src/main.rs`: the script, wrapped in a main method if it is a snippet,
+//! - any module files of a multi-file script, in the same places relative to `src/main.rs`,
+//! - a `.gitignore` for the `target` directory, and
+//! - with `--lock`, the script's sidecar lockfile as `Cargo.lock`.
+//!
+//! The exported project thus builds with `cargo build` exactly as the script did with `thag`.
+//!
+use crate::modules;
+use crate::{BuildState, ThagResult};
+use cargo_toml::Manifest;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use thag_common::{debug_log, vprtln, V};
+use thag_profiler::profiled;
+use thag_styling::{svprtln, Role};
+
+/// The path of the binary's source file in the exported project.
+pub const MAIN_RS: &str = "src/main.rs";
+
+/// The content of the exported project's `.gitignore`.
+const GITIGNORE: &str = "/target\n";
+
+/// Check that `dir` is a suitable destination for an export, i.e. it does not exist or is empty,
+/// so that an export never overwrites anything.
+///
+/// # Errors
+///
+/// Will return `Err` if `dir` is a file or a non-empty directory, or cannot be read.
+#[profiled]
+pub fn check_destination(dir: &Path) -> ThagResult<()> {
+    if dir.is_file() {
+        return Err(format!("Export destination `{}` is a file", dir.display()).into());
+    }
+    if dir.is_dir() && fs::read_dir(dir)?.next().is_some() {
+        return Err(format!(
+            "Export destination `{}` already exists and is not empty",
+            dir.display()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// The manifest of the exported project: the generated manifest with its binary pointing to
+/// `src/main.rs`.
+///
+/// # Errors
+///
+/// Will return `Err` if the generated manifest cannot be parsed or has no binary.
+#[profiled]
+pub fn export_manifest(generated: &str) -> ThagResult<String> {
+    let mut manifest = Manifest::from_str(generated)?;
+    let bin = manifest
+        .bin
+        .first_mut()
+        .ok_or("The generated manifest has no [[bin]] section")?;
+    bin.path = Some(MAIN_RS.to_string());
+    Ok(toml::to_string(&manifest)?)
+}
+
+/// Export the generated project of the script to `dir`. `generate` must already have run.
+///
+/// # Errors
+///
+/// Will return `Err` if `dir` is not empty, or if any of the project files cannot be read or
+/// written.
+#[profiled]
+pub fn export(build_state: &BuildState, dir: &Path) -> ThagResult<()> {
+    check_destination(dir)?;
+    let src_dir = dir.join("src");
+    fs::create_dir_all(&src_dir)?;
+
+    let generated = fs::read_to_string(&build_state.cargo_toml_path)?;
+    fs::write(dir.join("Cargo.toml"), export_manifest(&generated)?)?;
+
+    // A program is built from the script itself, a snippet from its wrapped version.
+    let main_source: PathBuf = if build_state.build_from_orig_source {
+        build_state.source_path.clone()
+    } else {
+        build_state.target_dir_path.join(&build_state.source_name)
+    };
+    debug_log!("Exporting {} as {MAIN_RS}", main_source.display());
+    fs::copy(&main_source, dir.join(MAIN_RS))?;
+    modules::mirror_modules(&build_state.modules, &src_dir)?;
+
+    fs::write(dir.join(".gitignore"), GITIGNORE)?;
+
+    if let Some(ref lock_path) = build_state.lock_path {
+        if lock_path.exists() {
+            fs::copy(lock_path, dir.join("Cargo.lock"))?;
+        } else {
+            svprtln!(
+                Role::WARN,
+                V::QQ,
+                "No lockfile {} to export. Run the script with --lock to create it.",
+                lock_path.display()
+            );
+        }
+    }
+
+    vprtln!(
+        V::N,
+        "✓ Exported {} to {}. Build it with `cargo build --manifest-path {}`",
+        build_state.source_name,
+        dir.display(),
+        dir.join("Cargo.toml").display()
+    );
+    Ok(())
+}
@@ -0,0 +1,287 @@
+//!
+//! Content fingerprints for cached executables, used to decide whether a script must be
+//! regenerated and rebuilt.
+//!
+//! When a script is built, a fingerprint is saved next to its cached executable as
+//! `<stem>.fingerprint`. It records a hash of everything that goes into the build other than
+//! the generated manifest: the normalized source of the script and its module files, any script
+//! lockfile, the `--features` list, the dependency inference level, the edition, the dependency
+//! and edition settings of the configuration and the `thag` version. It also records a hash of
+//! the merged manifest written to the generated `Cargo.toml`.
+//!
+//! On the next run the script is only skipped if both hashes still match, so a `git checkout`
+//! of an older version, a configuration change or a `thag` upgrade are all rebuilt, while a
+//! `touch` that leaves the content unchanged is not.
+//!
+//! An expression, loop filter or stdin snippet has no script file to fingerprint, so its cached
+//! executable is instead keyed on a hash of its final generated source and manifest, which is
+//! also recorded as the `inputs` of its fingerprint.
+//!
+//! The fingerprint file also records the path of the script and when the executable was last
+//! run, for listing and pruning the executable cache.
+//!
+use crate::{maybe_config, BuildState, ThagResult, VERSION};
+use serde::{Deserialize, Serialize};
+use siphasher::sip128::{Hasher128, SipHasher13};
+use std::{
+    collections::BTreeMap,
+    fs,
+    hash::Hasher,
+    mem,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use thag_common::{debug_log, vprtln, V};
+use thag_profiler::profiled;
+
+/// Suffix of the fingerprint file, which is appended to the path of the cached executable.
+pub const FINGERPRINT_SUFFIX: &str = ".fingerprint";
+
+/// The fingerprint recorded for a cached executable.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+pub struct Fingerprint {
+    /// Hash of the script's inputs other than the generated manifest
+    pub inputs: String,
+    /// Hash of the generated `Cargo.toml`
+    pub manifest: String,
+    /// The script the executable was built from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_path: Option<PathBuf>,
+    /// When the executable was last run, in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run: Option<u64>,
+}
+
+/// Return the path of the fingerprint file for the cached executable at `target_path`.
+#[must_use]
+#[profiled]
+pub fn fingerprint_path(target_path: &Path) -> PathBuf {
+    let mut path = target_path.as_os_str().to_owned();
+    path.push(FINGERPRINT_SUFFIX);
+    PathBuf::from(path)
+}
+
+/// Normalize source code so that differences in line endings and trailing whitespace
+/// don't count as changes.
+#[must_use]
+#[profiled]
+pub fn normalize_source(source: &str) -> String {
+    let mut normalized = source
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n");
+    normalized.truncate(normalized.trim_end().len());
+    normalized
+}
+
+/// A hasher that keeps the parts of a fingerprint from running into each other.
+struct FingerprintHasher(SipHasher13);
+
+impl FingerprintHasher {
+    fn new() -> Self {
+        Self(SipHasher13::new())
+    }
+
+    fn part(&mut self, label: &str, value: &str) -> &mut Self {
+        for item in [label, value] {
+            self.0.write_usize(item.len());
+            self.0.write(item.as_bytes());
+        }
+        self
+    }
+
+    fn finish(&self) -> String {
+        format!("{:032x}", self.0.finish128().as_u128())
+    }
+}
+
+/// Hash a string, such as the contents of a generated `Cargo.toml`.
+#[must_use]
+#[profiled]
+pub fn hash_str(content: &str) -> String {
+    FingerprintHasher::new().part("content", content).finish()
+}
+
+/// Compute the hash of the inputs of the script's build other than the generated manifest.
+///
+/// # Errors
+///
+/// Will return `Err` if the script source cannot be read or the configuration cannot be serialized.
+#[profiled]
+pub fn inputs(build_state: &BuildState) -> ThagResult<String> {
+    let mut hasher = FingerprintHasher::new();
+    hasher
+        .part("version", VERSION)
+        .part(
+            "source",
+            &normalize_source(&fs::read_to_string(&build_state.source_path)?),
+        )
+        .part(
+            "features",
+            build_state.features.as_deref().unwrap_or_default(),
+        )
+        .part("infer", &build_state.infer.to_string())
+        .part(
+            "edition",
+            &build_state
+                .edition
+                .map(|edition| edition.to_string())
+                .unwrap_or_default(),
+        );
+
+    for module in &build_state.modules {
+        // A missing module will fail the build, which won't leave a fingerprint to compare with
+        let source = fs::read_to_string(&module.path).unwrap_or_default();
+        hasher
+            .part("module", &module.path.to_string_lossy())
+            .part("module_source", &normalize_source(&source));
+    }
+
+    if let Some(ref lock_path) = build_state.lock_path {
+        hasher.part("lock", &fs::read_to_string(lock_path).unwrap_or_default());
+    }
+
+    if let Some(config) = maybe_config() {
+        // Sort the feature overrides so that their hash doesn't depend on `HashMap` order
+        let mut dependencies = config.dependencies.clone();
+        let feature_overrides: BTreeMap<_, _> = mem::take(&mut dependencies.feature_overrides)
+            .into_iter()
+            .collect();
+        hasher
+            .part("dependencies", &toml::to_string(&dependencies)?)
+            .part("feature_overrides", &toml::to_string(&feature_overrides)?)
+            .part(
+                "edition_default",
+                config.misc.edition.as_deref().unwrap_or_default(),
+            );
+    }
+
+    Ok(hasher.finish())
+}
+
+/// Compute the hash that keys the cached executable of an expression, loop filter or stdin
+/// snippet: that of its generated source and manifest, together with anything else that changes
+/// the build.
+///
+/// # Errors
+///
+/// Will return `Err` if the manifest cannot be serialized.
+#[profiled]
+pub fn dynamic_key(build_state: &BuildState, rs_source: &str) -> ThagResult<String> {
+    let manifest = build_state
+        .cargo_manifest
+        .as_ref()
+        .map(toml::to_string)
+        .transpose()?
+        .unwrap_or_default();
+    let mut hasher = FingerprintHasher::new();
+    hasher
+        .part("version", VERSION)
+        .part("source", &normalize_source(rs_source))
+        .part("manifest", &manifest)
+        .part(
+            "features",
+            build_state.features.as_deref().unwrap_or_default(),
+        )
+        .part(
+            "profile",
+            build_state.profile.as_deref().unwrap_or_default(),
+        );
+    if let Some(ref lock_path) = build_state.lock_path {
+        hasher.part("lock", &fs::read_to_string(lock_path).unwrap_or_default());
+    }
+    Ok(hasher.finish())
+}
+
+/// Read the fingerprint recorded for the cached executable at `target_path`, if any.
+#[must_use]
+#[profiled]
+pub fn read(target_path: &Path) -> Option<Fingerprint> {
+    let content = fs::read_to_string(fingerprint_path(target_path)).ok()?;
+    match toml::from_str(&content) {
+        Ok(fingerprint) => Some(fingerprint),
+        Err(e) => {
+            debug_log!("Ignoring unreadable fingerprint for {target_path:?}: {e}");
+            None
+        }
+    }
+}
+
+/// Return whether the cached executable must be regenerated and rebuilt because its recorded
+/// fingerprint is missing or no longer matches the script's inputs or generated manifest.
+#[must_use]
+#[profiled]
+pub fn is_stale(build_state: &BuildState) -> bool {
+    let Some(ref inputs) = build_state.fingerprint else {
+        return true;
+    };
+    let Some(recorded) = read(&build_state.target_path) else {
+        vprtln!(
+            V::V,
+            "No fingerprint found for {:?}",
+            build_state.target_path
+        );
+        return true;
+    };
+    if recorded.inputs != *inputs {
+        vprtln!(V::V, "Script inputs have changed since the last build");
+        return true;
+    }
+    let manifest = fs::read_to_string(&build_state.cargo_toml_path).map(|toml| hash_str(&toml));
+    if manifest.ok().as_ref() != Some(&recorded.manifest) {
+        vprtln!(
+            V::V,
+            "Generated Cargo.toml has changed since the last build"
+        );
+        return true;
+    }
+    false
+}
+
+/// Record the fingerprint of a freshly built and cached executable.
+///
+/// # Errors
+///
+/// Will return `Err` if the generated `Cargo.toml` cannot be read or the fingerprint cannot be written.
+#[profiled]
+pub fn save(build_state: &BuildState) -> ThagResult<()> {
+    let Some(ref inputs) = build_state.fingerprint else {
+        return Ok(());
+    };
+    let fingerprint = Fingerprint {
+        inputs: inputs.clone(),
+        manifest: hash_str(&fs::read_to_string(&build_state.cargo_toml_path)?),
+        // A dynamic script's source file is overwritten by the next one
+        source_path: (!build_state.is_dynamic).then(|| build_state.source_path.clone()),
+        last_run: None,
+    };
+    write(&build_state.target_path, &fingerprint)
+}
+
+/// Record that the cached executable at `target_path` has just been run, for least recently
+/// used eviction from the executable cache. Does nothing if it has no fingerprint.
+///
+/// # Errors
+///
+/// Will return `Err` if the fingerprint cannot be written.
+#[profiled]
+pub fn record_run(target_path: &Path) -> ThagResult<()> {
+    let Some(mut fingerprint) = read(target_path) else {
+        return Ok(());
+    };
+    fingerprint.last_run = Some(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs()),
+    );
+    write(target_path, &fingerprint)
+}
+
+#[profiled]
+fn write(target_path: &Path, fingerprint: &Fingerprint) -> ThagResult<()> {
+    let path = fingerprint_path(target_path);
+    fs::write(&path, toml::to_string(fingerprint)?)?;
+    debug_log!("Saved fingerprint {fingerprint:?} to {path:?}");
+    Ok(())
+}
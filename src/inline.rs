@@ -0,0 +1,449 @@
+//!
+//! Inlining of a small Cargo project into a single-file script, invoked by the `--inline` option.
+//!
+//! This is the reverse of `--export`. `thag --inline <crate-dir>` reads the crate's `Cargo.toml`,
+//! its `src/main.rs` and the module files it declares, and writes `<package>.rs` with:
+//!
+//! - a `/*[toml]` block carrying the crate's dependencies, features, profiles, patches, lints and
+//!   edition, with relative path dependencies made absolute, and
+//! - the main source with each `mod foo;` declaration replaced by an inline `mod foo { ... }` block
+//!   holding the content of its file, recursively.
+//!
+//! Anything that a script can't represent, such as a build script, multiple binaries, a library
+//! target or workspace inheritance, is left out and reported.
+//!
+use crate::modules::{self, ModuleDirs};
+use crate::{Cli, ThagResult, RS_SUFFIX, TOML_NAME};
+use proc_macro2::LineColumn;
+use std::{
+    collections::HashSet,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+use syn::{spanned::Spanned, ItemMod, Visibility};
+use thag_common::{debug_log, vprtln, V};
+use thag_profiler::profiled;
+use thag_styling::{svprtln, Role};
+use toml::{Table, Value};
+
+/// The manifest sections that are carried over into the script's toml block.
+const KEPT_SECTIONS: &[&str] = &[
+    "dependencies",
+    "dev-dependencies",
+    "features",
+    "profile",
+    "patch",
+    "target",
+    "lints",
+];
+
+/// The dependency tables of a manifest or of a `[target.<cfg>]` section that are carried over.
+const DEPENDENCY_SECTIONS: &[&str] = &["dependencies", "dev-dependencies"];
+
+/// A crate inlined into a single-file script.
+#[derive(Clone, Debug, Default)]
+pub struct Inlined {
+    /// The package name of the crate, which becomes the file stem of the script
+    pub name: String,
+    /// The source of the script
+    pub script: String,
+    /// The parts of the crate that could not be represented in the script
+    pub issues: Vec<String>,
+}
+
+/// Inline the crate in `crate_dir` into a single-file script.
+///
+/// # Errors
+///
+/// Will return `Err` if the crate's `Cargo.toml` cannot be read or parsed, if it is not a package,
+/// or if its main source file cannot be read.
+#[profiled]
+pub fn inline_crate(crate_dir: &Path) -> ThagResult<Inlined> {
+    let manifest_path = crate_dir.join(TOML_NAME);
+    let manifest: Table = toml::from_str(
+        &fs::read_to_string(&manifest_path)
+            .map_err(|e| format!("Could not read {}: {e}", manifest_path.display()))?,
+    )?;
+    let package = manifest
+        .get("package")
+        .and_then(Value::as_table)
+        .ok_or_else(|| {
+            format!(
+                "{} has no [package] section: a workspace can't be inlined, only one of its packages",
+                manifest_path.display()
+            )
+        })?;
+    let name = package
+        .get("name")
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("{} has no package name", manifest_path.display()))?
+        .to_string();
+
+    let mut issues = vec![];
+    let main_path = main_path(crate_dir, &manifest, &mut issues);
+    check_targets(crate_dir, &manifest, &mut issues);
+
+    let toml_block = toml_block(crate_dir, &manifest, &mut issues)?;
+    let main_source = fs::read_to_string(&main_path)
+        .map_err(|e| format!("Could not read {}: {e}", main_path.display()))?;
+    let main_dir = main_path
+        .parent()
+        .map_or_else(|| crate_dir.to_path_buf(), Path::to_path_buf);
+    let mut visited = HashSet::from([main_path.clone()]);
+    let source = inline_modules(
+        &main_source,
+        &main_path,
+        &ModuleDirs::root(&main_dir),
+        crate_dir,
+        &mut visited,
+        &mut issues,
+    );
+
+    let script = if toml_block.trim().is_empty() {
+        source
+    } else {
+        format!("/*[toml]\n{}\n*/\n\n{source}", toml_block.trim_end())
+    };
+    Ok(Inlined {
+        name,
+        script,
+        issues,
+    })
+}
+
+/// The path of the binary's main source file: that of the only `[[bin]]` target if it has one,
+/// otherwise `src/main.rs`.
+#[profiled]
+fn main_path(crate_dir: &Path, manifest: &Table, issues: &mut Vec<String>) -> PathBuf {
+    let bins = manifest
+        .get("bin")
+        .and_then(Value::as_array)
+        .map_or(&[][..], Vec::as_slice);
+    let bin_dir = crate_dir.join("src").join("bin");
+    let extra_bins = fs::read_dir(&bin_dir).map_or(0, |entries| {
+        entries
+            .filter_map(Result::ok)
+            .filter(|entry| {
+                let path = entry.path();
+                path.extension().is_some_and(|ext| ext == "rs") || path.join("main.rs").exists()
+            })
+            .count()
+    });
+    let main_path = bins
+        .first()
+        .and_then(|bin| bin.get("path"))
+        .and_then(Value::as_str)
+        .map_or_else(
+            || crate_dir.join("src").join("main.rs"),
+            |path| crate_dir.join(path),
+        );
+    if bins.len() > 1 || extra_bins > 0 {
+        issues.push(format!(
+            "The crate has multiple binaries: only {} is inlined",
+            main_path
+                .strip_prefix(crate_dir)
+                .unwrap_or(&main_path)
+                .display()
+        ));
+    }
+    main_path
+}
+
+/// Report the targets and build script that a script can't represent.
+#[profiled]
+fn check_targets(crate_dir: &Path, manifest: &Table, issues: &mut Vec<String>) {
+    let build_script = match manifest
+        .get("package")
+        .and_then(|package| package.get("build"))
+    {
+        Some(Value::Boolean(false)) => None,
+        Some(Value::String(path)) => Some(path.clone()),
+        _ => crate_dir
+            .join("build.rs")
+            .exists()
+            .then(|| "build.rs".to_string()),
+    };
+    if let Some(build_script) = build_script {
+        issues.push(format!(
+            "Build script {build_script} can't be run by a script and is left out"
+        ));
+    }
+    if manifest.contains_key("lib") || crate_dir.join("src").join("lib.rs").exists() {
+        issues.push(
+            "The library target is left out: move any items the binary uses from it into the script"
+                .to_string(),
+        );
+    }
+    for target in ["example", "test", "bench"] {
+        if manifest.contains_key(target) {
+            issues.push(format!("The [[{target}]] targets are left out"));
+        }
+    }
+}
+
+/// The content of the script's toml block, from the sections of the manifest that a script can
+/// represent.
+#[profiled]
+fn toml_block(crate_dir: &Path, manifest: &Table, issues: &mut Vec<String>) -> ThagResult<String> {
+    let mut package = Table::new();
+    if let Some(crate_package) = manifest.get("package").and_then(Value::as_table) {
+        for (key, value) in crate_package {
+            if is_inherited(value) {
+                issues.push(format!(
+                    "package.{key} is inherited from the workspace and is left out"
+                ));
+            } else if key == "edition" {
+                package.insert(key.clone(), value.clone());
+            }
+        }
+    }
+
+    let mut block = Table::new();
+    for (key, value) in manifest {
+        match key.as_str() {
+            "package" | "bin" | "lib" | "example" | "test" | "bench" => {}
+            "build-dependencies" => {
+                debug_log!("Leaving out build-dependencies with the build script");
+            }
+            // A script is always a workspace of its own
+            "workspace"
+                if value
+                    .get("members")
+                    .and_then(Value::as_array)
+                    .is_none_or(Vec::is_empty) => {}
+            "lints" if is_inherited(value) => {
+                issues.push("[lints] are inherited from the workspace and are left out".into());
+            }
+            _ if KEPT_SECTIONS.contains(&key.as_str()) => {
+                let mut value = value.clone();
+                if key == "target" {
+                    if let Some(targets) = value.as_table_mut() {
+                        for (cfg, target) in targets.iter_mut() {
+                            if let Some(target) = target.as_table_mut() {
+                                target.remove("build-dependencies");
+                            }
+                            for section in DEPENDENCY_SECTIONS {
+                                if let Some(deps) =
+                                    target.get_mut(*section).and_then(Value::as_table_mut)
+                                {
+                                    let context = format!("target.{cfg}.{section}");
+                                    resolve_dependencies(crate_dir, deps, &context, issues);
+                                }
+                            }
+                        }
+                    }
+                } else if let Some(deps) = DEPENDENCY_SECTIONS
+                    .contains(&key.as_str())
+                    .then(|| value.as_table_mut())
+                    .flatten()
+                {
+                    resolve_dependencies(crate_dir, deps, key, issues);
+                }
+                block.insert(key.clone(), value);
+            }
+            _ => issues.push(format!(
+                "[{key}] is not supported in a script and is left out"
+            )),
+        }
+    }
+
+    // Keep the package section at the top, where Cargo.toml has it
+    let mut toml = String::new();
+    if !package.is_empty() {
+        let mut package_block = Table::new();
+        package_block.insert("package".to_string(), Value::Table(package));
+        toml.push_str(&toml::to_string(&package_block)?);
+        if !block.is_empty() {
+            toml.push('\n');
+        }
+    }
+    toml.push_str(&toml::to_string(&block)?);
+    Ok(toml)
+}
+
+/// Leave out the dependencies inherited from the workspace and make relative path dependencies
+/// absolute, since the script won't be in the crate directory.
+#[profiled]
+fn resolve_dependencies(
+    crate_dir: &Path,
+    deps: &mut Table,
+    context: &str,
+    issues: &mut Vec<String>,
+) {
+    deps.retain(|name, dep| {
+        if is_inherited(dep) {
+            issues.push(format!(
+                "Dependency {name} in [{context}] is inherited from the workspace and is left out: add its version to the toml block"
+            ));
+            return false;
+        }
+        true
+    });
+    for (_, dep) in deps.iter_mut() {
+        let Some(Value::String(path)) = dep.get_mut("path") else {
+            continue;
+        };
+        if Path::new(path.as_str()).is_relative() {
+            let absolute = crate_dir.join(path.as_str());
+            *path = absolute
+                .canonicalize()
+                .unwrap_or(absolute)
+                .display()
+                .to_string();
+        }
+    }
+}
+
+/// Whether a manifest value is inherited from the workspace, as in `{ workspace = true }`.
+fn is_inherited(value: &Value) -> bool {
+    value
+        .get("workspace")
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+/// Replace each `mod foo;` declaration in `source`, the content of `source_path`, by an inline
+/// module with the content of its file, recursively. The files are resolved by the `modules`
+/// module the way `rustc` does. The declaration keeps its attributes other than `#[path]`.
+/// A declaration whose file can't be found is left as it is and reported.
+// Recursive - do not profile
+fn inline_modules(
+    source: &str,
+    source_path: &Path,
+    dirs: &ModuleDirs,
+    crate_dir: &Path,
+    visited: &mut HashSet<PathBuf>,
+    issues: &mut Vec<String>,
+) -> String {
+    let file = match syn::parse_file(source) {
+        Ok(file) => file,
+        Err(e) => {
+            issues.push(format!(
+                "Could not parse {}, so its modules are left as they are: {e}",
+                source_path.display()
+            ));
+            return source.to_string();
+        }
+    };
+    let mut decls = vec![];
+    modules::module_decls(&file.items, dirs, &mut decls);
+
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let offset = |location: LineColumn| byte_offset(source, &line_starts, location);
+
+    let mut output = String::with_capacity(source.len());
+    let mut copied = 0;
+    for decl in decls {
+        let item = decl.item;
+        let name = &item.ident;
+        // Only file modules are resolved, so the declaration ends in `;`
+        let Some(semi) = item.semi else {
+            continue;
+        };
+        let content = if visited.contains(&decl.path) {
+            None
+        } else {
+            fs::read_to_string(&decl.path).ok()
+        };
+        let Some(content) = content else {
+            issues.push(format!(
+                "Module file {} for `mod {name};` was not found and the declaration is left as is",
+                decl.path.display()
+            ));
+            continue;
+        };
+        visited.insert(decl.path.clone());
+        debug_log!("Inlining module {name} from {}", decl.path.display());
+
+        // The declaration up to its `;`, less any `#[path]` attributes
+        let head_start = offset(head_span(item).start());
+        let mut part_starts: Vec<usize> = item
+            .attrs
+            .iter()
+            .map(|attr| offset(attr.pound_token.span.start()))
+            .collect();
+        part_starts.push(head_start);
+        let mut declaration = String::new();
+        for (attr, part) in item.attrs.iter().zip(part_starts.windows(2)) {
+            if !attr.path().is_ident("path") {
+                declaration.push_str(&source[part[0]..part[1]]);
+            }
+        }
+        let semi_start = offset(semi.span.start());
+        declaration.push_str(source[head_start..semi_start].trim_end());
+
+        let start = part_starts[0];
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let indent = &source[line_start..start];
+        let indent = if indent.trim().is_empty() { indent } else { "" };
+
+        let mut content =
+            inline_modules(&content, &decl.path, &decl.dirs, crate_dir, visited, issues);
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        let relative = decl.path.strip_prefix(crate_dir).unwrap_or(&decl.path);
+        output.push_str(&source[copied..start]);
+        let _ = write!(
+            output,
+            "// Inlined from {}\n{indent}{declaration} {{\n{content}{indent}}}",
+            relative.display()
+        );
+        copied = semi_start + 1;
+    }
+    output.push_str(&source[copied..]);
+    output
+}
+
+/// The span of the part of a module declaration that follows its attributes.
+fn head_span(item: &ItemMod) -> proc_macro2::Span {
+    match (&item.vis, &item.unsafety) {
+        (Visibility::Inherited, Some(unsafety)) => unsafety.span,
+        (Visibility::Inherited, None) => item.mod_token.span,
+        (vis, _) => vis.span(),
+    }
+}
+
+/// The byte offset in `source` of a span location, whose line is counted from 1 and whose
+/// column is counted in characters.
+fn byte_offset(source: &str, line_starts: &[usize], location: LineColumn) -> usize {
+    let Some(&line_start) = line_starts.get(location.line.saturating_sub(1)) else {
+        return source.len();
+    };
+    source[line_start..]
+        .char_indices()
+        .nth(location.column)
+        .map_or(source.len(), |(i, _)| line_start + i)
+}
+
+/// Carry out an `--inline` command, writing the script to `<package>.rs` in the current directory.
+///
+/// # Errors
+///
+/// Will return `Err` if the crate can't be inlined, or if the script already exists or can't be
+/// written.
+#[profiled]
+pub fn execute(cli: &Cli) -> ThagResult<()> {
+    let Some(ref crate_dir) = cli.inline else {
+        return Err("Missing crate directory for --inline".into());
+    };
+    let inlined = inline_crate(Path::new(crate_dir))?;
+    let path = PathBuf::from(format!("{}{RS_SUFFIX}", inlined.name));
+    if path.exists() {
+        return Err(format!("File `{}` already exists", path.display()).into());
+    }
+    fs::write(&path, &inlined.script)?;
+    for issue in &inlined.issues {
+        svprtln!(Role::WARN, V::QQ, "{issue}");
+    }
+    vprtln!(
+        V::N,
+        "✓ Inlined {crate_dir} into {}. Run it with `thag {}`",
+        path.display(),
+        path.display()
+    );
+    Ok(())
+}
@@ -89,14 +89,59 @@ pub mod code_utils;
 //-----------------------------------------------------------------------------
 // Build System
 //-----------------------------------------------------------------------------
+/// Batch checking or building of every script in a directory
+#[cfg(feature = "build")]
+pub mod batch;
 #[cfg(feature = "build")]
 pub mod builder;
 /// Command-line argument and processing flags handling
 #[cfg(feature = "build")]
 pub mod cmd_args;
+/// Machine-readable JSON event stream for builds and runs
+#[cfg(feature = "build")]
+pub mod events;
+/// Executable cache listing, pruning and size limits
+#[cfg(feature = "build")]
+pub mod exec_cache;
+/// Export of a script as a standalone Cargo project
+#[cfg(feature = "build")]
+pub mod export;
+/// Content fingerprints for deciding when a script must be rebuilt
+#[cfg(feature = "build")]
+pub mod fingerprint;
+/// Inlining of a small Cargo project into a single-file script
+#[cfg(feature = "build")]
+pub mod inline;
+/// Resource limits and timeouts for running scripts
+#[cfg(feature = "build")]
+pub mod limits;
+/// Mapping of compiler diagnostics from generated source back to the script
+#[cfg(feature = "build")]
+pub mod line_map;
+/// Per-script lockfiles for reproducible dependency resolution
+#[cfg(feature = "build")]
+pub mod lockfile;
+/// Persistent cache of crate lookups for dependency inference
+#[cfg(feature = "build")]
+pub mod lookup_cache;
 /// Manifest processing and Cargo.toml generation for the script
 #[cfg(feature = "build")]
 pub mod manifest;
+/// Module resolution for multi-file scripts
+#[cfg(feature = "build")]
+pub mod modules;
+/// Offline crate lookup from the local Cargo registry index and vendored crates
+#[cfg(feature = "build")]
+pub mod offline;
+/// Embeddable API for running scripts and expressions from other programs
+#[cfg(feature = "build")]
+pub mod runner;
+/// Creation of new scripts from built-in and user templates
+#[cfg(feature = "build")]
+pub mod templates;
+/// Watch mode: regenerate, rebuild and rerun a script whenever it or its inputs change
+#[cfg(feature = "build")]
+pub mod watch;
 
 //-----------------------------------------------------------------------------
 // UI and configuration:
@@ -168,10 +213,10 @@ pub use {
 pub use {
     builder::{display_timings, execute, gen_build_run, process_expr, BuildState, ScriptState},
     cmd_args::{get_args, get_proc_flags, set_verbosity, validate_args, Cli, ProcFlags},
-    code_utils::modified_since_compiled,
     logging::configure_log,
     manifest::extract,
     ratatui::crossterm,
+    runner::{RunOutput, Runner},
 };
 
 #[cfg(feature = "color_detect")]
@@ -185,8 +230,8 @@ pub use thag_styling::inquire_theming;
 
 #[cfg(feature = "config")]
 pub use config::{
-    load, maybe_config, Config, Context, Dependencies, FeatureOverride, Logging, Misc, ProcMacros,
-    Styling,
+    load, maybe_config, AsyncRuntime, Config, Context, Dependencies, FeatureOverride, Logging,
+    Misc, ProcMacros, Run, Styling,
 };
 
 #[cfg(feature = "tui")]
@@ -215,6 +260,11 @@ pub const DYNAMIC_SUBDIR: &str = "rs_dyn";
 pub const SHARED_TARGET_SUBDIR: &str = "thag_rs_shared_target";
 /// Subdirectory name for executable cache (stores built script executables)
 pub const EXECUTABLE_CACHE_SUBDIR: &str = "thag_rs_bins";
+/// Subdirectory name for the dynamic executable cache (stores built expressions, filters and
+/// snippets by content hash)
+pub const DYNAMIC_CACHE_SUBDIR: &str = "thag_rs_dyn_bins";
+/// Subdirectory name for the crate lookup cache (stores resolved versions and features of crates)
+pub const LOOKUP_CACHE_SUBDIR: &str = "thag_rs_lookups";
 /// Length of decorative flower box borders for output formatting
 pub const FLOWER_BOX_LEN: usize = 70;
 /// Package name from Cargo.toml
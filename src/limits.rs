@@ -0,0 +1,361 @@
+//!
+//! Resource limits on running scripts, set by the `--timeout`, `--max-memory` and `--max-cpu`
+//! options or by defaults in the `[run]` section of the configuration.
+//!
+//! The time limit is enforced by a watchdog that waits on the script and kills it when the time
+//! is up. On Unix the memory and CPU limits are set as rlimits of the script's process, so that
+//! an allocation beyond the memory limit fails and the kernel stops the script when it has used
+//! its CPU time. Any child processes of the script inherit the rlimits but not the watchdog.
+//!
+//! A script stopped by the time or CPU limit is reported with `ThagError::LimitExceeded`, for
+//! which `thag` exits with `LIMIT_EXCEEDED_EXIT_CODE`. A script that fails an allocation under
+//! the memory limit usually aborts, which is reported like any other signal death.
+//!
+use crate::{maybe_config, Cli, ThagError, ThagResult};
+use std::{
+    io::Read,
+    process::{Child, Command, ExitStatus, Output, Stdio},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use thag_common::debug_log;
+use thag_profiler::profiled;
+
+/// How often the watchdog checks whether the script has finished.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The resource limits on running a script.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// The wall-clock time the script may run for
+    pub timeout: Option<Duration>,
+    /// The virtual memory in bytes the script may use
+    pub max_memory: Option<u64>,
+    /// The CPU time in seconds the script may use
+    pub max_cpu: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// The limits specified on the command line, or failing that in the `[run]` section of the
+    /// configuration.
+    #[must_use]
+    #[profiled]
+    pub fn resolve(cli: &Cli) -> Self {
+        let run_config = maybe_config().map(|config| config.run).unwrap_or_default();
+        Self {
+            timeout: cli
+                .timeout
+                .or_else(|| run_config.timeout_secs.map(Duration::from_secs)),
+            max_memory: cli.max_memory.or_else(|| {
+                run_config
+                    .max_memory_mb
+                    .map(|mb| mb.saturating_mul(1024 * 1024))
+            }),
+            max_cpu: cli.max_cpu.or(run_config.max_cpu_secs),
+        }
+    }
+
+    /// Spawn a command within the limits. The time limit is not enforced here, but is left to
+    /// the caller to check with `LimitedChild::is_overdue`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the command cannot be spawned.
+    #[profiled]
+    pub fn spawn(&self, command: &mut Command) -> ThagResult<LimitedChild> {
+        self.set_rlimits(command);
+        let cpu_before = children_cpu_time();
+        let child = command.spawn()?;
+        Ok(LimitedChild {
+            child,
+            started: Instant::now(),
+            cpu_before,
+        })
+    }
+
+    /// Run a command within the limits, capturing its standard output and standard error if
+    /// `capture_output` is true. Returns its output and its usage of the limited resources.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the command cannot be spawned or waited on.
+    #[profiled]
+    pub fn run(
+        &self,
+        command: &mut Command,
+        capture_output: bool,
+    ) -> ThagResult<(Output, RunUsage)> {
+        if capture_output {
+            command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
+        let mut running = self.spawn(command)?;
+
+        // Drain the pipes while waiting, so that a chatty script can't block on a full pipe
+        let stdout = running
+            .child
+            .stdout
+            .take()
+            .map(|pipe| thread::spawn(|| read_pipe(pipe)));
+        let stderr = running
+            .child
+            .stderr
+            .take()
+            .map(|pipe| thread::spawn(|| read_pipe(pipe)));
+        let (status, timed_out) = wait(&mut running.child, self.timeout)?;
+
+        let output = Output {
+            status,
+            stdout: join_reader(stdout),
+            stderr: join_reader(stderr),
+        };
+        Ok((output, running.usage(timed_out)))
+    }
+
+    /// Check whether a script that exited with `status` after using `usage` was stopped by one
+    /// of the limits.
+    ///
+    /// Only a stop that can be put down to a limit is reported as such. A failed allocation
+    /// under the memory limit can't be told apart from any other abort, so a script killed by a
+    /// signal other than `SIGXCPU` is treated as an ordinary signal death, unless it is a
+    /// `SIGKILL` after it has used up its CPU time.
+    ///
+    /// # Errors
+    ///
+    /// Will return `ThagError::LimitExceeded` if the watchdog stopped the script, or if it was
+    /// stopped by the kernel for exceeding the CPU limit.
+    #[profiled]
+    pub fn check(&self, status: ExitStatus, usage: &RunUsage) -> ThagResult<()> {
+        if let Some(timeout) = self.timeout.filter(|_| usage.timed_out) {
+            return Err(ThagError::LimitExceeded(format!(
+                "Script exceeded the time limit of {timeout:?} and was stopped"
+            )));
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+
+            if let Some(max_cpu) = self.max_cpu {
+                let used_cpu = usage
+                    .cpu_time
+                    .is_some_and(|cpu_time| cpu_time >= Duration::from_secs(max_cpu));
+                match status.signal() {
+                    Some(libc::SIGXCPU) => {}
+                    // The hard limit, if the script ignored the SIGXCPU
+                    Some(libc::SIGKILL) if used_cpu => {}
+                    _ => return Ok(()),
+                }
+                return Err(ThagError::LimitExceeded(format!(
+                    "Script exceeded the CPU time limit of {max_cpu}s and was stopped"
+                )));
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = status;
+
+        Ok(())
+    }
+
+    /// Set the memory and CPU limits as rlimits of the command's process.
+    #[cfg(unix)]
+    fn set_rlimits(&self, command: &mut Command) {
+        use std::os::unix::process::CommandExt;
+
+        let (max_memory, max_cpu) = (self.max_memory, self.max_cpu);
+        if max_memory.is_none() && max_cpu.is_none() {
+            return;
+        }
+        // SAFETY: the closure runs in the forked child before `exec`, and only calls `getrlimit`
+        // and `setrlimit`, which are async-signal-safe and don't allocate.
+        unsafe {
+            command.pre_exec(move || {
+                let set_rlimit = |resource, soft: u64, hard: u64| {
+                    let mut current = libc::rlimit {
+                        rlim_cur: 0,
+                        rlim_max: 0,
+                    };
+                    if libc::getrlimit(resource, &raw mut current) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    // An unprivileged process can't raise its hard limit
+                    let hard = (hard as libc::rlim_t).min(current.rlim_max);
+                    let limit = libc::rlimit {
+                        rlim_cur: (soft as libc::rlim_t).min(hard),
+                        rlim_max: hard,
+                    };
+                    if libc::setrlimit(resource, &raw const limit) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    Ok(())
+                };
+                if let Some(bytes) = max_memory {
+                    set_rlimit(libc::RLIMIT_AS, bytes, bytes)?;
+                }
+                if let Some(secs) = max_cpu {
+                    // SIGXCPU at the soft limit, then SIGKILL at the hard limit if it's ignored
+                    set_rlimit(libc::RLIMIT_CPU, secs, secs.saturating_add(1))?;
+                }
+                Ok(())
+            });
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn set_rlimits(&self, _command: &mut Command) {
+        if self.max_memory.is_some() || self.max_cpu.is_some() {
+            thag_common::vprtln!(
+                thag_common::V::N,
+                "Memory and CPU limits are only supported on Unix, so will be ignored"
+            );
+        }
+    }
+}
+
+/// The usage of the limited resources by a finished script.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RunUsage {
+    /// Whether the watchdog stopped the script at the time limit
+    pub timed_out: bool,
+    /// The CPU time used by the script and any children it waited for, where known
+    pub cpu_time: Option<Duration>,
+}
+
+/// A script spawned within the limits by `ResourceLimits::spawn`.
+#[derive(Debug)]
+pub struct LimitedChild {
+    /// The script's process
+    pub child: Child,
+    started: Instant,
+    cpu_before: Option<Duration>,
+}
+
+impl LimitedChild {
+    /// Whether the script has been running for longer than `timeout`.
+    #[must_use]
+    pub fn is_overdue(&self, timeout: Option<Duration>) -> bool {
+        timeout.is_some_and(|timeout| self.started.elapsed() >= timeout)
+    }
+
+    /// The script's usage of the limited resources, once it has been waited on.
+    #[must_use]
+    pub fn usage(&self, timed_out: bool) -> RunUsage {
+        let cpu_time = self
+            .cpu_before
+            .zip(children_cpu_time())
+            .map(|(before, after)| after.saturating_sub(before));
+        RunUsage {
+            timed_out,
+            cpu_time,
+        }
+    }
+}
+
+/// The total CPU time used so far by the children of this process that have been waited for.
+#[cfg(unix)]
+fn children_cpu_time() -> Option<Duration> {
+    // SAFETY: `rusage` is plain old data, for which all zeroes is a valid value.
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    // SAFETY: `usage` is a valid, writable `rusage`.
+    if unsafe { libc::getrusage(libc::RUSAGE_CHILDREN, &raw mut usage) } != 0 {
+        return None;
+    }
+    let to_duration = |time: libc::timeval| {
+        Duration::from_secs(u64::try_from(time.tv_sec).unwrap_or_default())
+            + Duration::from_micros(u64::try_from(time.tv_usec).unwrap_or_default())
+    };
+    Some(to_duration(usage.ru_utime) + to_duration(usage.ru_stime))
+}
+
+#[cfg(not(unix))]
+const fn children_cpu_time() -> Option<Duration> {
+    None
+}
+
+/// Wait for the child to finish, killing it if it is still running after `timeout`.
+/// Returns its exit status and whether it was killed.
+fn wait(child: &mut Child, timeout: Option<Duration>) -> ThagResult<(ExitStatus, bool)> {
+    let Some(timeout) = timeout else {
+        return Ok((child.wait()?, false));
+    };
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok((status, false));
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            debug_log!("Killing script {} after {timeout:?}", child.id());
+            child.kill()?;
+            return Ok((child.wait()?, true));
+        }
+        thread::sleep(POLL_INTERVAL.min(deadline - now));
+    }
+}
+
+fn read_pipe(mut pipe: impl Read) -> Vec<u8> {
+    let mut buffer = vec![];
+    if let Err(e) = pipe.read_to_end(&mut buffer) {
+        debug_log!("Error reading script output: {e}");
+    }
+    buffer
+}
+
+fn join_reader(reader: Option<JoinHandle<Vec<u8>>>) -> Vec<u8> {
+    reader
+        .and_then(|reader| reader.join().ok())
+        .unwrap_or_default()
+}
+
+/// Parse a duration such as `30s`, `5m`, `1h` or `500ms`. A number without a unit is in seconds.
+///
+/// # Errors
+///
+/// Will return `Err` if the duration is not a positive whole number followed by an optional unit.
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let duration = duration.trim();
+    let split = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration.len());
+    let (number, unit) = duration.split_at(split);
+    let number: u64 = number
+        .parse()
+        .ok()
+        .filter(|&number| number > 0)
+        .ok_or_else(|| {
+            format!("Invalid duration '{duration}': expected a number and unit, e.g. 30s")
+        })?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(number)),
+        "" | "s" => Ok(Duration::from_secs(number)),
+        "m" => Ok(Duration::from_secs(number.saturating_mul(60))),
+        "h" => Ok(Duration::from_secs(number.saturating_mul(60 * 60))),
+        _ => Err(format!("Invalid duration unit '{unit}': use ms, s, m or h")),
+    }
+}
+
+/// Parse a size in bytes such as `512M`, `2G` or `4096K`, with an optional trailing `B`.
+/// A number without a unit is in bytes.
+///
+/// # Errors
+///
+/// Will return `Err` if the size is not a positive whole number followed by an optional unit.
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number: u64 = number
+        .parse()
+        .ok()
+        .filter(|&number| number > 0)
+        .ok_or_else(|| format!("Invalid size '{size}': expected a number and unit, e.g. 512M"))?;
+    let multiplier: u64 = match unit.to_ascii_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" => 1024,
+        "M" => 1024 * 1024,
+        "G" => 1024 * 1024 * 1024,
+        _ => return Err(format!("Invalid size unit '{unit}': use K, M or G")),
+    };
+    Ok(number.saturating_mul(multiplier))
+}
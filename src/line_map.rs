@@ -0,0 +1,423 @@
+//!
+//! Mapping of compiler diagnostics from the generated source of a script back to the script.
+//!
+//! A snippet or expression is not compiled as written: any shebang and frontmatter are removed,
+//! inner attributes are extracted, the code is wrapped in a `main` function with a preamble of
+//! its own, expressions may be rewritten to print their values, and the result is reformatted.
+//! So the compiler's diagnostics refer to lines of the generated source, which the user may
+//! never see, especially for `--expr` and `--loop`.
+//!
+//! A `LineMap` is built after generation by aligning the tokens of the generated source with
+//! those of the script, ignoring whitespace and comments. Tokens that only occur in the
+//! generated source, such as those of the wrapper, are left unmatched. Cargo's JSON diagnostics
+//! are then rewritten so that their spans, and the locations and line numbers of their rendered
+//! text, refer to the script. Anything that cannot be mapped is left as reported.
+//!
+use crate::code_utils::strip_frontmatter;
+use regex::Regex;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use thag_common::re;
+use thag_profiler::profiled;
+
+/// How many tokens of the script to search ahead for the next match.
+const SEARCH_WINDOW: usize = 256;
+
+/// How many consecutive tokens must match to anchor a token of the generated source.
+const MIN_RUN: usize = 3;
+
+/// A 1-based line and column, as reported by the compiler.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    /// The line number
+    pub line: usize,
+    /// The column number, in characters
+    pub column: usize,
+}
+
+/// A token of Rust source code and where it starts.
+#[derive(Clone, Debug)]
+struct Token<'a> {
+    text: &'a str,
+    position: Position,
+}
+
+/// A mapping of positions in the generated source of a script to positions in the script.
+#[derive(Clone, Debug, Default)]
+pub struct LineMap {
+    /// The path of the script, as diagnostics are to report it
+    pub script_path: PathBuf,
+    /// The path of the generated source
+    pub generated_path: PathBuf,
+    /// The lines of the script
+    script_lines: Vec<String>,
+    /// Matched tokens as pairs of generated and script positions, in generated order
+    anchors: Vec<(Position, Position)>,
+}
+
+impl LineMap {
+    /// Start a `LineMap` for a script with the given source. Positions can only be mapped once
+    /// it has been aligned with the generated source.
+    ///
+    /// The path need not exist: a label such as `<expr>` may be given for a script that has no
+    /// file of its own.
+    #[must_use]
+    #[profiled]
+    pub fn new(script_path: &Path, script_source: &str) -> Self {
+        // Blanked out, so that the manifest doesn't take part in the alignment
+        let script_source = strip_frontmatter(script_source);
+        Self {
+            script_path: script_path.to_path_buf(),
+            generated_path: PathBuf::new(),
+            script_lines: script_source.lines().map(str::to_string).collect(),
+            anchors: vec![],
+        }
+    }
+
+    /// Align the tokens of the generated source with those of the script.
+    #[profiled]
+    pub fn align(&mut self, generated_path: &Path, generated_source: &str) {
+        let script_source = self.script_lines.join("\n");
+        let script_tokens = tokenize(&script_source);
+        let generated_tokens = tokenize(generated_source);
+
+        let mut anchors = vec![];
+        let mut cursor = 0;
+        let mut previous: Option<usize> = None;
+        for (i, token) in generated_tokens.iter().enumerate() {
+            // Continue a matched run, or else look ahead for a new one
+            let found = previous
+                .map(|j| j + 1)
+                .filter(|&j| script_tokens.get(j).is_some_and(|t| t.text == token.text))
+                .or_else(|| {
+                    // A very short script can't supply a full run
+                    let run = MIN_RUN
+                        .min(script_tokens.len())
+                        .min(generated_tokens.len() - i);
+                    let end = script_tokens.len().min(cursor + SEARCH_WINDOW);
+                    (cursor..end).find(|&j| {
+                        (0..run).all(|k| {
+                            script_tokens
+                                .get(j + k)
+                                .is_some_and(|script| script.text == generated_tokens[i + k].text)
+                        })
+                    })
+                });
+            previous = found;
+            if let Some(j) = found {
+                anchors.push((token.position, script_tokens[j].position));
+                cursor = j + 1;
+            }
+        }
+
+        self.generated_path = generated_path.to_path_buf();
+        self.anchors = anchors;
+    }
+
+    /// The line of the script that a line of the generated source came from, if any.
+    #[must_use]
+    pub fn script_line(&self, generated_line: usize) -> Option<usize> {
+        self.anchors
+            .iter()
+            .find(|(generated, _)| generated.line == generated_line)
+            .map(|(_, script)| script.line)
+    }
+
+    /// The position in the script that a position in the generated source came from, if any.
+    /// Only positions on lines with at least one matched token can be mapped.
+    #[must_use]
+    pub fn script_position(&self, generated: Position) -> Option<Position> {
+        let on_line = self
+            .anchors
+            .iter()
+            .filter(|(anchor, _)| anchor.line == generated.line);
+        // Measure from the nearest matched token at or before the position, else the first after it
+        let (anchor, script) = on_line
+            .clone()
+            .rev()
+            .find(|(anchor, _)| anchor.column <= generated.column)
+            .or_else(|| on_line.clone().next())?;
+        let column = (script.column + generated.column).saturating_sub(anchor.column);
+        Some(Position {
+            line: script.line,
+            column: column.max(1),
+        })
+    }
+
+    /// Whether a file name in a diagnostic refers to the generated source, allowing for Cargo
+    /// reporting it relative to the generated project.
+    fn is_generated(&self, file_name: &str) -> bool {
+        let path = Path::new(file_name);
+        path == self.generated_path
+            || self
+                .generated_path
+                .parent()
+                .is_some_and(|project_dir| project_dir.join(path) == self.generated_path)
+    }
+
+    /// Rewrite a compiler diagnostic from Cargo's JSON output, including its children, so that
+    /// its spans and rendered text refer to the script wherever they can be mapped.
+    #[profiled]
+    pub fn rewrite_diagnostic(&self, diagnostic: &mut Value) {
+        if let Some(spans) = diagnostic.get_mut("spans").and_then(Value::as_array_mut) {
+            for span in spans {
+                self.rewrite_span(span);
+            }
+        }
+        if let Some(children) = diagnostic.get_mut("children").and_then(Value::as_array_mut) {
+            for child in children {
+                self.rewrite_diagnostic(child);
+            }
+        }
+        if let Some(rendered) = diagnostic.get("rendered").and_then(Value::as_str) {
+            let rendered = self.rewrite_rendered(rendered);
+            diagnostic["rendered"] = Value::String(rendered);
+        }
+    }
+
+    fn rewrite_span(&self, span: &mut Value) {
+        if let Some(expansion) = span.get_mut("expansion").filter(|e| e.is_object()) {
+            for key in ["span", "def_site_span"] {
+                if let Some(expansion_span) = expansion.get_mut(key) {
+                    self.rewrite_span(expansion_span);
+                }
+            }
+        }
+
+        let field = |key: &str| {
+            span.get(key)
+                .and_then(Value::as_u64)
+                .and_then(|n| usize::try_from(n).ok())
+        };
+        let (Some(line_start), Some(column_start), Some(line_end), Some(column_end)) = (
+            field("line_start"),
+            field("column_start"),
+            field("line_end"),
+            field("column_end"),
+        ) else {
+            return;
+        };
+        if !span
+            .get("file_name")
+            .and_then(Value::as_str)
+            .is_some_and(|file_name| self.is_generated(file_name))
+        {
+            return;
+        }
+        let Some(start) = self.script_position(Position {
+            line: line_start,
+            column: column_start,
+        }) else {
+            return;
+        };
+        let end = self
+            .script_position(Position {
+                line: line_end,
+                column: column_end,
+            })
+            .filter(|end| *end >= start)
+            .unwrap_or_else(|| Position {
+                line: start.line,
+                column: start.column + column_end.saturating_sub(column_start),
+            });
+
+        let text: Vec<Value> = (start.line..=end.line)
+            .filter_map(|line| {
+                let text = self.script_lines.get(line - 1)?;
+                let highlight_start = if line == start.line { start.column } else { 1 };
+                let highlight_end = if line == end.line {
+                    end.column
+                } else {
+                    text.chars().count() + 1
+                };
+                Some(json!({
+                    "text": text,
+                    "highlight_start": highlight_start,
+                    "highlight_end": highlight_end,
+                }))
+            })
+            .collect();
+
+        span["file_name"] = json!(self.script_path.display().to_string());
+        span["line_start"] = json!(start.line);
+        span["column_start"] = json!(start.column);
+        span["line_end"] = json!(end.line);
+        span["column_end"] = json!(end.column);
+        span["text"] = Value::Array(text);
+    }
+
+    /// Rewrite the locations and the line numbers in the margin of the rendered text of a
+    /// diagnostic, which may contain ANSI colour codes.
+    #[must_use]
+    #[profiled]
+    pub fn rewrite_rendered(&self, rendered: &str) -> String {
+        let location_re: &Regex = re!(
+            r"^(?P<lead>(?:\x1b\[[0-9;]*m|\s)*(?:-->|:::)(?:\x1b\[[0-9;]*m|\s)*)(?P<path>[^\x1b]+?):(?P<line>\d+):(?P<column>\d+)(?P<rest>.*)$"
+        );
+        let margin_re: &Regex = re!(
+            r"^(?P<lead>(?:\x1b\[[0-9;]*m| )*)(?P<line>\d+)(?P<rest>(?:\x1b\[[0-9;]*m| )+\|.*)$"
+        );
+
+        let mut in_generated = false;
+        let mut lines = vec![];
+        for line in rendered.split('\n') {
+            if let Some(caps) = location_re.captures(line) {
+                in_generated = self.is_generated(&caps["path"]);
+                let position = Position {
+                    line: caps["line"].parse().unwrap_or_default(),
+                    column: caps["column"].parse().unwrap_or_default(),
+                };
+                if let Some(script) = self.script_position(position).filter(|_| in_generated) {
+                    lines.push(format!(
+                        "{}{}:{}:{}{}",
+                        &caps["lead"],
+                        self.script_path.display(),
+                        script.line,
+                        script.column,
+                        &caps["rest"]
+                    ));
+                    continue;
+                }
+            } else if in_generated {
+                if let Some(caps) = margin_re.captures(line) {
+                    let lead = &caps["lead"];
+                    let width = lead.matches(' ').count() + caps["line"].len();
+                    let script_line = caps["line"]
+                        .parse()
+                        .ok()
+                        .and_then(|line| self.script_line(line))
+                        .map(|line| line.to_string())
+                        .filter(|line| line.len() <= width);
+                    if let Some(script_line) = script_line {
+                        lines.push(format!(
+                            "{}{script_line:>width$}{}",
+                            lead.replace(' ', ""),
+                            &caps["rest"]
+                        ));
+                        continue;
+                    }
+                }
+            }
+            lines.push(line.to_string());
+        }
+        lines.join("\n")
+    }
+}
+
+/// Split Rust source code into tokens with their positions, skipping whitespace and comments.
+/// Literals are kept whole, while punctuation is split into single characters so that the
+/// tokens of reformatted code still line up.
+fn tokenize(source: &str) -> Vec<Token<'_>> {
+    let chars: Vec<(usize, char)> = source.char_indices().collect();
+    let byte_at = |i: usize| chars.get(i).map_or(source.len(), |&(byte, _)| byte);
+    let char_at = |i: usize| chars.get(i).map(|&(_, c)| c);
+
+    let mut tokens = vec![];
+    let mut position = Position { line: 1, column: 1 };
+    let mut i = 0;
+    while let Some(c) = char_at(i) {
+        let start = i;
+        let next = char_at(i + 1);
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '/' && next == Some('/') {
+            while char_at(i).is_some_and(|c| c != '\n') {
+                i += 1;
+            }
+        } else if c == '/' && next == Some('*') {
+            let mut depth = 0;
+            while let Some(c) = char_at(i) {
+                match (c, char_at(i + 1)) {
+                    ('/', Some('*')) => {
+                        depth += 1;
+                        i += 2;
+                    }
+                    ('*', Some('/')) => {
+                        depth -= 1;
+                        i += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                    _ => i += 1,
+                }
+            }
+        } else if c.is_alphanumeric() || c == '_' {
+            while char_at(i).is_some_and(|c| c.is_alphanumeric() || c == '_') {
+                i += 1;
+            }
+            // A prefixed literal such as r#"..."#, b"..." or b'x'
+            match (&source[byte_at(start)..byte_at(i)], char_at(i)) {
+                ("r" | "br" | "cr", Some('"' | '#')) => i = skip_raw_string(&chars, i),
+                ("b" | "c", Some('"')) => i = skip_quoted(&chars, i, '"'),
+                ("b", Some('\'')) => i = skip_quoted(&chars, i, '\''),
+                _ => {}
+            }
+        } else if c == '"' {
+            i = skip_quoted(&chars, i, '"');
+        } else if c == '\'' {
+            // A char literal, or else a lifetime or label
+            i = if next == Some('\\') || char_at(i + 2) == Some('\'') {
+                skip_quoted(&chars, i, '\'')
+            } else {
+                let mut end = i + 1;
+                while char_at(end).is_some_and(|c| c.is_alphanumeric() || c == '_') {
+                    end += 1;
+                }
+                end
+            };
+        } else {
+            i += 1;
+        }
+
+        let is_token = !(c.is_whitespace() || c == '/' && matches!(next, Some('/' | '*')));
+        if is_token {
+            tokens.push(Token {
+                text: &source[byte_at(start)..byte_at(i)],
+                position,
+            });
+        }
+        for &(_, c) in &chars[start..i.min(chars.len())] {
+            if c == '\n' {
+                position.line += 1;
+                position.column = 1;
+            } else {
+                position.column += 1;
+            }
+        }
+    }
+    tokens
+}
+
+/// Return the index just past a literal quoted by `quote` that starts at `i`, allowing for escapes.
+fn skip_quoted(chars: &[(usize, char)], i: usize, quote: char) -> usize {
+    let mut i = i + 1;
+    while let Some(&(_, c)) = chars.get(i) {
+        i += 1;
+        if c == '\\' {
+            i += 1;
+        } else if c == quote {
+            break;
+        }
+    }
+    i.min(chars.len())
+}
+
+/// Return the index just past a raw string literal whose hashes or opening quote start at `i`.
+fn skip_raw_string(chars: &[(usize, char)], i: usize) -> usize {
+    let mut i = i;
+    let mut hashes = 0;
+    while chars.get(i).is_some_and(|&(_, c)| c == '#') {
+        hashes += 1;
+        i += 1;
+    }
+    // Past the opening quote
+    i += 1;
+    while let Some(&(_, c)) = chars.get(i) {
+        i += 1;
+        if c == '"' && (0..hashes).all(|k| chars.get(i + k).is_some_and(|&(_, c)| c == '#')) {
+            return i + hashes;
+        }
+    }
+    chars.len()
+}
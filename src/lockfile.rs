@@ -0,0 +1,225 @@
+//!
+//! Per-script lockfiles for reproducible dependency resolution, invoked by the `--lock`
+//! and `--update-lock` options.
+//!
+//! The `Cargo.lock` resolved for a script's generated project is saved in a sidecar file
+//! `<stem>.thag.lock` next to the script. On later builds the sidecar is restored into the
+//! generated project and Cargo is run with `--locked`, so the script keeps building against
+//! the same dependency versions instead of the latest releases found at build time.
+//!
+use crate::{BuildState, ThagResult};
+use cargo_toml::{Dependency, DepsSet, Manifest};
+use semver::{Version, VersionReq};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use thag_common::{debug_log, V};
+use thag_profiler::profiled;
+use thag_styling::{svprtln, Role};
+
+/// Suffix of the sidecar lockfile, which replaces the `.rs` suffix of the script.
+pub const LOCK_SUFFIX: &str = "thag.lock";
+
+/// Name of the lockfile in the generated project.
+const CARGO_LOCK: &str = "Cargo.lock";
+
+/// The package versions recorded in a lockfile, keyed by package name.
+pub type LockedVersions = BTreeMap<String, Vec<Version>>;
+
+/// Return the path of the sidecar lockfile for the script at `source_path`.
+#[must_use]
+#[profiled]
+pub fn lock_path(source_path: &Path) -> PathBuf {
+    source_path.with_extension(LOCK_SUFFIX)
+}
+
+/// Read the package versions recorded in a `Cargo.lock`-format lockfile.
+///
+/// # Errors
+///
+/// Will return `Err` if the lockfile cannot be read or is not a valid `Cargo.lock` file.
+#[profiled]
+pub fn read_locked_versions(lock_path: &Path) -> ThagResult<LockedVersions> {
+    let content = fs::read_to_string(lock_path)?;
+    let lock: toml::Table = toml::from_str(&content)?;
+    let packages = lock
+        .get("package")
+        .and_then(toml::Value::as_array)
+        .ok_or_else(|| format!("No packages found in lockfile {}", lock_path.display()))?;
+
+    let mut locked_versions = LockedVersions::new();
+    for package in packages {
+        let (Some(name), Some(version)) = (
+            package.get("name").and_then(toml::Value::as_str),
+            package.get("version").and_then(toml::Value::as_str),
+        ) else {
+            return Err(format!(
+                "Malformed package entry in lockfile {}",
+                lock_path.display()
+            )
+            .into());
+        };
+        let version = Version::parse(version).map_err(|e| {
+            format!(
+                "Invalid version {version} for package {name} in lockfile {}: {e}",
+                lock_path.display()
+            )
+        })?;
+        locked_versions
+            .entry(name.to_string())
+            .or_default()
+            .push(version);
+    }
+    Ok(locked_versions)
+}
+
+/// Pin inferred dependencies to the versions recorded in the lockfile, so that inference
+/// does not request a newer release than the one locked.
+///
+/// Only dependencies named in `inferred_deps`, in either their underscored or hyphenated
+/// form, are affected. Their features are left as inferred.
+#[profiled]
+pub fn pin_inferred(
+    inferred_deps: &[String],
+    rs_dep_map: &mut BTreeMap<String, Dependency>,
+    locked_versions: &LockedVersions,
+) {
+    for dep_name in inferred_deps {
+        let hyphenated = dep_name.replace('_', "-");
+        let Some(name) = [dep_name, &hyphenated]
+            .into_iter()
+            .find(|name| rs_dep_map.contains_key(*name))
+        else {
+            continue;
+        };
+        let Some(version) = locked_versions
+            .get(name)
+            .and_then(|versions| versions.iter().max())
+        else {
+            continue;
+        };
+        // A caret requirement on the locked version keeps the lock valid for Cargo.
+        let Ok(req) = VersionReq::parse(&version.to_string()) else {
+            continue;
+        };
+        debug_log!("Pinning inferred dependency {name} to locked version {version}");
+        match rs_dep_map.get_mut(name) {
+            Some(Dependency::Simple(version_req)) => *version_req = req,
+            Some(Dependency::Detailed(detail)) => detail.version = Some(req),
+            Some(Dependency::Inherited(_)) | None => {}
+        }
+    }
+}
+
+/// Check that every dependency of the merged manifest is satisfied by a version in the lockfile.
+///
+/// # Errors
+///
+/// Will return `Err` listing each dependency that is missing from the lockfile or whose
+/// version requirement no locked version satisfies.
+#[profiled]
+pub fn check(
+    manifest: &Manifest,
+    locked_versions: &LockedVersions,
+    lock_path: &Path,
+) -> ThagResult<()> {
+    let dep_sets = [
+        &manifest.dependencies,
+        &manifest.dev_dependencies,
+        &manifest.build_dependencies,
+    ]
+    .into_iter()
+    .chain(manifest.target.values().flat_map(|target| {
+        [
+            &target.dependencies,
+            &target.dev_dependencies,
+            &target.build_dependencies,
+        ]
+    }));
+
+    let mut mismatches: Vec<String> = dep_sets
+        .flat_map(DepsSet::iter)
+        .filter_map(|(key, dependency)| mismatch(key, dependency, locked_versions))
+        .collect();
+
+    if mismatches.is_empty() {
+        return Ok(());
+    }
+    mismatches.sort();
+    mismatches.dedup();
+    Err(format!(
+        "The script's dependencies disagree with its lockfile {}:\n  {}\nRun with --update-lock to re-resolve the dependencies and update the lockfile.",
+        lock_path.display(),
+        mismatches.join("\n  ")
+    )
+    .into())
+}
+
+/// Describe how a single dependency disagrees with the lockfile, if it does.
+#[profiled]
+fn mismatch(
+    key: &str,
+    dependency: &Dependency,
+    locked_versions: &LockedVersions,
+) -> Option<String> {
+    let name = dependency.package().unwrap_or(key);
+    let Some(versions) = locked_versions.get(name) else {
+        return Some(format!("{name} is not in the lockfile"));
+    };
+    let version_req = dependency.req();
+    if *version_req == VersionReq::STAR {
+        return None;
+    }
+    if versions.iter().any(|version| version_req.matches(version)) {
+        return None;
+    }
+    let locked = versions
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    Some(format!(
+        "{name} = \"{version_req}\" does not match locked version {locked}"
+    ))
+}
+
+/// Restore the script's lockfile into the generated project.
+///
+/// # Errors
+///
+/// Will return `Err` if the lockfile cannot be copied.
+#[profiled]
+pub fn restore(lock_path: &Path, build_state: &BuildState) -> ThagResult<()> {
+    let cargo_lock = build_state.target_dir_path.join(CARGO_LOCK);
+    fs::copy(lock_path, &cargo_lock)?;
+    svprtln!(
+        Role::INFO,
+        V::V,
+        "Restored lockfile {} to {}",
+        lock_path.display(),
+        cargo_lock.display()
+    );
+    Ok(())
+}
+
+/// Save the generated project's `Cargo.lock` as the script's lockfile, if it has changed.
+///
+/// # Errors
+///
+/// Will return `Err` if the generated project has no `Cargo.lock`, or if it cannot be read
+/// or saved.
+#[profiled]
+pub fn save(lock_path: &Path, build_state: &BuildState) -> ThagResult<()> {
+    let cargo_lock = build_state.target_dir_path.join(CARGO_LOCK);
+    let content = fs::read_to_string(&cargo_lock)
+        .map_err(|e| format!("Could not read generated {}: {e}", cargo_lock.display()))?;
+    if fs::read_to_string(lock_path).is_ok_and(|existing| existing == content) {
+        debug_log!("Lockfile {} is unchanged", lock_path.display());
+        return Ok(());
+    }
+    fs::write(lock_path, content)?;
+    svprtln!(Role::INFO, V::N, "Saved lockfile {}", lock_path.display());
+    Ok(())
+}
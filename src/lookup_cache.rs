@@ -0,0 +1,117 @@
+//!
+//! Persistent cache of crate lookups for dependency inference.
+//!
+//! Each crate looked up by `manifest::cargo_lookup` or `get_crate_features` is recorded in a
+//! small TOML file named for the crate under `temp_dir`/`thag_rs_lookups`, holding the resolved
+//! name and version, whether that release is yanked or a pre-release, and the crate's features.
+//! Entries expire after the time to live specified by `lookup_cache_ttl_hours` in the
+//! `[dependencies]` section of the configuration, except in offline mode, where any cached
+//! entry is better than none. A TTL of 0 disables the cache.
+//!
+//! The cache is cleared by `thag --clean lookups` or `thag --clean all`.
+//!
+use crate::{maybe_config, offline, Dependencies, LOOKUP_CACHE_SUBDIR, TMPDIR};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use thag_common::debug_log;
+use thag_profiler::profiled;
+
+const SECONDS_PER_HOUR: u64 = 3600;
+
+/// The resolved information about a crate, as cached between runs.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CachedCrate {
+    /// The crate name as found in the index, which may be hyphenated
+    pub name: String,
+    /// The highest stable release, if any
+    pub version: Option<String>,
+    /// Whether the resolved release has been yanked
+    pub yanked: bool,
+    /// Whether the resolved release is a pre-release
+    pub prerelease: bool,
+    /// The crate's features, if any
+    pub features: Option<Vec<String>>,
+    /// When the crate was looked up, in seconds since the Unix epoch. Set by `put`.
+    #[serde(default)]
+    pub cached_at: u64,
+}
+
+/// Return the directory holding the crate lookup cache.
+#[must_use]
+#[profiled]
+pub fn cache_dir() -> PathBuf {
+    TMPDIR.join(LOOKUP_CACHE_SUBDIR)
+}
+
+#[profiled]
+fn cache_path(crate_name: &str) -> PathBuf {
+    cache_dir().join(format!("{crate_name}.toml"))
+}
+
+#[profiled]
+fn ttl_seconds() -> u64 {
+    let config = maybe_config();
+    let binding = Dependencies::default();
+    let dep_config = config.as_ref().map_or(&binding, |c| &c.dependencies);
+    dep_config
+        .lookup_cache_ttl_hours
+        .saturating_mul(SECONDS_PER_HOUR)
+}
+
+#[profiled]
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Return the cached lookup for a crate, provided the cache is enabled and the entry
+/// has not expired.
+#[must_use]
+#[profiled]
+pub fn get(crate_name: &str) -> Option<CachedCrate> {
+    let ttl = ttl_seconds();
+    if ttl == 0 {
+        return None;
+    }
+    let content = fs::read_to_string(cache_path(crate_name)).ok()?;
+    let cached: CachedCrate = match toml::from_str(&content) {
+        Ok(cached) => cached,
+        Err(e) => {
+            debug_log!("Ignoring unreadable lookup cache entry for {crate_name}: {e}");
+            return None;
+        }
+    };
+    if now().saturating_sub(cached.cached_at) > ttl && !offline::is_offline() {
+        debug_log!("Lookup cache entry for {crate_name} has expired");
+        return None;
+    }
+    debug_log!("Using cached lookup for {crate_name}: {cached:?}");
+    Some(cached)
+}
+
+/// Record the lookup for a crate, stamped with the current time, unless the cache is disabled.
+/// Failure to write the cache is not an error, since the lookup can simply be repeated next time.
+#[profiled]
+pub fn put(crate_name: &str, cached: &CachedCrate) {
+    if ttl_seconds() == 0 {
+        return;
+    }
+    let cached = CachedCrate {
+        cached_at: now(),
+        ..cached.clone()
+    };
+    let result = toml::to_string(&cached)
+        .map_err(|e| e.to_string())
+        .and_then(|content| {
+            fs::create_dir_all(cache_dir()).map_err(|e| e.to_string())?;
+            fs::write(cache_path(crate_name), content).map_err(|e| e.to_string())
+        });
+    if let Err(e) = result {
+        debug_log!("Failed to cache lookup for {crate_name}: {e}");
+    }
+}
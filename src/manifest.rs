@@ -1,30 +1,38 @@
 #![allow(clippy::uninlined_format_args)]
 use crate::{
     ast::{infer_deps_from_ast, infer_deps_from_source},
-    code_utils::get_source_path,
+    code_utils::{extract_frontmatter, get_source_path},
     config::DependencyInference,
-    maybe_config, Ast, BuildState, Dependencies, Style, ThagError, ThagResult,
+    events::{self, Event},
+    lockfile,
+    lookup_cache::{self, CachedCrate},
+    maybe_config, offline, Ast, BuildState, Cli, Dependencies, Style, ThagError, ThagResult,
+    RS_SUFFIX,
 };
-use cargo_lookup::{Package, Query, Release};
+use cargo_lookup::{error::Error as LookupError, Package, Query, Release};
 use cargo_toml::{Dependency, DependencyDetail, Edition, Manifest, Value};
 use regex::Regex;
 use semver::VersionReq;
+use serde::Deserialize;
 use serde_merge::omerge;
 use std::{
     collections::BTreeMap,
-    env,
+    env, fs,
     path::{Path, PathBuf},
     str::FromStr,
     time::Instant,
 };
 use syn::{parse_file, File};
-use thag_common::{debug_log, get_verbosity, re, vprtln, V};
+use thag_common::{debug_log, get_verbosity, re, vprtln, Verbosity, V};
 use thag_profiler::{end, profile, profiled};
 use thag_styling::{svprtln, Role, Styleable, StyledPrint};
 
 #[cfg(debug_assertions)]
 use crate::debug_timings;
 
+/// The package name given to a script's toml block that has a `[package]` section without one.
+const PLACEHOLDER_PACKAGE_NAME: &str = "thag-script-placeholder";
+
 #[allow(clippy::missing_panics_doc)]
 #[must_use]
 /// Looks up a crate's latest stable version using cargo-lookup.
@@ -45,56 +53,24 @@ pub fn cargo_lookup(dep_crate: &str) -> Option<(String, String)> {
     let crate_variants = vec![dep_crate.to_string(), dep_crate.replace('_', "-")];
 
     for crate_name in crate_variants {
-        let query: Query = match crate_name.parse() {
-            Ok(q) => q,
-            Err(e) => {
-                debug_log!("Failed to parse query for crate {}: {}", crate_name, e);
-                continue;
-            }
-        };
-
-        match query.package() {
-            Ok(package) => {
-                debug_log!(
-                    "Found package {} with {} releases",
-                    package.name(),
-                    package.releases().len()
-                );
-
-                // Log all available versions and their pre-release status
-                // #[cfg(debug_assertions)]
-                // for release in package.releases() {
-                //     debug_log!(
-                //         "Version {} {}",
-                //         release.vers,
-                //         if release.vers.pre.is_empty() {
-                //             "(stable)"
-                //         } else {
-                //             "(pre-release)"
-                //         }
-                //     );
-                // }
-
-                let release = highest_release(&package);
-
-                match release {
-                    Some(r) => {
-                        debug_log!("Selected stable version: {}", r.vers);
-                        let name = r.name.clone();
-                        let version = r.vers.to_string();
-
-                        // Check if either variant matches
-                        if name == dep_crate || name == dep_crate.replace('_', "-") {
-                            return Some((name, version));
-                        }
-                    }
-                    None => {
-                        debug_log!("No stable version found for {}", crate_name);
-                    }
+        match crate_info(&crate_name) {
+            Some(CachedCrate {
+                name,
+                version: Some(version),
+                ..
+            }) => {
+                debug_log!("Selected stable version: {}", version);
+
+                // Check if either variant matches
+                if name == dep_crate || name == dep_crate.replace('_', "-") {
+                    return Some((name, version));
                 }
             }
-            Err(e) => {
-                debug_log!("Failed to look up crate {}: {}", crate_name, e);
+            Some(_) => {
+                debug_log!("No stable version found for {}", crate_name);
+            }
+            None => {
+                debug_log!("Failed to look up crate {}", crate_name);
             }
         }
     }
@@ -102,6 +78,85 @@ pub fn cargo_lookup(dep_crate: &str) -> Option<(String, String)> {
     None
 }
 
+/// Resolve a crate's highest stable release and its features, from the lookup cache if
+/// possible, otherwise from the index, in which case the result is cached for next time.
+#[profiled]
+fn crate_info(crate_name: &str) -> Option<CachedCrate> {
+    if let Some(cached) = lookup_cache::get(crate_name) {
+        return Some(cached);
+    }
+
+    let package = find_package(crate_name)?;
+    debug_log!(
+        "Found package {} with {} releases",
+        package.name(),
+        package.releases().len()
+    );
+
+    let release = highest_release(&package);
+    let info = CachedCrate {
+        name: release.map_or_else(|| package.name().to_string(), |r| r.name.clone()),
+        version: release.map(|r| r.vers.to_string()),
+        yanked: release.is_some_and(|r| r.yanked),
+        prerelease: release.is_some_and(|r| !r.vers.pre.is_empty()),
+        features: latest_features(package),
+        cached_at: 0,
+    };
+    lookup_cache::put(crate_name, &info);
+    Some(info)
+}
+
+/// Look up a crate's package information in the crates.io index, or in the local Cargo
+/// registry index cache and any vendored directory when offline.
+///
+/// If the index cannot be reached but the crate is found locally, offline mode is switched on
+/// for the rest of the run. A crate that the index reports as not found is not looked up
+/// locally, since `cargo_lookup` routinely tries name variants that do not exist.
+#[profiled]
+fn find_package(crate_name: &str) -> Option<Package> {
+    if offline::is_offline() {
+        return offline::local_package(crate_name);
+    }
+
+    let query: Query = match crate_name.parse() {
+        Ok(q) => q,
+        Err(e) => {
+            debug_log!("Failed to parse query for crate {}: {}", crate_name, e);
+            return None;
+        }
+    };
+
+    match query.package() {
+        Ok(package) => Some(package),
+        Err(e) if !is_unreachable(&e) => {
+            debug_log!("Failed to look up crate {} online: {}", crate_name, e);
+            None
+        }
+        Err(e) => {
+            debug_log!("Failed to reach the index for crate {}: {}", crate_name, e);
+            let package = offline::local_package(crate_name)?;
+            svprtln!(
+                Role::WARN,
+                V::V,
+                "The crates.io index is unreachable: looking up crates in the local registry index instead"
+            );
+            offline::set_offline(true);
+            Some(package)
+        }
+    }
+}
+
+/// Whether a failed index lookup means that the index could not be reached, as opposed to
+/// the index answering, e.g. with a 404 for a crate that does not exist.
+#[must_use]
+pub fn is_unreachable(error: &LookupError) -> bool {
+    match error {
+        LookupError::Request(error) => matches!(**error, ureq::Error::Transport(_)),
+        LookupError::Io(_) => true,
+        _ => false,
+    }
+}
+
 /// Returns the highest non-yanked release for a package, matching how
 /// `cargo search` resolves versions.
 ///
@@ -165,7 +220,11 @@ gen_src_path={gen_src_path}",
         build_state.build_from_orig_source
     );
 
-    default(source_stem, &gen_src_path)
+    let mut cargo_manifest = default(source_stem, &gen_src_path)?;
+    if let (Some(edition), Some(package)) = (build_state.edition, cargo_manifest.package.as_mut()) {
+        package.edition = cargo_toml::Inheritable::Set(edition);
+    }
+    Ok(cargo_manifest)
 }
 
 /// Parse the default manifest from a string template.
@@ -177,7 +236,7 @@ pub fn default(source_stem: &str, gen_src_path: &str) -> ThagResult<Manifest> {
         r#"[package]
 name = "{}"
 version = "0.0.1"
-edition = "2021"
+edition = "{}"
 
 [dependencies]
 
@@ -191,7 +250,10 @@ edition = "2021"
 name = "{}"
 path = "{}"
 "#,
-        source_stem, source_stem, gen_src_path
+        source_stem,
+        default_edition(),
+        source_stem,
+        gen_src_path
     );
 
     // vprtln!(V::N, "cargo_manifest=\n{cargo_manifest}");
@@ -199,6 +261,38 @@ path = "{}"
     Ok(Manifest::from_str(&cargo_manifest)?)
 }
 
+/// Parse a Rust edition such as "2024".
+#[must_use]
+pub fn parse_edition(edition: &str) -> Option<Edition> {
+    match edition.trim() {
+        "2015" => Some(Edition::E2015),
+        "2018" => Some(Edition::E2018),
+        "2021" => Some(Edition::E2021),
+        "2024" => Some(Edition::E2024),
+        _ => None,
+    }
+}
+
+/// The edition for scripts whose toml block doesn't specify one: the configured `misc.edition`
+/// if any, otherwise 2021.
+#[must_use]
+#[profiled]
+pub fn default_edition() -> Edition {
+    maybe_config()
+        .and_then(|config| config.misc.edition.as_deref().and_then(parse_edition))
+        .unwrap_or(Edition::E2021)
+}
+
+/// The edition a manifest will build with, falling back to the default edition if it has no
+/// package or inherits its edition.
+#[must_use]
+pub fn edition(manifest: &Manifest) -> Edition {
+    match manifest.package.as_ref().map(|package| &package.edition) {
+        Some(cargo_toml::Inheritable::Set(edition)) => *edition,
+        _ => default_edition(),
+    }
+}
+
 /// Check if a dependency exists in a manifest, either in regular dependencies
 /// or in any target-specific dependencies.
 ///
@@ -225,6 +319,29 @@ fn dependency_exists_in_manifest(manifest: &Manifest, dep_name: &str) -> bool {
     false
 }
 
+/// Enable a feature of a dependency of the manifest, if it has that dependency and doesn't
+/// inherit it from a workspace.
+pub fn enable_feature(manifest: &mut Manifest, dep_name: &str, feature: &str) {
+    let Some(dependency) = manifest.dependencies.get_mut(dep_name) else {
+        return;
+    };
+    match dependency {
+        Dependency::Simple(vreq) => {
+            *dependency = Dependency::Detailed(Box::new(DependencyDetail {
+                version: Some(vreq.clone()),
+                features: vec![feature.to_string()],
+                ..Default::default()
+            }));
+        }
+        Dependency::Detailed(detail) => {
+            if !detail.features.iter().any(|existing| existing == feature) {
+                detail.features.push(feature.to_string());
+            }
+        }
+        Dependency::Inherited(_) => {}
+    }
+}
+
 /// Merge manifest data harvested from the source script and its optional embedded toml block
 /// into the default manifest.
 /// # Errors
@@ -259,7 +376,7 @@ pub fn merge(build_state: &mut BuildState, rs_source: &str) -> ThagResult<()> {
     // debug_log!("build_state.rs_manifest={0:#?}\n", build_state.rs_manifest);
 
     profile!(merge_manifest, time);
-    let merged_manifest = if let Some(ref mut rs_manifest) = build_state.rs_manifest {
+    let mut merged_manifest = if let Some(ref mut rs_manifest) = build_state.rs_manifest {
         if !rs_inferred_deps.is_empty() {
             // Filter out dependencies that already exist in rs_manifest (from toml block)
             // to avoid conflicts between target.dependencies and dependencies
@@ -275,11 +392,28 @@ pub fn merge(build_state: &mut BuildState, rs_source: &str) -> ThagResult<()> {
                     "rs_dep_map (before inferred) {:#?}",
                     rs_manifest.dependencies
                 );
+                let declared: Vec<String> = rs_manifest.dependencies.keys().cloned().collect();
                 lookup_deps(
                     &build_state.infer,
                     &filtered_deps,
                     &mut rs_manifest.dependencies,
                 );
+                if let Some(ref locked_versions) = build_state.locked_versions {
+                    lockfile::pin_inferred(
+                        &filtered_deps,
+                        &mut rs_manifest.dependencies,
+                        locked_versions,
+                    );
+                }
+                for (name, dep) in &rs_manifest.dependencies {
+                    if !declared.contains(name) {
+                        events::emit(&Event::DependencyInferred {
+                            name,
+                            version: &dep.req().to_string(),
+                            features: dep.req_features(),
+                        });
+                    }
+                }
             }
 
             #[cfg(debug_assertions)]
@@ -289,11 +423,27 @@ pub fn merge(build_state: &mut BuildState, rs_source: &str) -> ThagResult<()> {
             );
         }
 
+        // Keep the default package name unless the toml block specifies one
+        if let (Some(rs_package), Some(package)) = (
+            rs_manifest.package.as_mut(),
+            cargo_manifest.package.as_ref(),
+        ) {
+            if rs_package.name == PLACEHOLDER_PACKAGE_NAME {
+                rs_package.name.clone_from(&package.name);
+            }
+        }
+
         call_omerge(&cargo_manifest, rs_manifest)?
     } else {
         cargo_manifest
     };
 
+    // An edition specified with --edition overrides any in the script's toml block
+    if let (Some(edition), Some(package)) = (build_state.edition, merged_manifest.package.as_mut())
+    {
+        package.edition = cargo_toml::Inheritable::Set(edition);
+    }
+
     // Reassign the merged manifest back to build_state
     build_state.cargo_manifest = Some(merged_manifest);
     end!(merge_manifest);
@@ -349,13 +499,29 @@ pub fn extract(
     let maybe_rs_toml = extract_toml_block(rs_full_source);
 
     profile!(parse, mem_summary, time);
+    let mut has_edition = false;
     let mut rs_manifest = if let Some(rs_toml_str) = maybe_rs_toml {
         // cargo_toml v1 requires strict SemVer, so transform any
         // 'version = "X, thag-auto"' into 'version = "X", thag-auto = "X"'
         // before parsing.  The thag-auto field lands in
         // DependencyDetail::unstable where should_process_thag_auto() finds it.
-        let preprocessed = preprocess_thag_auto_toml(&rs_toml_str);
+        let mut preprocessed = preprocess_thag_auto_toml(&rs_toml_str);
         // debug_log!("rs_toml_str={rs_toml_str}");
+        if let Ok(mut table) = preprocessed.parse::<toml::Table>() {
+            if let Some(package) = table.get_mut("package").and_then(toml::Value::as_table_mut) {
+                // cargo_toml defaults a missing edition to 2015, so note whether it was specified.
+                has_edition = package.contains_key("edition");
+                // A [package] section for settings such as [package.metadata.thag] needn't
+                // name the package, but cargo_toml requires a name: `merge` replaces this one.
+                if !package.contains_key("name") {
+                    package.insert(
+                        "name".to_string(),
+                        toml::Value::String(PLACEHOLDER_PACKAGE_NAME.to_string()),
+                    );
+                    preprocessed = toml::to_string(&table)?;
+                }
+            }
+        }
         Manifest::from_str(&preprocessed)?
     } else {
         Manifest::from_str("")?
@@ -364,7 +530,9 @@ pub fn extract(
 
     profile!(set_edition, mem_summary, time);
     if let Some(package) = rs_manifest.package.as_mut() {
-        package.edition = cargo_toml::Inheritable::Set(Edition::E2021);
+        if !has_edition {
+            package.edition = cargo_toml::Inheritable::Set(default_edition());
+        }
     }
     end!(set_edition);
 
@@ -375,6 +543,115 @@ pub fn extract(
     Ok(rs_manifest)
 }
 
+/// Script-level `thag` settings from the `[package.metadata.thag]` section of a script's toml
+/// block, so that a script can carry its own defaults for options that would otherwise have to
+/// be given on the command line or in the configuration. Command-line options take precedence
+/// over these settings, which in turn take precedence over the configuration.
+///
+/// ```toml
+/// [package.metadata.thag]
+/// infer = "minimal"
+/// unquote = true
+/// multimain = false
+/// features = ["simd"]
+/// verbosity = "quiet"
+/// ```
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptSettings {
+    /// The level of dependency inference, as for `--infer`
+    pub infer: Option<DependencyInference>,
+    /// Whether to strip double quotes from a returned string, as for `--unquote`
+    pub unquote: Option<bool>,
+    /// Whether to allow multiple main methods, as for `--multimain`
+    pub multimain: Option<bool>,
+    /// The features to pass to Cargo, as for `--features`
+    pub features: Option<Vec<String>>,
+    /// The verbosity, as for `--verbose`, `--normal` or `--quiet`
+    pub verbosity: Option<Verbosity>,
+}
+
+impl ScriptSettings {
+    /// Apply these settings to the command-line arguments wherever the corresponding option was
+    /// not specified on the command line.
+    #[profiled]
+    pub fn apply(&self, args: &mut Cli) {
+        if args.infer.is_none() {
+            args.infer.clone_from(&self.infer);
+        }
+        if args.unquote.is_none() {
+            args.unquote = self.unquote;
+        }
+        if !args.multimain {
+            args.multimain = self.multimain.unwrap_or_default();
+        }
+        if args.features.is_none() {
+            args.features = self.features.as_ref().map(|features| features.join(","));
+        }
+        let verbosity_specified = args.verbose > 0 || args.quiet > 0 || args.normal_verbosity;
+        if let (false, Some(verbosity)) = (verbosity_specified, self.verbosity) {
+            match verbosity {
+                V::Quieter => args.quiet = 2,
+                V::Quiet => args.quiet = 1,
+                V::Normal => args.normal_verbosity = true,
+                V::Verbose => args.verbose = 1,
+                V::Debug => args.verbose = 2,
+            }
+        }
+    }
+}
+
+/// Return the script-level `thag` settings in the `[package.metadata.thag]` section of a
+/// manifest extracted from a script's toml block, if any.
+/// # Errors
+/// Will return `Err` if the section contains an unknown setting or an invalid value.
+#[profiled]
+pub fn script_settings(rs_manifest: &Manifest) -> ThagResult<Option<ScriptSettings>> {
+    let Some(thag_metadata) = rs_manifest
+        .package
+        .as_ref()
+        .and_then(|package| package.metadata.as_ref())
+        .and_then(|metadata| metadata.get("thag"))
+    else {
+        return Ok(None);
+    };
+    let settings = thag_metadata.clone().try_into().map_err(|e| {
+        ThagError::FromStr(format!("Invalid [package.metadata.thag] settings: {e}").into())
+    })?;
+    debug_log!("Script settings: {settings:?}");
+    Ok(Some(settings))
+}
+
+/// Apply any script-level settings from the toml block of the script or expression named in
+/// the command-line arguments, for options not specified on the command line.
+/// Returns `true` if any settings were found.
+///
+/// Input that is not known at this point, such as standard input, is not considered.
+/// # Errors
+/// Will return `Err` if the toml block cannot be parsed or the settings are invalid.
+#[profiled]
+pub fn apply_script_settings(args: &mut Cli) -> ThagResult<bool> {
+    let rs_source = if let Some(ref expr) = args.expression {
+        expr.clone()
+    } else if let Some(ref script) = args.script {
+        if !script.ends_with(RS_SUFFIX) {
+            return Ok(false);
+        }
+        // A missing script is reported later
+        let Ok(rs_source) = fs::read_to_string(script) else {
+            return Ok(false);
+        };
+        rs_source
+    } else {
+        return Ok(false);
+    };
+    let Some(settings) = script_settings(&extract(&rs_source, Instant::now())?)? else {
+        return Ok(false);
+    };
+    settings.apply(args);
+    Ok(true)
+}
+
 /// Processes thag-auto dependencies in the manifest, replacing them with appropriate
 /// dependency sources based on environment variables and context.
 ///
@@ -648,8 +925,13 @@ fn preprocess_thag_auto_toml(toml_str: &str) -> String {
     .into_owned()
 }
 
+/// Extract the manifest from a Cargo script frontmatter block at the start of the source, or
+/// failing that from a `/*[toml]` comment block.
 #[profiled]
 fn extract_toml_block(input: &str) -> Option<String> {
+    if let Some(frontmatter) = extract_frontmatter(input) {
+        return Some(frontmatter.to_string());
+    }
     let re: &Regex = re!(r"(?s)/\*\[toml\](.*?)\*/");
     re.captures(input)
         .and_then(|caps| caps.get(1).map(|m| m.as_str().to_string()))
@@ -698,36 +980,30 @@ fn clean_features(features: Vec<String>) -> Vec<String> {
 
 #[profiled]
 fn get_crate_features(name: &str) -> Option<Vec<String>> {
-    let query: Query = match name.parse() {
-        Ok(q) => q,
-        Err(e) => {
-            debug_log!("Failed to parse query for crate {}: {}", name, e);
-            return None;
-        }
+    let Some(info) = crate_info(name) else {
+        debug_log!("Failed to get features for crate {}", name);
+        return None;
     };
+    info.features
+}
 
-    match query.package() {
-        Ok(package) => {
-            let latest = package.into_latest()?;
+/// Collect the features of the most recently published release of a package.
+#[profiled]
+fn latest_features(package: Package) -> Option<Vec<String>> {
+    let latest = package.into_latest()?;
 
-            // Collect features from both fields
-            let mut all_features: Vec<String> = latest.features.keys().cloned().collect();
+    // Collect features from both fields
+    let mut all_features: Vec<String> = latest.features.keys().cloned().collect();
 
-            // Add features2 if present
-            if let Some(features2) = latest.features2 {
-                all_features.extend(features2.keys().cloned());
-            }
+    // Add features2 if present
+    if let Some(features2) = latest.features2 {
+        all_features.extend(features2.keys().cloned());
+    }
 
-            if all_features.is_empty() {
-                None
-            } else {
-                Some(clean_features(all_features))
-            }
-        }
-        Err(e) => {
-            debug_log!("Failed to get features for crate {}: {}", name, e);
-            None
-        }
+    if all_features.is_empty() {
+        None
+    } else {
+        Some(clean_features(all_features))
     }
 }
 
@@ -0,0 +1,343 @@
+//!
+//! Module resolution for multi-file scripts.
+//!
+//! A script may declare file modules (`mod foo;`), which Rust looks for relative to the script:
+//! `foo.rs` or `foo/mod.rs`, or the file named by a `#[path = "..."]` attribute. Modules may
+//! declare further modules in turn. `find_modules` resolves the whole tree the way `rustc`
+//! does, so that the module files can be:
+//!
+//! - mirrored into the generated project when the script is wrapped rather than built in place,
+//! - included in the staleness check of the cached executable, and
+//! - scanned for imports during dependency inference.
+//!
+use crate::ast::{find_crates, find_metadata, find_modules_source, CratesFinder, MetadataFinder};
+use crate::code_utils::{extract_ast_expr, read_file_contents, strip_frontmatter};
+use crate::{Ast, ThagResult};
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+use syn::{Expr, Item, ItemMod, Stmt};
+use thag_common::{debug_log, V};
+use thag_profiler::profiled;
+use thag_styling::{svprtln, Role};
+
+/// A module file of a multi-file script.
+#[derive(Clone, Debug)]
+pub struct ScriptModule {
+    /// The path of the module file
+    pub path: PathBuf,
+    /// The path of the module file relative to the script's directory, or `None` if it
+    /// lies outside that directory and cannot be mirrored into the generated project
+    pub relative_path: Option<PathBuf>,
+    /// Whether the module was reached through an absolute `#[path]`, on its own declaration or
+    /// that of a module above it, so that the generated project finds it without a copy
+    pub absolute: bool,
+}
+
+/// The directories against which the module declarations of a source file are resolved.
+#[derive(Clone, Debug)]
+pub(crate) struct ModuleDirs {
+    /// Where `mod foo;` looks for `foo.rs` and `foo/mod.rs`
+    module_dir: PathBuf,
+    /// What a `#[path]` attribute is relative to
+    path_attr_dir: PathBuf,
+    /// Whether these directories were reached through an absolute `#[path]`
+    absolute: bool,
+}
+
+impl ModuleDirs {
+    /// The directories for the declarations of a script or crate root in `dir`.
+    pub(crate) fn root(dir: &Path) -> Self {
+        Self {
+            module_dir: dir.to_path_buf(),
+            path_attr_dir: dir.to_path_buf(),
+            absolute: false,
+        }
+    }
+}
+
+/// A file module declaration, `mod foo;`, and the file it resolves to, which may not exist.
+pub(crate) struct ModuleDecl<'a> {
+    /// The declaration
+    pub(crate) item: &'a ItemMod,
+    /// The module file
+    pub(crate) path: PathBuf,
+    /// The directories against which the declarations in the module file are resolved
+    pub(crate) dirs: ModuleDirs,
+}
+
+/// Resolve the file modules declared by the script at `source_path`, recursively.
+///
+/// Declarations whose files cannot be found are left for the compiler to report.
+#[must_use]
+#[profiled]
+pub fn find_modules(source_path: &Path) -> Vec<ScriptModule> {
+    let Ok(source) = read_file_contents(source_path) else {
+        return vec![];
+    };
+    // Cheap check before parsing
+    if find_modules_source(&source).is_empty() {
+        return vec![];
+    }
+    let Some(script_dir) = source_path.parent() else {
+        return vec![];
+    };
+
+    let source = strip_frontmatter(&source);
+    let items = if let Ok(file) = syn::parse_file(&source) {
+        file.items
+    } else if let Ok(Expr::Block(expr_block)) = extract_ast_expr(&source) {
+        block_items(expr_block.block.stmts)
+    } else {
+        return vec![];
+    };
+
+    let mut modules = vec![];
+    resolve_items(
+        &items,
+        &ModuleDirs::root(script_dir),
+        script_dir,
+        &mut modules,
+    );
+    debug_log!("Script modules: {modules:#?}");
+    modules
+}
+
+#[profiled]
+fn block_items(stmts: Vec<Stmt>) -> Vec<Item> {
+    stmts
+        .into_iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Item(item) => Some(item),
+            _ => None,
+        })
+        .collect()
+}
+
+// Recursive - do not profile
+fn resolve_items(
+    items: &[Item],
+    dirs: &ModuleDirs,
+    script_dir: &Path,
+    modules: &mut Vec<ScriptModule>,
+) {
+    let mut decls = vec![];
+    module_decls(items, dirs, &mut decls);
+    for decl in decls {
+        let path = decl.path;
+        if !path.exists() {
+            debug_log!("Module file {} not found", path.display());
+            continue;
+        }
+        if modules.iter().any(|module| module.path == path) {
+            continue;
+        }
+
+        let relative_path = relative_to(&path, script_dir);
+        modules.push(ScriptModule {
+            path: path.clone(),
+            relative_path,
+            absolute: decl.dirs.absolute,
+        });
+
+        let Ok(file) = read_file_contents(&path).map(|source| syn::parse_file(&source)) else {
+            continue;
+        };
+        let Ok(file) = file else {
+            debug_log!("Could not parse module file {}", path.display());
+            continue;
+        };
+        resolve_items(&file.items, &decl.dirs, script_dir, modules);
+    }
+}
+
+/// Resolve the file module declarations among `items`, including those nested in inline
+/// modules, the way `rustc` does, without reading the module files.
+// Recursive - do not profile
+pub(crate) fn module_decls<'a>(
+    items: &'a [Item],
+    dirs: &ModuleDirs,
+    decls: &mut Vec<ModuleDecl<'a>>,
+) {
+    for item in items {
+        let Item::Mod(item_mod) = item else {
+            continue;
+        };
+        let name = item_mod.ident.to_string();
+        let name = name.trim_start_matches("r#");
+        let path_attr = path_attribute(item_mod);
+        let absolute = dirs.absolute || path_attr.as_deref().is_some_and(is_absolute);
+
+        if let Some((_, ref content)) = item_mod.content {
+            // Inline module: its file modules live in a subdirectory named for it.
+            let inline_dir = dirs.module_dir.join(path_attr.as_deref().unwrap_or(name));
+            let inline_dirs = ModuleDirs {
+                module_dir: inline_dir.clone(),
+                path_attr_dir: inline_dir,
+                absolute,
+            };
+            module_decls(content, &inline_dirs, decls);
+            continue;
+        }
+
+        // A file loaded via `#[path]`, or named `mod.rs`, owns its own directory. Any other
+        // module file owns the subdirectory named for the module.
+        let (path, child_module_dir) = if let Some(path_attr) = path_attr {
+            let path = dirs.path_attr_dir.join(path_attr);
+            let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+            (path, dir)
+        } else {
+            let flat = dirs.module_dir.join(format!("{name}.rs"));
+            let nested = dirs.module_dir.join(name).join("mod.rs");
+            if flat.exists() {
+                (flat, dirs.module_dir.join(name))
+            } else {
+                (nested, dirs.module_dir.join(name))
+            }
+        };
+        let child_dirs = ModuleDirs {
+            path_attr_dir: path.parent().map(Path::to_path_buf).unwrap_or_default(),
+            module_dir: child_module_dir,
+            absolute,
+        };
+        decls.push(ModuleDecl {
+            item: item_mod,
+            path,
+            dirs: child_dirs,
+        });
+    }
+}
+
+/// Return the value of any `#[path = "..."]` attribute of a module declaration.
+#[profiled]
+pub(crate) fn path_attribute(item_mod: &ItemMod) -> Option<String> {
+    item_mod.attrs.iter().find_map(|attr| {
+        let syn::Meta::NameValue(name_value) = &attr.meta else {
+            return None;
+        };
+        if !name_value.path.is_ident("path") {
+            return None;
+        }
+        match &name_value.value {
+            Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Str(lit_str),
+                ..
+            }) => Some(lit_str.value()),
+            _ => None,
+        }
+    })
+}
+
+/// Return whether a `#[path]` attribute value is an absolute path.
+fn is_absolute(path_attr: &str) -> bool {
+    Path::new(path_attr).is_absolute()
+}
+
+/// Express `path` relative to `base` without leaving it, normalising any `.` and `..`
+/// components along the way.
+#[profiled]
+fn relative_to(path: &Path, base: &Path) -> Option<PathBuf> {
+    if path.is_absolute() && !path.starts_with(base) {
+        return None;
+    }
+    let relative = path.strip_prefix(base).unwrap_or(path);
+    let mut normalised = PathBuf::new();
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => normalised.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalised.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(normalised)
+}
+
+/// Add the crates referenced by the script's module files to those found in the script
+/// itself, and exclude the names of the modules they declare.
+#[profiled]
+pub fn infer_module_crates(
+    modules: &[ScriptModule],
+    crates_finder: &mut CratesFinder,
+    metadata_finder: &mut MetadataFinder,
+) {
+    for module in modules {
+        let Ok(source) = read_file_contents(&module.path) else {
+            continue;
+        };
+        let Ok(file) = syn::parse_file(&source) else {
+            continue;
+        };
+        let ast = Ast::File(file);
+        let module_crates = find_crates(&ast);
+        let module_metadata = find_metadata(&ast);
+        debug_log!(
+            "Crates found in module {}: {:?}",
+            module.path.display(),
+            module_crates.crates
+        );
+
+        for name in module_crates.crates {
+            if !crates_finder.crates.contains(&name) {
+                crates_finder.crates.push(name);
+            }
+        }
+        crates_finder
+            .names_to_exclude
+            .extend(module_crates.names_to_exclude);
+        metadata_finder
+            .extern_crates
+            .extend(module_metadata.extern_crates);
+        metadata_finder
+            .mods_to_exclude
+            .extend(module_metadata.mods_to_exclude);
+        metadata_finder
+            .names_to_exclude
+            .extend(module_metadata.names_to_exclude);
+    }
+}
+
+/// Copy the script's module files into the generated project, preserving their layout
+/// relative to the script, so that the generated source finds them where the script would.
+/// Modules reached through an absolute `#[path]` are found from anywhere, so are not copied.
+///
+/// # Errors
+///
+/// Will return `Err` if a module file cannot be copied.
+#[profiled]
+pub fn mirror_modules(modules: &[ScriptModule], target_dir_path: &Path) -> ThagResult<()> {
+    for module in modules {
+        if module.absolute {
+            debug_log!(
+                "Module {} has an absolute path, so needs no copy",
+                module.path.display()
+            );
+            continue;
+        }
+        let Some(ref relative_path) = module.relative_path else {
+            svprtln!(
+                Role::WARN,
+                V::QQ,
+                "Module file {} is outside the script's directory and can't be copied to the generated project. Use an absolute #[path] to refer to it.",
+                module.path.display()
+            );
+            continue;
+        };
+        let mirror_path = target_dir_path.join(relative_path);
+        if let Some(parent) = mirror_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&module.path, &mirror_path)?;
+        debug_log!(
+            "Copied module {} to {}",
+            module.path.display(),
+            mirror_path.display()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,179 @@
+//!
+//! Offline dependency lookup for `thag_rs`, invoked by the `--offline` option or automatically
+//! when the crates.io index is unreachable.
+//!
+//! Crate releases and features are looked up in any vendored directory specified in the
+//! `[dependencies]` section of the configuration, then in the index caches that Cargo keeps
+//! under `$CARGO_HOME/registry/index` for both sparse and git registries. The results are
+//! returned as `cargo_lookup` packages so that the online and offline paths share their
+//! version and feature selection.
+//!
+//! Once offline mode is in effect, `--offline` is also passed to Cargo.
+//!
+use crate::{get_home_dir, maybe_config};
+use cargo_lookup::{get_index_path, Package};
+use cargo_toml::Manifest;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+use thag_common::debug_log;
+use thag_profiler::profiled;
+
+/// Whether crate lookups and Cargo should avoid the network for the rest of this run.
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// The cache format version written by current versions of Cargo, which is followed by
+/// a 4-byte index format version.
+const INDEX_CACHE_VERSION: u8 = 3;
+
+/// Put `thag` into or out of offline mode for the rest of this run.
+#[profiled]
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+/// Return whether `thag` is in offline mode, either because it was requested or set after
+/// a failed network lookup, or because Cargo itself has been configured via the
+/// `CARGO_NET_OFFLINE` environment variable not to use the network.
+#[must_use]
+#[profiled]
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+        || env::var("CARGO_NET_OFFLINE").is_ok_and(|value| value == "true")
+}
+
+/// Look up a crate without using the network: first in the configured vendored directory,
+/// if any, then in the local Cargo registry index caches.
+#[must_use]
+#[profiled]
+pub fn local_package(crate_name: &str) -> Option<Package> {
+    let vendored = maybe_config()
+        .and_then(|config| config.dependencies.vendor_dir)
+        .and_then(|vendor_dir| vendored_package(Path::new(&vendor_dir), crate_name));
+    if vendored.is_some() {
+        return vendored;
+    }
+
+    let cargo_home = cargo_home()?;
+    registry_package(&cargo_home, crate_name)
+}
+
+#[profiled]
+fn cargo_home() -> Option<PathBuf> {
+    match env::var("CARGO_HOME") {
+        Ok(string) if !string.is_empty() => Some(PathBuf::from(string)),
+        _ => get_home_dir().ok().map(|home_dir| home_dir.join(".cargo")),
+    }
+}
+
+/// Look up a crate in the index caches of the registries under `cargo_home`, preferring
+/// the crates.io sparse index over any git index.
+#[profiled]
+fn registry_package(cargo_home: &Path, crate_name: &str) -> Option<Package> {
+    let Ok(entries) = fs::read_dir(cargo_home.join("registry").join("index")) else {
+        debug_log!("No local registry index under {}", cargo_home.display());
+        return None;
+    };
+    let mut index_dirs: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect();
+    index_dirs.sort_by_key(|path| {
+        !path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with("index.crates.io-"))
+    });
+
+    let index_path = get_index_path(crate_name);
+    index_dirs.iter().find_map(|index_dir| {
+        let cache_path = index_dir.join(".cache").join(&index_path);
+        let bytes = fs::read(&cache_path).ok()?;
+        let index = parse_index_cache(&bytes)?;
+        debug_log!("Found {crate_name} in {}", cache_path.display());
+        Package::from_index(index).ok()
+    })
+}
+
+/// Convert the contents of a Cargo registry index cache file into the newline-delimited JSON
+/// format of the index itself.
+///
+/// A cache file consists of a one-byte cache version, a four-byte index format version
+/// (cache version 3 onwards), then NUL-terminated strings: the index revision followed by
+/// pairs of release version and release JSON.
+#[must_use]
+#[profiled]
+pub fn parse_index_cache(bytes: &[u8]) -> Option<String> {
+    let (&cache_version, rest) = bytes.split_first()?;
+    let rest = if cache_version >= INDEX_CACHE_VERSION {
+        rest.get(4..)?
+    } else {
+        rest
+    };
+
+    let mut strings = rest.split(|&byte| byte == 0);
+    let _revision = strings.next()?;
+    let releases: Vec<&str> = strings
+        .collect::<Vec<_>>()
+        .chunks_exact(2)
+        .filter_map(|pair| std::str::from_utf8(pair[1]).ok())
+        .collect();
+
+    if releases.is_empty() {
+        None
+    } else {
+        Some(releases.join("\n"))
+    }
+}
+
+/// Look up a crate in a directory populated by `cargo vendor`, where each crate is in a
+/// subdirectory named either for the crate or for the crate and its version.
+#[profiled]
+fn vendored_package(vendor_dir: &Path, crate_name: &str) -> Option<Package> {
+    let hyphenated = crate_name.replace('_', "-");
+    let index: Vec<String> = fs::read_dir(vendor_dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let dir_name = entry.file_name().to_string_lossy().to_string();
+            [crate_name, hyphenated.as_str()].iter().any(|name| {
+                dir_name == *name
+                    || dir_name
+                        .strip_prefix(&format!("{name}-"))
+                        .is_some_and(|version| version.starts_with(|c: char| c.is_ascii_digit()))
+            })
+        })
+        .filter_map(|entry| vendored_release(&entry.path().join("Cargo.toml")))
+        .collect();
+
+    if index.is_empty() {
+        None
+    } else {
+        debug_log!(
+            "Found {crate_name} in vendored directory {}",
+            vendor_dir.display()
+        );
+        Package::from_index(index.join("\n")).ok()
+    }
+}
+
+/// Describe a vendored crate's manifest as a registry index entry.
+#[profiled]
+fn vendored_release(cargo_toml_path: &Path) -> Option<String> {
+    let content = fs::read_to_string(cargo_toml_path).ok()?;
+    let manifest = Manifest::from_str(&content).ok()?;
+    let package = manifest.package.as_ref()?;
+    let version = package.version.get().ok()?;
+
+    let record = serde_json::json!({
+        "name": package.name,
+        "vers": version.to_string(),
+        "deps": [],
+        "cksum": "",
+        "features": manifest.features,
+        "yanked": false,
+    });
+    serde_json::to_string(&record).ok()
+}
@@ -0,0 +1,289 @@
+//!
+//! Embeddable API for running scripts and expressions from other programs, without going through
+//! the `thag` command line.
+//!
+//! A `Runner` is configured builder-style with a script path or an expression and any of the
+//! options that `thag` would take from its command line, and then `run` generates, builds and runs
+//! it with the same `BuildState::pre_configure`, `gen_build` and `run_script` functions as `thag`,
+//! including the staleness checks that skip unnecessary generation and build steps.
+//!
+//! `run` returns a `RunOutput` with the exit status of the script, its captured output if
+//! requested, the paths of the generated project and executable, and timings.
+//!
+//! ```ignore
+//! use thag_rs::Runner;
+//!
+//! let output = Runner::new()
+//!     .script("demo/hello.rs")
+//!     .capture_output(true)
+//!     .run()?;
+//! assert!(output.success());
+//! println!("{}", output.stdout);
+//! ```
+//!
+//! `thag`'s own progress messages are governed by the global verbosity as usual, so a caller that
+//! wants silence can call `thag_common::set_global_verbosity(V::Quieter)` beforehand. Cargo's
+//! build output is not captured.
+//!
+use crate::builder::{
+    gen_build, resolve_script_dir_path, run_script, set_script_state, BuildState,
+};
+use crate::code_utils::{create_temp_source_file, write_source};
+use crate::config::DependencyInference;
+use crate::manifest::{self, extract, parse_edition};
+use crate::{get_proc_flags, offline, validate_args, Cli, ThagResult};
+use std::{
+    path::PathBuf,
+    process::ExitStatus,
+    time::{Duration, Instant},
+};
+use thag_common::debug_log;
+use thag_profiler::profiled;
+use thag_styling::TermAttributes;
+
+/// What a `Runner` is to run.
+#[derive(Clone, Debug)]
+enum Source {
+    Script(PathBuf),
+    Expression(String),
+}
+
+/// A builder for generating, building and running a script or expression.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Debug, Default)]
+pub struct Runner {
+    source: Option<Source>,
+    args: Vec<String>,
+    features: Option<String>,
+    infer: Option<DependencyInference>,
+    force: bool,
+    release: bool,
+    profile: Option<String>,
+    edition: Option<String>,
+    offline: bool,
+    capture_output: bool,
+}
+
+/// The result of a `Runner::run`.
+#[derive(Clone, Debug)]
+pub struct RunOutput {
+    /// The exit status of the script
+    pub status: ExitStatus,
+    /// The standard output of the script if captured, otherwise empty
+    pub stdout: String,
+    /// The standard error of the script if captured, otherwise empty
+    pub stderr: String,
+    /// The path of the script source, which for an expression is a temporary file
+    pub source_path: PathBuf,
+    /// The directory of the generated Cargo project
+    pub target_dir_path: PathBuf,
+    /// The path of the generated Cargo.toml
+    pub cargo_toml_path: PathBuf,
+    /// The path of the cached executable
+    pub executable_path: PathBuf,
+    /// Whether the Cargo project was generated, as opposed to being up to date
+    pub generated: bool,
+    /// Whether the script was built, as opposed to its executable being up to date
+    pub built: bool,
+    /// The time taken to generate and build the script, including any skipped steps
+    pub build_time: Duration,
+    /// The time taken to run the script
+    pub run_time: Duration,
+}
+
+impl RunOutput {
+    /// Whether the script exited successfully.
+    #[must_use]
+    pub fn success(&self) -> bool {
+        self.status.success()
+    }
+
+    /// The exit code of the script, if it was not terminated by a signal.
+    #[must_use]
+    pub fn exit_code(&self) -> Option<i32> {
+        self.status.code()
+    }
+}
+
+impl Runner {
+    /// Create a new `Runner` with default options. A script or expression must be specified
+    /// before calling `run`.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run the script at this path, which must end in `.rs`.
+    #[must_use]
+    pub fn script(mut self, path: impl Into<PathBuf>) -> Self {
+        self.source = Some(Source::Script(path.into()));
+        self
+    }
+
+    /// Evaluate this expression or snippet, as with `thag --expr`.
+    #[must_use]
+    pub fn expression(mut self, expr: impl Into<String>) -> Self {
+        self.source = Some(Source::Expression(expr.into()));
+        self
+    }
+
+    /// Add an argument to pass to the script.
+    #[must_use]
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Add arguments to pass to the script.
+    #[must_use]
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Features to pass to Cargo, as a comma-separated list, as with `thag --features`.
+    #[must_use]
+    pub fn features(mut self, features: impl Into<String>) -> Self {
+        self.features = Some(features.into());
+        self
+    }
+
+    /// The level of dependency inference, as with `thag --infer`.
+    #[must_use]
+    pub const fn infer(mut self, infer: DependencyInference) -> Self {
+        self.infer = Some(infer);
+        self
+    }
+
+    /// Whether to generate and build even if the script is unchanged, as with `thag --force`.
+    #[must_use]
+    pub const fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Whether to build with the release profile, as with `thag --release`.
+    #[must_use]
+    pub const fn release(mut self, release: bool) -> Self {
+        self.release = release;
+        self
+    }
+
+    /// The Cargo profile to build with, as with `thag --profile`. Takes precedence over `release`.
+    #[must_use]
+    pub fn profile(mut self, profile: impl Into<String>) -> Self {
+        self.profile = Some(profile.into());
+        self
+    }
+
+    /// The Rust edition to build with, such as "2024", as with `thag --edition`.
+    #[must_use]
+    pub fn edition(mut self, edition: impl Into<String>) -> Self {
+        self.edition = Some(edition.into());
+        self
+    }
+
+    /// Whether to avoid the network for crate lookups and Cargo, as with `thag --offline`.
+    /// Once on, offline mode stays on for the rest of the process.
+    #[must_use]
+    pub const fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Whether to capture the standard output and standard error of the script in the
+    /// `RunOutput` rather than letting it inherit them from the caller.
+    #[must_use]
+    pub const fn capture_output(mut self, capture_output: bool) -> Self {
+        self.capture_output = capture_output;
+        self
+    }
+
+    /// The equivalent `thag` command line for the options specified.
+    fn to_cli(&self) -> Cli {
+        let (script, expression) = match self.source {
+            Some(Source::Script(ref path)) => (Some(path.display().to_string()), None),
+            Some(Source::Expression(ref expr)) => (None, Some(expr.clone())),
+            None => (None, None),
+        };
+        Cli {
+            script,
+            expression,
+            args: self.args.clone(),
+            features: self.features.clone(),
+            infer: self.infer.clone(),
+            force: self.force,
+            release: self.release && self.profile.is_none(),
+            profile: self.profile.clone(),
+            edition: self.edition.clone(),
+            offline: self.offline,
+            ..Cli::default()
+        }
+    }
+
+    /// Generate, build and run the script or expression.
+    ///
+    /// An unsuccessful exit of the script is reported in the `RunOutput` rather than as an error.
+    /// # Errors
+    ///
+    /// Will return `Err` if no script or expression was specified, if an option is invalid,
+    /// or if there is an error generating or building the script or spawning its executable.
+    #[profiled]
+    pub fn run(&self) -> ThagResult<RunOutput> {
+        TermAttributes::get_or_init();
+
+        let mut cli = self.to_cli();
+        if let Some(ref edition) = cli.edition {
+            if parse_edition(edition).is_none() {
+                return Err(format!("Unsupported Rust edition {edition}").into());
+            }
+        }
+        // Script-level settings fill in options not specified here
+        manifest::apply_script_settings(&mut cli)?;
+        let proc_flags = get_proc_flags(&cli)?;
+        validate_args(&cli, &proc_flags)?;
+        if self.offline {
+            offline::set_offline(true);
+        }
+
+        let is_dynamic = cli.expression.is_some();
+        if is_dynamic {
+            let _ = create_temp_source_file()?;
+        }
+        let script_dir_path = resolve_script_dir_path(false, &cli, None, is_dynamic)?;
+        let script_state = set_script_state(&cli, script_dir_path, false, None, is_dynamic)?;
+        let mut build_state = BuildState::pre_configure(&proc_flags, &cli, &script_state)?;
+
+        if let Some(rs_source) = cli.expression.take() {
+            build_state.rs_manifest = Some(extract(&rs_source, Instant::now())?);
+            write_source(&build_state.source_path, &rs_source)?;
+        }
+
+        let start_build = Instant::now();
+        gen_build(&cli, &proc_flags, &mut build_state)?;
+        let build_time = start_build.elapsed();
+
+        let start_run = Instant::now();
+        let output = run_script(&proc_flags, &cli.args, &build_state, self.capture_output)?;
+        let run_time = start_run.elapsed();
+        debug_log!("Runner: script exited with {:?}", output.status);
+
+        Ok(RunOutput {
+            status: output.status,
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            source_path: build_state.source_path,
+            target_dir_path: build_state.target_dir_path,
+            cargo_toml_path: build_state.cargo_toml_path,
+            executable_path: build_state.target_path,
+            generated: build_state.generated,
+            built: build_state.built,
+            build_time,
+            run_time,
+        })
+    }
+}
@@ -0,0 +1,221 @@
+//!
+//! Creation of new scripts from templates, invoked by the `--new` option.
+//!
+//! `thag --new <name> --template <kind>` writes `<name>.rs` from the template of that kind, with
+//! the toml block, doc-comment header and any boilerplate that the kind of script needs. The
+//! built-in templates are `plain` (the default), `clap`, `tokio`, `ratatui`, `profiled` and
+//! `styled`.
+//!
+//! Users can add their own templates as `<kind>.rs` files in the `templates_dir` configured in the
+//! `[misc]` section, where a template takes precedence over a built-in one of the same kind.
+//!
+//! The placeholders `{{name}}`, `{{author}}` and `{{date}}` in a template are replaced by the
+//! script's file stem, the configured author or else the git `user.name`, and today's date.
+//!
+use crate::{maybe_config, Cli, ThagResult, RS_SUFFIX};
+use chrono::Local;
+use std::{
+    borrow::Cow,
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use thag_common::{debug_log, vprtln, V};
+use thag_profiler::profiled;
+
+/// The template used when `--template` is not specified.
+pub const DEFAULT_TEMPLATE: &str = "plain";
+
+/// The built-in templates by kind.
+const BUILT_IN_TEMPLATES: &[(&str, &str)] = &[
+    ("plain", include_str!("../assets/templates/plain.rs")),
+    ("clap", include_str!("../assets/templates/clap.rs")),
+    ("tokio", include_str!("../assets/templates/tokio.rs")),
+    ("ratatui", include_str!("../assets/templates/ratatui.rs")),
+    ("profiled", include_str!("../assets/templates/profiled.rs")),
+    ("styled", include_str!("../assets/templates/styled.rs")),
+];
+
+/// The values substituted for the placeholders in a template.
+#[derive(Clone, Debug)]
+pub struct Placeholders {
+    /// The file stem of the new script, for `{{name}}`
+    pub name: String,
+    /// The author of the new script, for `{{author}}`
+    pub author: String,
+    /// The creation date of the new script, for `{{date}}`
+    pub date: String,
+}
+
+/// The directory of user templates configured in the `[misc]` section, if any.
+#[must_use]
+pub fn user_templates_dir() -> Option<PathBuf> {
+    maybe_config()
+        .and_then(|config| config.misc.templates_dir)
+        .map(PathBuf::from)
+}
+
+/// The kinds of template available, built-in and from `user_dir`, in name order.
+///
+/// # Errors
+///
+/// Will return `Err` if `user_dir` exists but cannot be read.
+#[profiled]
+pub fn available(user_dir: Option<&Path>) -> ThagResult<Vec<String>> {
+    let mut kinds: Vec<String> = BUILT_IN_TEMPLATES
+        .iter()
+        .map(|(kind, _)| (*kind).to_string())
+        .collect();
+    if let Some(user_dir) = user_dir.filter(|dir| dir.is_dir()) {
+        for entry in fs::read_dir(user_dir)? {
+            let path = entry?.path();
+            if let Some(kind) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(RS_SUFFIX))
+            {
+                kinds.push(kind.to_string());
+            }
+        }
+    }
+    kinds.sort();
+    kinds.dedup();
+    Ok(kinds)
+}
+
+/// Load the template of this kind, from `user_dir` if it has one, otherwise from the built-in
+/// templates.
+///
+/// # Errors
+///
+/// Will return `Err` if there is no template of this kind, or if the user template cannot be read.
+#[profiled]
+pub fn load(kind: &str, user_dir: Option<&Path>) -> ThagResult<Cow<'static, str>> {
+    if let Some(path) = user_dir.map(|dir| dir.join(format!("{kind}{RS_SUFFIX}"))) {
+        if path.is_file() {
+            debug_log!("Using user template {}", path.display());
+            return Ok(Cow::Owned(fs::read_to_string(path)?));
+        }
+    }
+    BUILT_IN_TEMPLATES
+        .iter()
+        .find(|(built_in, _)| *built_in == kind)
+        .map(|(_, template)| Cow::Borrowed(*template))
+        .ok_or_else(|| {
+            let kinds = available(user_dir).unwrap_or_default().join(", ");
+            format!("Unknown template `{kind}`. Available templates: {kinds}").into()
+        })
+}
+
+/// Replace the placeholders in a template.
+#[must_use]
+pub fn render(template: &str, placeholders: &Placeholders) -> String {
+    template
+        .replace("{{name}}", &placeholders.name)
+        .replace("{{author}}", &placeholders.author)
+        .replace("{{date}}", &placeholders.date)
+}
+
+/// The author of new scripts: the one configured in the `[misc]` section, or else the git
+/// `user.name`, or else the login name.
+#[must_use]
+#[profiled]
+pub fn default_author() -> String {
+    if let Some(author) = maybe_config().and_then(|config| config.misc.author) {
+        return author;
+    }
+    Command::new("git")
+        .args(["config", "user.name"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|name| !name.is_empty())
+        .or_else(|| env::var("USER").ok())
+        .or_else(|| env::var("USERNAME").ok())
+        .unwrap_or_else(|| "Unknown".to_string())
+}
+
+/// The path of the script to create for `name`, which may include a directory and may omit the
+/// `.rs` suffix.
+///
+/// # Errors
+///
+/// Will return `Err` if the file stem is not a valid package name, since it becomes the name of
+/// the script's Cargo package.
+pub fn script_path(name: &str) -> ThagResult<PathBuf> {
+    let path = if name.ends_with(RS_SUFFIX) {
+        PathBuf::from(name)
+    } else {
+        PathBuf::from(format!("{name}{RS_SUFFIX}"))
+    };
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default();
+    if stem.is_empty()
+        || !stem.starts_with(|c: char| c.is_ascii_alphabetic())
+        || !stem
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(format!(
+            "Invalid script name `{stem}`: use a letter followed by letters, digits, `_` or `-`"
+        )
+        .into());
+    }
+    Ok(path)
+}
+
+/// Create a new script at `path` from the template of this kind. Won't overwrite an existing file.
+///
+/// # Errors
+///
+/// Will return `Err` if the file already exists, if there is no template of this kind, or if the
+/// script cannot be written.
+#[profiled]
+pub fn create(path: &Path, kind: &str, user_dir: Option<&Path>, author: &str) -> ThagResult<()> {
+    if path.exists() {
+        return Err(format!("File `{}` already exists", path.display()).into());
+    }
+    let template = load(kind, user_dir)?;
+    let placeholders = Placeholders {
+        name: path
+            .file_stem()
+            .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned()),
+        author: author.to_string(),
+        date: Local::now().format("%Y-%m-%d").to_string(),
+    };
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, render(&template, &placeholders))?;
+    Ok(())
+}
+
+/// Carry out a `--new` command.
+///
+/// # Errors
+///
+/// Will return `Err` if the script name is invalid or the script cannot be created.
+#[profiled]
+pub fn execute(cli: &Cli) -> ThagResult<()> {
+    let Some(ref name) = cli.new else {
+        return Err("Missing name for --new".into());
+    };
+    let kind = cli.template.as_deref().unwrap_or(DEFAULT_TEMPLATE);
+    let path = script_path(name)?;
+    create(
+        &path,
+        kind,
+        user_templates_dir().as_deref(),
+        &default_author(),
+    )?;
+    vprtln!(
+        V::N,
+        "✓ Created {} from the {kind} template. Run it with `thag {}`",
+        path.display(),
+        path.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,244 @@
+//!
+//! Watch mode for `thag_rs`, invoked by the `--watch (-w)` option.
+//!
+//! After an initial generate/build/run cycle, `watch` polls the script, its generated `Cargo.toml`,
+//! any `mod` files declared by the script and the user configuration file for changes. Changes are
+//! debounced so that a burst of saves from an editor triggers a single cycle. Any run still in
+//! progress is killed before the script is regenerated, rebuilt and rerun.
+//!
+//! Each run is subject to the same resource limits as a normal run, with the time limit enforced
+//! by the polling loop.
+//!
+//! Since the configuration is loaded once per process, a change to the configuration file restarts
+//! the `thag` process with the same arguments so that the new settings take effect.
+//!
+use crate::builder::{create_run_command, display_timings, gen_build_run};
+use crate::config::{Context, RealContext};
+use crate::limits::{LimitedChild, ResourceLimits};
+use crate::{BuildState, Cli, ProcFlags, Role, ScriptState, ThagResult, FLOWER_BOX_LEN};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+use thag_common::{debug_log, V};
+use thag_profiler::profiled;
+use thag_styling::svprtln;
+
+/// How often to poll the watched files for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How long the watched files must stay unchanged before a new cycle is started.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// A snapshot of the modification times of the watched files. A file that does not
+/// exist is recorded as `None`, so that its creation or deletion counts as a change.
+type Snapshot = BTreeMap<PathBuf, Option<SystemTime>>;
+
+/// What kind of change ended a wait.
+enum Change {
+    /// The script or one of its build inputs changed.
+    Source,
+    /// The user configuration file changed.
+    Config,
+}
+
+/// Run the script in watch mode: generate, build and run it (or check, test or run the
+/// specified Cargo subcommand), then repeat whenever the script or one of its inputs changes.
+///
+/// The `build_state` for the first cycle is the one already configured by the caller.
+/// Subsequent cycles force generation and building, since a change has already been detected.
+///
+/// # Errors
+///
+/// Will return `Err` if the script's `BuildState` cannot be configured, or if a running
+/// script cannot be killed or `thag` cannot be restarted after a configuration change.
+/// Build and run failures are reported and the watch continues.
+// Loops until interrupted - do not profile
+pub fn watch(
+    args: &Cli,
+    proc_flags: &ProcFlags,
+    script_state: &ScriptState,
+    mut build_state: BuildState,
+    mut start: Instant,
+) -> ThagResult<()> {
+    let config_path = RealContext::new().get_config_path();
+    let is_run = proc_flags.contains(ProcFlags::RUN);
+
+    // The run step is managed here, so that it can be killed when a change is detected.
+    let mut cycle_flags = proc_flags.clone();
+    cycle_flags.remove(ProcFlags::RUN);
+
+    loop {
+        let cycle_result = gen_build_run(args, &cycle_flags, &mut build_state, &start);
+
+        let mut child = match cycle_result {
+            Ok(()) if is_run => Some(start_run(&args.args, &build_state)?),
+            Ok(()) => None,
+            Err(e) => {
+                svprtln!(Role::ERR, V::QQ, "{e}");
+                None
+            }
+        };
+
+        let watched = watched_files(&build_state, &config_path);
+        svprtln!(
+            Role::EMPH,
+            V::N,
+            "Watching {} file(s) for changes. Press Ctrl-C to stop.",
+            watched.len()
+        );
+
+        let change = wait_for_change(
+            &watched,
+            &config_path,
+            &mut child,
+            &build_state.limits,
+            &start,
+            proc_flags,
+        )?;
+
+        if let Some(mut running) = child.take() {
+            svprtln!(Role::WARN, V::N, "Change detected: stopping previous run");
+            running.child.kill()?;
+            let _ = running.child.wait()?;
+        }
+
+        if matches!(change, Change::Config) {
+            svprtln!(
+                Role::EMPH,
+                V::N,
+                "Configuration changed: restarting {}",
+                crate::PACKAGE_NAME
+            );
+            return restart();
+        }
+
+        svprtln!(Role::EMPH, V::N, "Change detected: rerunning");
+        start = Instant::now();
+        cycle_flags.insert(ProcFlags::FORCE);
+        build_state = BuildState::pre_configure(&cycle_flags, args, script_state)?;
+    }
+}
+
+/// Spawn the built program within its resource limits, sandwiched between dash lines like a
+/// normal run.
+#[profiled]
+fn start_run(args: &[String], build_state: &BuildState) -> ThagResult<LimitedChild> {
+    let dash_line = "─".repeat(FLOWER_BOX_LEN);
+    svprtln!(Role::EMPH, V::Q, "{dash_line}");
+    build_state
+        .limits
+        .spawn(&mut create_run_command(args, build_state))
+}
+
+/// Collect the files whose modification should trigger a new cycle.
+#[profiled]
+fn watched_files(build_state: &BuildState, config_path: &Path) -> Snapshot {
+    let mut paths = vec![
+        build_state.source_path.clone(),
+        build_state.cargo_toml_path.clone(),
+        config_path.to_path_buf(),
+    ];
+    paths.extend(build_state.modules.iter().map(|module| module.path.clone()));
+    snapshot(paths)
+}
+
+#[profiled]
+fn snapshot(paths: impl IntoIterator<Item = PathBuf>) -> Snapshot {
+    paths
+        .into_iter()
+        .map(|path| {
+            let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+            (path, modified)
+        })
+        .collect()
+}
+
+/// Poll the watched files until a change has settled, meanwhile reporting the completion
+/// of any running script and stopping it if it exceeds the time limit.
+// Polling loop - do not profile
+fn wait_for_change(
+    watched: &Snapshot,
+    config_path: &Path,
+    child: &mut Option<LimitedChild>,
+    limits: &ResourceLimits,
+    start: &Instant,
+    proc_flags: &ProcFlags,
+) -> ThagResult<Change> {
+    let mut baseline = watched.clone();
+    let mut changed_at: Option<Instant> = None;
+    let mut config_changed = false;
+
+    loop {
+        thread::sleep(POLL_INTERVAL);
+
+        if let Some(running) = child {
+            let finished = match running.child.try_wait()? {
+                Some(exit_status) => Some((exit_status, false)),
+                None if running.is_overdue(limits.timeout) => {
+                    debug_log!("Killing script {} at the time limit", running.child.id());
+                    running.child.kill()?;
+                    Some((running.child.wait()?, true))
+                }
+                None => None,
+            };
+            if let Some((exit_status, timed_out)) = finished {
+                let dash_line = "─".repeat(FLOWER_BOX_LEN);
+                svprtln!(Role::EMPH, V::Q, "{dash_line}");
+                display_timings(start, "Completed run", proc_flags);
+                if let Err(e) = limits.check(exit_status, &running.usage(timed_out)) {
+                    svprtln!(Role::ERR, V::QQ, "{e}");
+                } else if !exit_status.success() {
+                    svprtln!(Role::ERR, V::QQ, "Script exited with {exit_status}");
+                }
+                *child = None;
+            }
+        }
+
+        let current = snapshot(baseline.keys().cloned());
+        if current == baseline {
+            if changed_at.is_some_and(|instant| instant.elapsed() >= DEBOUNCE) {
+                return Ok(if config_changed {
+                    Change::Config
+                } else {
+                    Change::Source
+                });
+            }
+            continue;
+        }
+
+        for (path, modified) in &current {
+            if baseline.get(path) != Some(modified) {
+                debug_log!("Watched file changed: {}", path.display());
+                config_changed |= path == config_path;
+            }
+        }
+        changed_at = Some(Instant::now());
+        baseline = current;
+    }
+}
+
+/// Replace the current `thag` process with a fresh one with the same arguments, so that
+/// a changed configuration is reloaded.
+#[profiled]
+fn restart() -> ThagResult<()> {
+    let mut command = Command::new(env::current_exe()?);
+    command.args(env::args_os().skip(1));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // `exec` only returns if it failed.
+        Err(command.exec().into())
+    }
+
+    #[cfg(not(unix))]
+    {
+        let status = command.status()?;
+        std::process::exit(status.code().unwrap_or(1));
+    }
+}
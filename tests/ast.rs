@@ -2,7 +2,7 @@
 mod tests {
     use std::sync::Once;
     use thag_rs::ast::{
-        find_crates, find_metadata, infer_deps_from_ast, infer_deps_from_source,
+        find_crates, find_metadata, infer_deps_from_ast, infer_deps_from_source, needs_async_main,
         should_filter_dependency,
     };
     use thag_rs::Ast;
@@ -143,4 +143,41 @@ mod tests {
         assert!(should_filter_dependency("Result"));
         assert!(should_filter_dependency("Option"));
     }
+
+    fn snippet_ast(source: &str) -> Ast {
+        Ast::Expr(syn::parse_str::<syn::Expr>(&format!("{{{source}}}")).unwrap())
+    }
+
+    #[test]
+    fn test_ast_needs_async_main_top_level_await() {
+        set_up();
+        let ast = snippet_ast("let body = reqwest::get(url).await?.text().await?; body");
+        assert!(needs_async_main(&ast));
+    }
+
+    #[test]
+    fn test_ast_needs_async_main_async_block() {
+        set_up();
+        assert!(needs_async_main(&snippet_ast("let x = async { 42 }; x")));
+    }
+
+    #[test]
+    fn test_ast_needs_async_main_await_in_macro() {
+        set_up();
+        assert!(needs_async_main(&snippet_ast(
+            r#"println!("{}", fetch().await);"#
+        )));
+    }
+
+    #[test]
+    fn test_ast_needs_async_main_await_only_in_async_fn() {
+        set_up();
+        let ast = snippet_ast(
+            "async fn fetch() -> u32 { ready(1).await }
+            let closure = async || ready(2).await;
+            5",
+        );
+        assert!(!needs_async_main(&ast));
+        assert!(!needs_async_main(&snippet_ast("let x = 5; x + 1")));
+    }
 }
@@ -0,0 +1,180 @@
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path, process::Command, sync::Once, time::Duration};
+    use tempfile::TempDir;
+    use thag_rs::batch::{
+        find_scripts, first_error, run_all, script_options, to_json, to_junit, BatchMode,
+        BatchResult,
+    };
+    use thag_rs::config::DependencyInference;
+    use thag_rs::Cli;
+
+    // Set environment variables before running tests
+    fn set_up() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| unsafe {
+            std::env::set_var("TEST_ENV", "1");
+            std::env::set_var("VISUAL", "cat");
+            std::env::set_var("EDITOR", "cat");
+        });
+    }
+
+    fn results() -> Vec<BatchResult> {
+        vec![
+            BatchResult {
+                script: "scripts/bad.rs".into(),
+                passed: false,
+                duration: Duration::from_millis(1500),
+                error: Some(
+                    "error[E0308]: mismatched types: expected `String`, found <int>".into(),
+                ),
+            },
+            BatchResult {
+                script: "scripts/good.rs".into(),
+                passed: true,
+                duration: Duration::from_millis(250),
+                error: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_batch_find_scripts() {
+        set_up();
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        fs::write(dir.join("b.rs"), "").unwrap();
+        fs::write(dir.join("a.rs"), "").unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        fs::create_dir(dir.join("sub.rs")).unwrap();
+        let scripts = find_scripts(dir).unwrap();
+        assert_eq!(scripts, vec![dir.join("a.rs"), dir.join("b.rs")]);
+        assert!(find_scripts(&dir.join("missing")).is_err());
+    }
+
+    #[test]
+    fn test_batch_first_error() {
+        set_up();
+        let stderr = "    Checking bad v0.0.1 (/tmp/thag_rs/bad)\n\u{1b}[1m\u{1b}[91merror[E0308]\u{1b}[0m: mismatched types\n --> bad.rs:2:21\nerror: could not compile `bad`\n";
+        assert_eq!(
+            first_error(stderr, "").as_deref(),
+            Some("error[E0308]: mismatched types")
+        );
+        let stdout = "Checking\n\u{1b}[91mError running thag: Script path `x.rs` does not exist.\n\u{1b}[0m\n";
+        assert_eq!(
+            first_error("warning: unused\n", stdout).as_deref(),
+            Some("Script path `x.rs` does not exist.")
+        );
+        assert_eq!(
+            first_error("thread 'main' panicked\n\n", "").as_deref(),
+            Some("thread 'main' panicked")
+        );
+        assert_eq!(first_error("", ""), None);
+    }
+
+    #[test]
+    fn test_batch_to_json() {
+        set_up();
+        let json = to_json(Path::new("scripts"), BatchMode::Check, &results()).unwrap();
+        let report: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(report["mode"], "check");
+        assert_eq!(report["passed"], 1);
+        assert_eq!(report["failed"], 1);
+        assert_eq!(report["results"][0]["duration_ms"], 1500);
+        assert_eq!(report["results"][1]["error"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_batch_to_junit() {
+        set_up();
+        let xml = to_junit(Path::new("scripts"), BatchMode::Build, &results());
+        assert!(xml.contains(r#"<testsuite name="thag build scripts" tests="2" failures="1""#));
+        assert!(xml.contains(
+            r#"<failure message="error[E0308]: mismatched types: expected `String`, found &lt;int&gt;">"#
+        ));
+        assert!(xml.contains(r#"name="good.rs" time="0.250"/>"#));
+    }
+
+    #[test]
+    fn test_batch_run_all() {
+        set_up();
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        fs::write(dir.join("batch_good.rs"), "println!(\"hi\");\n").unwrap();
+        fs::write(dir.join("batch_bad.rs"), "let x: String = 5;\n").unwrap();
+        let scripts = find_scripts(dir).unwrap();
+        let results = run_all(
+            Path::new(env!("CARGO_BIN_EXE_thag")),
+            &scripts,
+            BatchMode::Check,
+            &["--force".to_string()],
+            2,
+            |_| {},
+        );
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].name(), "batch_bad.rs");
+        assert!(!results[0].passed);
+        assert!(results[0]
+            .error
+            .as_deref()
+            .is_some_and(|error| error.starts_with("error[E0308]")));
+        assert_eq!(results[1].name(), "batch_good.rs");
+        assert!(results[1].passed, "{:?}", results[1].error);
+    }
+
+    #[test]
+    fn test_batch_script_options() {
+        set_up();
+        let cli = Cli {
+            check_all: Some("demo".to_string()),
+            force: true,
+            lock: true,
+            features: Some("a,b".to_string()),
+            infer: Some(DependencyInference::Min),
+            edition: Some("2024".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            script_options(&cli),
+            [
+                "--force",
+                "--lock",
+                "--features",
+                "a,b",
+                "--infer",
+                "min",
+                "--edition",
+                "2024"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_batch_failed_report_stdout() {
+        set_up();
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        fs::write(dir.join("report_good.rs"), "println!(\"hi\");\n").unwrap();
+        fs::write(dir.join("report_bad.rs"), "let x: String = 5;\n").unwrap();
+        let report = |format: &str| {
+            let output = Command::new(env!("CARGO_BIN_EXE_thag"))
+                .arg("--check-all")
+                .arg(dir)
+                .args(["--report", format])
+                .output()
+                .unwrap();
+            assert!(!output.status.success());
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(stderr.contains("1 of 2 script(s) failed"), "{stderr}");
+            String::from_utf8(output.stdout).unwrap()
+        };
+
+        let json: serde_json::Value = serde_json::from_str(&report("json")).unwrap();
+        assert_eq!(json["failed"], 1);
+
+        let xml = report("junit");
+        assert!(xml.starts_with("<?xml "));
+        assert!(xml.ends_with("</testsuite>\n"), "{xml}");
+        assert!(!xml.contains('\x1b'));
+    }
+}
@@ -11,12 +11,15 @@ use std::{
 };
 use thag_proc_macros::{safe_eprintln, safe_println};
 use thag_rs::ast::Ast;
-use thag_rs::builder::{build, display_timings, generate, run, BuildState, ScriptState};
+use thag_rs::builder::{
+    build, display_timings, generate, profile_dir, run, BuildState, ScriptState,
+};
 use thag_rs::cmd_args::Cli;
 use thag_rs::code_utils::{self};
 use thag_rs::config::DependencyInference;
 #[cfg(debug_assertions)]
 use thag_rs::debug_timings;
+use thag_rs::limits::ResourceLimits;
 use thag_rs::{escape_path_for_windows, execute, ProcFlags, EXECUTABLE_CACHE_SUBDIR, TMPDIR};
 
 // Set environment variables before running tests
@@ -85,6 +88,17 @@ fn create_sample_build_state(source_name: &str) -> BuildState {
         args: vec![],
         features: None,
         thag_auto_processed: false,
+        lock_path: None,
+        locked_versions: None,
+        modules: vec![],
+        profile: None,
+        edition: None,
+        fingerprint: None,
+        is_dynamic: false,
+        line_map: None,
+        limits: ResourceLimits::default(),
+        generated: false,
+        built: false,
     }
 }
 
@@ -242,6 +256,17 @@ name = "bitflags_t"
         args: vec![],
         features: None,
         thag_auto_processed: false,
+        lock_path: None,
+        locked_versions: None,
+        modules: vec![],
+        profile: None,
+        edition: None,
+        fingerprint: None,
+        is_dynamic: false,
+        line_map: None,
+        limits: ResourceLimits::default(),
+        generated: false,
+        built: false,
     };
     dbg!(&build_state);
     let proc_flags = ProcFlags::empty();
@@ -337,6 +362,49 @@ fn test_builder_build_state_pre_configure() {
     );
 }
 
+#[test]
+fn test_builder_build_state_pre_configure_profile() {
+    set_up();
+    let _ = env_logger::try_init();
+
+    let proc_flags = ProcFlags::empty();
+    let script = "tests/assets/fizz_buzz_t.rs";
+    let script_state = ScriptState::Named {
+        script: script.to_string(),
+        script_dir_path: PathBuf::from(script),
+    };
+
+    let cli = Cli {
+        release: true,
+        ..Default::default()
+    };
+    let build_state = BuildState::pre_configure(&proc_flags, &cli, &script_state).unwrap();
+    assert_eq!(build_state.profile.as_deref(), Some("release"));
+    assert!(build_state
+        .target_path
+        .starts_with(TMPDIR.join(EXECUTABLE_CACHE_SUBDIR).join("release")));
+
+    // The default dev profile shares the top level of the executable cache
+    let cli = Cli {
+        profile: Some("dev".to_string()),
+        ..Default::default()
+    };
+    let build_state = BuildState::pre_configure(&proc_flags, &cli, &script_state).unwrap();
+    assert_eq!(build_state.profile, None);
+    assert_eq!(
+        build_state.target_path.parent().unwrap(),
+        TMPDIR.join(EXECUTABLE_CACHE_SUBDIR)
+    );
+}
+
+#[test]
+fn test_builder_profile_dir() {
+    assert_eq!(profile_dir(None), "debug");
+    assert_eq!(profile_dir(Some("test")), "debug");
+    assert_eq!(profile_dir(Some("bench")), "release");
+    assert_eq!(profile_dir(Some("bench-fast")), "bench-fast");
+}
+
 #[test]
 fn test_builder_script_state_getters() {
     set_up();
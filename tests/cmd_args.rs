@@ -1,7 +1,8 @@
 use clap::Parser;
 use std::sync::Once;
 use thag_proc_macros::safe_eprintln;
-use thag_rs::{get_proc_flags, Cli, ProcFlags};
+use thag_rs::cmd_args::{CacheAction, MessageFormat, OutputFormat, ReportFormat};
+use thag_rs::{get_proc_flags, validate_args, Cli, ProcFlags};
 
 // Set environment variables before running tests
 fn set_up() {
@@ -33,6 +34,170 @@ fn test_cmd_args_get_args_expr() {
     assert!(Some("'2 + 5'") == cli.expression.as_deref());
 }
 
+#[test]
+fn test_cmd_args_message_format() {
+    set_up();
+    let cli = Cli::parse_from(["thag", "demo_script.rs"]);
+    assert_eq!(cli.message_format, MessageFormat::Human);
+    let cli = Cli::parse_from(["thag", "--message-format", "json", "demo_script.rs"]);
+    assert_eq!(cli.message_format, MessageFormat::Json);
+}
+
+#[test]
+fn test_cmd_args_cache() {
+    set_up();
+    let cli = Cli::parse_from(["thag", "--cache", "prune", "--older-than", "30d"]);
+    assert_eq!(cli.cache, Some(CacheAction::Prune));
+    assert_eq!(
+        cli.older_than,
+        Some(std::time::Duration::from_secs(30 * 86_400))
+    );
+    assert!(Cli::try_parse_from(["thag", "--cache", "prune", "--older-than", "30y"]).is_err());
+    assert!(Cli::try_parse_from(["thag", "--cache", "list", "--clean"]).is_err());
+}
+
+#[test]
+fn test_cmd_args_limits() {
+    set_up();
+    let cli = Cli::parse_from([
+        "thag",
+        "--timeout",
+        "90s",
+        "--max-memory",
+        "512M",
+        "--max-cpu",
+        "30",
+        "demo_script.rs",
+    ]);
+    assert_eq!(cli.timeout, Some(std::time::Duration::from_secs(90)));
+    assert_eq!(cli.max_memory, Some(512 * 1024 * 1024));
+    assert_eq!(cli.max_cpu, Some(30));
+    assert!(Cli::try_parse_from(["thag", "--timeout", "soon", "demo_script.rs"]).is_err());
+    assert!(Cli::try_parse_from(["thag", "--max-cpu", "0", "demo_script.rs"]).is_err());
+}
+
+#[test]
+fn test_cmd_args_batch() {
+    set_up();
+    let cli = Cli::parse_from([
+        "thag",
+        "--check-all",
+        "demo",
+        "-j",
+        "4",
+        "--report",
+        "junit",
+    ]);
+    assert_eq!(cli.check_all.as_deref(), Some("demo"));
+    assert_eq!(cli.jobs, Some(4));
+    assert_eq!(cli.report, Some(ReportFormat::Junit));
+    assert!(Cli::try_parse_from(["thag", "--check-all", "demo", "--build-all", "demo"]).is_err());
+    assert!(Cli::try_parse_from(["thag", "--build-all", "demo", "--jobs", "0"]).is_err());
+    let cli = Cli::parse_from(["thag", "--jobs", "4", "demo_script.rs"]);
+    let proc_flags = get_proc_flags(&cli).unwrap();
+    assert!(validate_args(&cli, &proc_flags).is_err());
+    let cli = Cli::parse_from(["thag", "--check-all", "demo", "--lock", "--infer", "min"]);
+    let proc_flags = get_proc_flags(&cli).unwrap();
+    assert!(validate_args(&cli, &proc_flags).is_ok());
+    let cli = Cli::parse_from(["thag", "--build-all", "demo", "--timeout", "30s"]);
+    let proc_flags = get_proc_flags(&cli).unwrap();
+    assert!(validate_args(&cli, &proc_flags).is_err());
+    let cli = Cli::parse_from(["thag", "--lock", "--expr", "1"]);
+    let proc_flags = get_proc_flags(&cli).unwrap();
+    assert!(validate_args(&cli, &proc_flags).is_err());
+}
+
+#[test]
+fn test_cmd_args_new() {
+    set_up();
+    let cli = Cli::parse_from(["thag", "--new", "my_tool", "--template", "clap"]);
+    assert_eq!(cli.new.as_deref(), Some("my_tool"));
+    assert_eq!(cli.template.as_deref(), Some("clap"));
+    let cli = Cli::parse_from(["thag", "--template", "clap", "demo_script.rs"]);
+    let proc_flags = get_proc_flags(&cli).unwrap();
+    assert!(validate_args(&cli, &proc_flags).is_err());
+}
+
+#[test]
+fn test_cmd_args_export() {
+    set_up();
+    let cli = Cli::parse_from(["thag", "--export", "my_project", "demo_script.rs"]);
+    assert_eq!(cli.export.as_deref(), Some("my_project"));
+    let proc_flags = get_proc_flags(&cli).unwrap();
+    assert!(proc_flags.contains(ProcFlags::GENERATE | ProcFlags::NORUN));
+    assert!(!proc_flags.contains(ProcFlags::RUN));
+    assert!(Cli::try_parse_from([
+        "thag",
+        "--export",
+        "my_project",
+        "--build",
+        "demo_script.rs"
+    ])
+    .is_err());
+}
+
+#[test]
+fn test_cmd_args_inline() {
+    set_up();
+    let cli = Cli::parse_from(["thag", "--inline", "my_crate"]);
+    assert_eq!(cli.inline.as_deref(), Some("my_crate"));
+    assert!(Cli::try_parse_from(["thag", "--inline", "my_crate", "--new", "my_tool"]).is_err());
+}
+
+#[test]
+fn test_cmd_args_loop_modes() {
+    set_up();
+    let cli = Cli::parse_from(["thag", "-l", "fields[0]", "-F", ",", "-0"]);
+    assert_eq!(cli.field_separator.as_deref(), Some(","));
+    assert!(cli.null_data);
+    let cli = Cli::parse_from(["thag", "-l", "&row[0]", "--csv", "-F", "ab"]);
+    let proc_flags = get_proc_flags(&cli).unwrap();
+    assert!(validate_args(&cli, &proc_flags).is_err());
+    assert!(Cli::try_parse_from(["thag", "-l", "record", "--jsonl", "--csv"]).is_err());
+    let cli = Cli::parse_from(["thag", "--csv", "demo_script.rs"]);
+    assert!(get_proc_flags(&cli).is_err());
+}
+
+#[test]
+fn test_cmd_args_loop_in_place() {
+    set_up();
+    let cli = Cli::parse_from(["thag", "-l", "line", "-I.bak", "--", "a.txt", "b.txt"]);
+    assert_eq!(cli.in_place.as_deref(), Some(".bak"));
+    assert_eq!(cli.args, ["a.txt", "b.txt"]);
+    let proc_flags = get_proc_flags(&cli).unwrap();
+    assert!(validate_args(&cli, &proc_flags).is_ok());
+    let cli = Cli::parse_from(["thag", "-l", "line", "-I", "--", "a.txt"]);
+    assert_eq!(cli.in_place.as_deref(), Some(""));
+    let cli = Cli::parse_from(["thag", "-l", "line", "-I"]);
+    let proc_flags = get_proc_flags(&cli).unwrap();
+    assert!(validate_args(&cli, &proc_flags).is_err());
+}
+
+#[test]
+fn test_cmd_args_expr_args() {
+    set_up();
+    let cli = Cli::parse_from(["thag", "-e", "arg1.len()", "--", "foo"]);
+    assert_eq!(cli.args, ["foo"]);
+    let proc_flags = get_proc_flags(&cli).unwrap();
+    assert!(validate_args(&cli, &proc_flags).is_ok());
+    let cli = Cli::parse_from(["thag", "-s", "--", "foo"]);
+    let proc_flags = get_proc_flags(&cli).unwrap();
+    assert!(validate_args(&cli, &proc_flags).is_err());
+}
+
+#[test]
+fn test_cmd_args_format() {
+    set_up();
+    let cli = Cli::parse_from(["thag", "-e", "1 + 1"]);
+    assert_eq!(cli.format, OutputFormat::Debug);
+    let cli = Cli::parse_from(["thag", "--format", "json", "-e", "1 + 1"]);
+    assert_eq!(cli.format, OutputFormat::Json);
+    assert_eq!(cli.format.crate_name(), Some("serde_json"));
+    assert_eq!(OutputFormat::Pretty.crate_name(), None);
+    assert!(Cli::try_parse_from(["thag", "--format", "xml", "-e", "1 + 1"]).is_err());
+    assert!(Cli::try_parse_from(["thag", "--format", "table", "-l", "line"]).is_err());
+}
+
 #[test]
 fn test_cmd_args_get_args_stdin() {
     set_up();
@@ -177,3 +342,31 @@ fn test_cmd_args_proc_flags_executable() {
     let proc_flags = result.expect("Couldn't access ProcFlags");
     assert!(proc_flags.contains(ProcFlags::NORUN | ProcFlags::EXECUTABLE));
 }
+
+#[test]
+fn test_cmd_args_proc_flags_watch() {
+    set_up();
+    let args = vec!["thag", "/demo/hello.rs", "-w"];
+    let cli = Cli::parse_from(args);
+    let result = get_proc_flags(&cli);
+    let proc_flags = result.expect("Couldn't access ProcFlags");
+    assert!(proc_flags.contains(ProcFlags::WATCH | ProcFlags::RUN));
+}
+
+#[test]
+fn test_cmd_args_watch_conflicts_with_executable() {
+    set_up();
+    let args = vec!["thag", "/demo/hello.rs", "--watch", "-x"];
+    let result = Cli::try_parse_from(args);
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_cmd_args_proc_flags_update_lock() {
+    set_up();
+    let args = vec!["thag", "/demo/hello.rs", "--update-lock"];
+    let cli = Cli::parse_from(args);
+    let result = get_proc_flags(&cli);
+    let proc_flags = result.expect("Couldn't access ProcFlags");
+    assert!(proc_flags.contains(ProcFlags::LOCK | ProcFlags::UPDATE_LOCK));
+}
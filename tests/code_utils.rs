@@ -1,12 +1,16 @@
 #[cfg(test)]
 mod tests {
-    use std::{io::Write, path::Path, sync::Once};
+    use std::{fs, io::Write, path::Path, process::Command, sync::Once};
     use syn::token::RArrow;
-    use tempfile::NamedTempFile;
+    use tempfile::{NamedTempFile, TempDir};
     use thag_rs::ast::{is_last_stmt_unit_type, is_path_unit_type, is_stmt_unit_type};
+    use thag_rs::cmd_args::OutputFormat;
     use thag_rs::code_utils::{
-        extract_inner_attribs, path_to_str, read_file_contents, wrap_snippet,
+        build_loop, contains_verbatim, csv_delimiter, escape_keywords, expr_prelude,
+        extract_frontmatter, extract_inner_attribs, path_to_str, print_value, read_file_contents,
+        strip_frontmatter, to_ast, wrap_async_snippet, wrap_snippet, EDITION_2024_KEYWORDS,
     };
+    use thag_rs::{AsyncRuntime, Cli};
 
     // Set environment variables before running tests
     fn set_up() {
@@ -59,6 +63,228 @@ mod tests {
         assert!(wrapped.contains("fn main() -> Result<(), Box<dyn Error>>"));
     }
 
+    #[test]
+    fn test_code_utils_wrap_async_snippet() {
+        set_up();
+        let body = "let x = async { 5 }.await;\nprintln!(\"{x}\");";
+
+        let wrapped = wrap_async_snippet("", body, AsyncRuntime::Tokio);
+        assert!(wrapped.contains("#[tokio::main]\nasync fn main() -> Result<(), Box<dyn Error>>"));
+        assert!(wrapped.contains(body));
+
+        let wrapped = wrap_async_snippet("", body, AsyncRuntime::AsyncStd);
+        assert!(wrapped.contains("#[async_std::main]\nasync fn main()"));
+
+        let wrapped = wrap_async_snippet("", body, AsyncRuntime::Smol);
+        assert!(wrapped.contains("smol::block_on(async_main())"));
+        assert!(wrapped.contains("async fn async_main() -> Result<(), Box<dyn Error>>"));
+        assert!(!wrapped.contains("async fn main"));
+    }
+
+    #[test]
+    fn test_code_utils_print_value() {
+        set_up();
+        let ast = to_ast("snippet", "vec![(1, \"a\"), (2, \"b\")]").unwrap();
+        let printed = |format, unquote| {
+            let body = print_value(&ast, format, unquote);
+            let program = wrap_snippet("", &body);
+            assert!(syn::parse_file(&program).is_ok(), "{program}");
+            body.replace(' ', "")
+        };
+
+        assert!(printed(OutputFormat::Debug, false).contains(r#"format!("{:?}",vec!"#));
+        assert!(printed(OutputFormat::Debug, true).contains("trim_matches"));
+        assert!(printed(OutputFormat::Pretty, true).contains(r#"println!("{:#?}",vec!"#));
+        assert!(printed(OutputFormat::Display, false).contains(r#"println!("{}",vec!"#));
+        assert!(printed(OutputFormat::Json, false).contains("serde_json::to_string_pretty(&(vec!"));
+        assert!(printed(OutputFormat::Yaml, false).contains("serde_yaml_ok::to_string(&(vec!"));
+        let table = printed(OutputFormat::Table, false);
+        assert!(table.contains("fnprint_table(rows:&[serde_json::Value])"));
+        assert!(table.contains(".map(serde_json::to_value)"));
+    }
+
+    #[test]
+    fn test_code_utils_expr_prelude() {
+        set_up();
+        assert_eq!(expr_prelude("1 + 1"), "");
+
+        let prelude = expr_prelude(r#"format!("{arg2}-{}", arg1)"#);
+        assert!(prelude.contains("let args: Vec<String> = std::env::args().skip(1).collect();"));
+        assert!(prelude.contains("let arg1: &str = args.get(0)"));
+        assert!(prelude.contains("let arg2: &str = args.get(1)"));
+        assert!(!prelude.contains("input"));
+
+        let prelude = expr_prelude("lines().count()");
+        assert!(prelude.contains("let input: String"));
+        assert!(prelude.contains("let lines = || input.lines();"));
+        assert!(!prelude.contains("args"));
+
+        // Stdin is left alone for code that reads it itself or has its own `input`
+        assert_eq!(expr_prelude("io::stdin().lines().count()"), "");
+        assert_eq!(expr_prelude("{ let input = 5; input + 1 }"), "");
+        assert_eq!(expr_prelude("std::env::args().count()"), "");
+
+        // Names bound by patterns are the expression's own
+        assert_eq!(
+            expr_prelude(r#"for input in ["a", "b"] { println!("{input}"); }"#),
+            ""
+        );
+        assert_eq!(expr_prelude("[1, 2].map(|input| input + 1)"), "");
+        assert_eq!(
+            expr_prelude("fn f(lines: &str) -> usize { lines.len() } f(\"x\")"),
+            ""
+        );
+        assert_eq!(expr_prelude("{ let (a, input) = (1, 2); a + input }"), "");
+        assert_eq!(
+            expr_prelude("match Some(1) { Some(arg1) => arg1, None => 0 }"),
+            ""
+        );
+        // ...but only where they are in scope
+        let prelude =
+            expr_prelude("{ let n = input.len(); for input in [n] { println!(\"{input}\"); } }");
+        assert!(prelude.contains("let input: String"));
+        let prelude = expr_prelude("[1].map(|x| x + arg1.len())");
+        assert!(prelude.contains("let arg1: &str"));
+
+        let body = r#"println!("{}", input.len());"#;
+        let program = wrap_snippet("", &format!("{}{body}", expr_prelude(body)));
+        assert!(syn::parse_file(&program).is_ok(), "{program}");
+    }
+
+    fn loop_program(args: &Cli, filter: &str) -> String {
+        let program = build_loop(args, filter.to_string());
+        assert!(syn::parse_file(&program).is_ok(), "{program}");
+        program
+    }
+
+    #[test]
+    fn test_code_utils_build_loop_fields() {
+        set_up();
+        let filter = r#"println!("{}", fields[1]);"#;
+        let mut args = Cli::default();
+        assert!(!loop_program(&args, filter).contains("let fields"));
+
+        args.field_separator = Some(" ".to_string());
+        assert!(loop_program(&args, filter).contains("line.split_whitespace()"));
+
+        args.field_separator = Some(",".to_string());
+        assert!(loop_program(&args, filter).contains(r#"line.split(",")"#));
+
+        args.field_separator = Some("[,;]+".to_string());
+        let program = loop_program(&args, filter);
+        assert!(program.contains(r#"regex::Regex::new("[,;]+")?"#));
+        assert!(program.contains("field_separator.split(&line)"));
+
+        args.null_data = true;
+        let program = loop_program(&args, filter);
+        assert!(program.contains(r"split(b'\0')"));
+        assert!(program.contains("field_separator.split(&line)"));
+    }
+
+    #[test]
+    fn test_code_utils_build_loop_csv() {
+        set_up();
+        let args = Cli {
+            csv: true,
+            ..Default::default()
+        };
+        let program = loop_program(&args, r#"println!("{}", &row["name"]);"#);
+        assert!(program.contains("csv::Reader::from_reader(io::stdin().lock())"));
+        assert!(program.contains("impl std::ops::Index<&str> for Row<'_>"));
+
+        let args = Cli {
+            csv: true,
+            field_separator: Some(r"\t".to_string()),
+            ..Default::default()
+        };
+        let program = loop_program(&args, r#"println!("{}", &row[0]);"#);
+        assert!(program.contains("csv::ReaderBuilder::new().delimiter(9)"));
+    }
+
+    #[test]
+    fn test_code_utils_build_loop_jsonl() {
+        set_up();
+        let args = Cli {
+            jsonl: true,
+            ..Default::default()
+        };
+        let program = loop_program(&args, r#"record["name"].clone()"#);
+        assert!(program.contains("let record: serde_json::Value = serde_json::from_str(&line)"));
+        assert!(!program.contains("struct Row"));
+    }
+
+    #[test]
+    fn test_code_utils_build_loop_files() {
+        set_up();
+        let mut args = Cli {
+            args: vec!["a.txt".to_string(), "dir/b.txt".to_string()],
+            ..Default::default()
+        };
+        let program = loop_program(&args, r#"println!("{filename}: {line}");"#);
+        assert!(program.contains("for filename in std::env::args().skip(1)"));
+        assert!(!program.contains("a.txt"));
+        assert!(program.contains("io::BufReader::new(file).lines()"));
+        assert!(!program.contains("InPlace"));
+
+        args.in_place = Some(".bak".to_string());
+        let program = loop_program(&args, "line.to_uppercase()");
+        assert!(program.contains("struct InPlace"));
+        assert!(program.contains(r#"in_place.commit(out, ".bak")?;"#));
+        assert!(program.contains("macro_rules! println"));
+        assert!(program.contains(r#"writeln!(out, "{}", line.to_uppercase())?;"#));
+    }
+
+    #[test]
+    fn test_code_utils_loop_in_place_failure() {
+        set_up();
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        fs::write(dir.join("a.txt"), "1\n2\n").unwrap();
+        // The filter fails on the second line of the second file
+        fs::write(dir.join("b.txt"), "3\nx\n4\n").unwrap();
+        let output = Command::new(env!("CARGO_BIN_EXE_thag"))
+            .args([
+                "-I.bak",
+                "--loop",
+                "line.parse::<i32>()? * 2",
+                "--",
+                "a.txt",
+                "b.txt",
+            ])
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(
+            String::from_utf8_lossy(&output.stderr).contains("InvalidDigit"),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        // The first file was replaced and backed up, and the second left as it was
+        assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "2\n4\n");
+        assert_eq!(fs::read_to_string(dir.join("a.txt.bak")).unwrap(), "1\n2\n");
+        assert_eq!(fs::read_to_string(dir.join("b.txt")).unwrap(), "3\nx\n4\n");
+        assert!(!dir.join("b.txt.bak").exists());
+        assert!(!dir.join(".b.txt.thag-in-place").exists());
+    }
+
+    #[test]
+    fn test_code_utils_build_loop_stdin_filename() {
+        set_up();
+        let program = loop_program(&Cli::default(), "line");
+        assert!(program.contains(r#"let filename = "-";"#));
+        assert!(program.contains("io::stdin().lock().lines()"));
+    }
+
+    #[test]
+    fn test_code_utils_csv_delimiter() {
+        set_up();
+        assert_eq!(csv_delimiter(";"), Some(b';'));
+        assert_eq!(csv_delimiter(r"\t"), Some(b'\t'));
+        assert_eq!(csv_delimiter("\t"), Some(b'\t'));
+        assert_eq!(csv_delimiter("ab"), None);
+    }
+
     // #[test]
     // fn test_code_utils_find_use_renames_source() {
     //     set_up();
@@ -639,4 +865,59 @@ mod tests {
         let function_map = HashMap::new();
         assert!(!is_last_stmt_unit_type(&expr, &function_map));
     }
+
+    #[test]
+    fn test_code_utils_escape_keywords() {
+        set_up();
+        let source = r#"{ let x: u8 = rng.gen(); let r#gen = 1; println!("{}", (gen, x)); x }"#;
+        let mut ast = to_ast("snippet", source).unwrap();
+        assert_eq!(escape_keywords(&mut ast, EDITION_2024_KEYWORDS), 2);
+        let escaped = quote::quote!(#ast).to_string();
+        assert!(escaped.contains(". r#gen ()"));
+        assert!(escaped.contains("(r#gen , x)"));
+    }
+
+    #[test]
+    fn test_code_utils_wrap_unsafe_extern() {
+        set_up();
+        let source = r#"unsafe extern "C" {
+    pub safe fn abs(input: i32) -> i32;
+}
+println!("{}", abs(-3));"#;
+        assert!(to_ast("snippet", &format!("{{{source}}}")).is_some());
+        let wrapped = wrap_snippet("", source);
+        let syntax_tree = syn::parse_file(&wrapped).unwrap();
+        // The `safe` item is verbatim to syn, so prettyplease must not be used on it
+        assert!(contains_verbatim(&syntax_tree));
+    }
+
+    #[test]
+    fn test_code_utils_frontmatter() {
+        set_up();
+        let source = "#!/usr/bin/env -S cargo +nightly -Zscript\n\n---cargo\n[dependencies]\nregex = \"1\"\n---\nfn main() {}\n";
+        assert_eq!(
+            extract_frontmatter(source),
+            Some("[dependencies]\nregex = \"1\"\n")
+        );
+        // The block is blanked out rather than removed, to keep the line numbers
+        let stripped = strip_frontmatter(source);
+        assert_eq!(
+            stripped,
+            "#!/usr/bin/env -S cargo +nightly -Zscript\n\n\n\n\n\nfn main() {}\n"
+        );
+        assert!(to_ast("script", source).is_some_and(|ast| ast.is_file()));
+    }
+
+    #[test]
+    fn test_code_utils_frontmatter_not_found() {
+        set_up();
+        // Unclosed
+        assert!(extract_frontmatter("---cargo\n[dependencies]\nfn main() {}\n").is_none());
+        // Not at the start
+        let source = "fn main() {}\n---\n[dependencies]\n---\n";
+        assert!(extract_frontmatter(source).is_none());
+        assert_eq!(strip_frontmatter(source), source);
+        // Too few dashes
+        assert!(extract_frontmatter("--\n[dependencies]\n--\n").is_none());
+    }
 }
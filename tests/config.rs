@@ -8,7 +8,7 @@ mod tests {
             self, validate_config_format, Config, Dependencies, FeatureOverride, MockContext,
             RealContext,
         },
-        load, ColorSupport, Context, TermBgLuma, ThagResult, Verbosity,
+        load, AsyncRuntime, ColorSupport, Context, TermBgLuma, ThagResult, Verbosity,
     };
 
     #[cfg(feature = "simplelog")]
@@ -268,6 +268,21 @@ required_features = [
         assert!(config.should_include_feature("with-file-history", "rustyline"));
     }
 
+    #[test]
+    fn test_config_async_runtime() {
+        set_up();
+        assert_eq!(Dependencies::default().async_runtime, AsyncRuntime::Tokio);
+        let config: Config = toml::from_str(
+            r#"
+            [dependencies]
+            async_runtime = "async-std"
+        "#,
+        )
+        .unwrap();
+        assert_eq!(config.dependencies.async_runtime, AsyncRuntime::AsyncStd);
+        assert_eq!(config.dependencies.async_runtime.crate_name(), "async_std");
+    }
+
     #[test]
     fn test_config_validation() {
         // Test valid config
@@ -0,0 +1,89 @@
+#[cfg(test)]
+mod tests {
+    use std::{fs, process::Command, sync::Once};
+    use tempfile::TempDir;
+    use thag_rs::events::{emit_cargo_message, Event};
+
+    // Set environment variables before running tests
+    fn set_up() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| unsafe {
+            std::env::set_var("TEST_ENV", "1");
+            std::env::set_var("VISUAL", "cat");
+            std::env::set_var("EDITOR", "cat");
+        });
+    }
+
+    #[test]
+    fn test_events_serialize() {
+        set_up();
+        let features = vec!["derive".to_string()];
+        let event = Event::DependencyInferred {
+            name: "serde",
+            version: "^1.0.228",
+            features: &features,
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"dependency-inferred","name":"serde","version":"^1.0.228","features":["derive"]}"#
+        );
+
+        let event = Event::RunFinished {
+            script: "hello.rs",
+            exit_code: None,
+            success: false,
+            elapsed_ms: 5,
+        };
+        let value = serde_json::to_value(&event).unwrap();
+        assert_eq!(value["event"], "run-finished");
+        assert!(value["exit_code"].is_null());
+    }
+
+    #[test]
+    fn test_events_emit_cargo_message() {
+        set_up();
+        // Events are off, so nothing is written, but JSON is still recognised
+        assert!(emit_cargo_message(
+            r#"{"reason":"compiler-message","message":{"level":"warning"}}"#
+        ));
+        assert!(emit_cargo_message(
+            r#"{"reason":"build-finished","success":true}"#
+        ));
+        assert!(!emit_cargo_message("running 1 test"));
+    }
+
+    #[test]
+    fn test_events_stdout_is_json() {
+        set_up();
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        fs::create_dir(dir.join("sub")).unwrap();
+        fs::write(dir.join("shared.rs"), "pub fn one() -> i32 { 1 }\n").unwrap();
+        // A module outside the script's directory draws a warning, and the build fails
+        let snippet = dir.join("sub").join("events_warn.rs");
+        fs::write(
+            &snippet,
+            "#[path = \"../shared.rs\"]\nmod shared;\n\nlet one = shared::one();\n",
+        )
+        .unwrap();
+        let program = dir.join("events_error.rs");
+        fs::write(&program, "fn main() {\n    let x: i32 = \"oops\";\n}\n").unwrap();
+
+        for script in [snippet, program] {
+            let output = Command::new(env!("CARGO_BIN_EXE_thag"))
+                .args(["--message-format", "json", "--force"])
+                .arg(&script)
+                .output()
+                .unwrap();
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            assert!(!stdout.is_empty());
+            for line in stdout.lines() {
+                let event: serde_json::Value = serde_json::from_str(line)
+                    .unwrap_or_else(|e| panic!("Not a JSON line: {line:?}: {e}"));
+                assert!(event["event"].is_string(), "Not an event: {line}");
+            }
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(!stderr.is_empty());
+        }
+    }
+}
@@ -0,0 +1,154 @@
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::Path,
+        sync::Once,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+    use tempfile::TempDir;
+    use thag_rs::exec_cache::{
+        dynamic_cache_dir, dynamic_target_path, evict_to_count, evict_to_size, format_size, list,
+        parse_age, prune_older_than,
+    };
+    use thag_rs::fingerprint::fingerprint_path;
+
+    // Set environment variables before running tests
+    fn set_up() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| unsafe {
+            std::env::set_var("TEST_ENV", "1");
+            std::env::set_var("VISUAL", "cat");
+            std::env::set_var("EDITOR", "cat");
+        });
+    }
+
+    // Helper function to create a fake cached executable, optionally recording a run
+    // `offset` seconds from now
+    fn create_executable(dir: &Path, name: &str, size: usize, offset: Option<u64>) {
+        let path = dir.join(name);
+        fs::write(&path, vec![0_u8; size]).unwrap();
+        if let Some(offset) = offset {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+            fs::write(
+                fingerprint_path(&path),
+                format!(
+                    "inputs = \"0123456789abcdef\"\nmanifest = \"fedcba9876543210\"\nsource_path = \"/scripts/{name}.rs\"\nlast_run = {}\n",
+                    now.as_secs() + offset
+                ),
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_exec_cache_parse_age() {
+        set_up();
+        assert_eq!(parse_age("30d"), Ok(Duration::from_secs(30 * 86_400)));
+        assert_eq!(parse_age("12h"), Ok(Duration::from_secs(12 * 3_600)));
+        assert_eq!(parse_age("90m"), Ok(Duration::from_secs(90 * 60)));
+        assert_eq!(parse_age("45s"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_age("2w"), Ok(Duration::from_secs(14 * 86_400)));
+        assert_eq!(parse_age("7"), Ok(Duration::from_secs(7 * 86_400)));
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("5y").is_err());
+    }
+
+    #[test]
+    fn test_exec_cache_format_size() {
+        set_up();
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(2048), "2.0 KB");
+        assert_eq!(format_size(5 * 1024 * 1024 + 512 * 1024), "5.5 MB");
+    }
+
+    #[test]
+    fn test_exec_cache_list() {
+        set_up();
+        let dir = TempDir::new().unwrap();
+        create_executable(dir.path(), "older", 100, Some(1_000));
+        create_executable(dir.path(), "newer", 200, Some(2_000));
+        let release_dir = dir.path().join("release");
+        fs::create_dir(&release_dir).unwrap();
+        create_executable(&release_dir, "optimized", 300, None);
+
+        let executables = list(dir.path()).unwrap();
+        let names: Vec<_> = executables.iter().map(|e| e.name()).collect();
+        assert_eq!(names, ["newer", "older", "optimized"]);
+        assert_eq!(executables[0].size, 200);
+        assert_eq!(executables[2].profile.as_deref(), Some("release"));
+        let fingerprint = executables[0].fingerprint.as_ref().unwrap();
+        assert_eq!(
+            fingerprint.source_path.as_deref(),
+            Some(Path::new("/scripts/newer.rs"))
+        );
+        assert!(executables[2].fingerprint.is_none());
+    }
+
+    #[test]
+    fn test_exec_cache_evict_to_size() {
+        set_up();
+        let dir = TempDir::new().unwrap();
+        create_executable(dir.path(), "a", 100, Some(1_000));
+        create_executable(dir.path(), "b", 100, Some(2_000));
+        create_executable(dir.path(), "c", 100, None);
+
+        // The least recently used are evicted first, sparing the one to keep
+        let keep = dir.path().join("c");
+        let removed = evict_to_size(dir.path(), 150, Some(&keep)).unwrap();
+        let names: Vec<_> = removed.iter().map(|e| e.name()).collect();
+        assert_eq!(names, ["a", "b"]);
+        assert!(!fingerprint_path(&dir.path().join("a")).exists());
+        assert!(keep.exists());
+
+        assert!(evict_to_size(dir.path(), 150, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_exec_cache_evict_to_count() {
+        set_up();
+        let dir = TempDir::new().unwrap();
+        create_executable(dir.path(), "a", 100, Some(1_000));
+        create_executable(dir.path(), "b", 100, Some(2_000));
+        create_executable(dir.path(), "c", 100, Some(3_000));
+        create_executable(dir.path(), "d", 100, None);
+
+        // The least recently used are evicted first, sparing the one to keep
+        let keep = dir.path().join("d");
+        let removed = evict_to_count(dir.path(), 2, Some(&keep)).unwrap();
+        let names: Vec<_> = removed.iter().map(|e| e.name()).collect();
+        assert_eq!(names, ["a", "b"]);
+        assert!(!fingerprint_path(&dir.path().join("b")).exists());
+        assert!(keep.exists());
+
+        assert!(evict_to_count(dir.path(), 2, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_exec_cache_dynamic_target_path() {
+        set_up();
+        let path = dynamic_target_path("0123456789abcdef");
+        assert_eq!(path.parent(), Some(dynamic_cache_dir().as_path()));
+        assert!(path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("0123456789abcdef"));
+    }
+
+    #[test]
+    fn test_exec_cache_prune_older_than() {
+        set_up();
+        let dir = TempDir::new().unwrap();
+        create_executable(dir.path(), "recent", 100, Some(1_000));
+        create_executable(dir.path(), "stale", 100, None);
+
+        assert!(prune_older_than(dir.path(), Duration::from_secs(3_600))
+            .unwrap()
+            .is_empty());
+        let removed = prune_older_than(dir.path(), Duration::ZERO).unwrap();
+        let names: Vec<_> = removed.iter().map(|e| e.name()).collect();
+        assert_eq!(names, ["stale"]);
+        assert!(dir.path().join("recent").exists());
+    }
+}
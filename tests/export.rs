@@ -0,0 +1,96 @@
+#[cfg(test)]
+mod tests {
+    use std::{fs, process::Command, sync::Once};
+    use tempfile::TempDir;
+    use thag_rs::export::{check_destination, export_manifest, MAIN_RS};
+
+    // Set environment variables before running tests
+    fn set_up() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| unsafe {
+            std::env::set_var("TEST_ENV", "1");
+            std::env::set_var("VISUAL", "cat");
+            std::env::set_var("EDITOR", "cat");
+        });
+    }
+
+    #[test]
+    fn test_export_check_destination() {
+        set_up();
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        assert!(check_destination(&dir.join("new_project")).is_ok());
+        assert!(check_destination(dir).is_ok());
+        fs::write(dir.join("file.txt"), "content").unwrap();
+        assert!(check_destination(dir).is_err());
+        assert!(check_destination(&dir.join("file.txt")).is_err());
+    }
+
+    #[test]
+    fn test_export_manifest() {
+        set_up();
+        let generated = r#"[package]
+name = "hello"
+version = "0.0.1"
+edition = "2021"
+
+[dependencies.serde_json]
+version = "1.0"
+
+[workspace]
+
+[[bin]]
+name = "hello"
+path = "/tmp/thag_rs/hello/hello.rs"
+"#;
+        let manifest = export_manifest(generated).unwrap();
+        assert!(manifest.contains(&format!("path = \"{MAIN_RS}\"")));
+        assert!(!manifest.contains("/tmp/thag_rs"));
+        assert!(manifest.contains("[dependencies.serde_json]"));
+        assert!(manifest.contains("name = \"hello\""));
+    }
+
+    #[test]
+    fn test_export_snippet() {
+        set_up();
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let script = dir.join("export_snippet.rs");
+        fs::write(&script, "let answer = 6 * 7;\nprintln!(\"{answer}\");\n").unwrap();
+        let project = dir.join("project");
+        let output = Command::new(env!("CARGO_BIN_EXE_thag"))
+            .arg("--export")
+            .arg(&project)
+            .arg(&script)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stdout)
+        );
+        let main_rs = fs::read_to_string(project.join(MAIN_RS)).unwrap();
+        assert!(main_rs.contains("fn main()"));
+        assert!(main_rs.contains("6 * 7"));
+        let manifest = fs::read_to_string(project.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains("name = \"export_snippet\""));
+        assert_eq!(
+            fs::read_to_string(project.join(".gitignore")).unwrap(),
+            "/target\n"
+        );
+
+        // The exported project builds and runs with plain Cargo
+        let output = Command::new("cargo")
+            .args(["run", "--quiet", "--offline", "--manifest-path"])
+            .arg(project.join("Cargo.toml"))
+            .env("CARGO_TARGET_DIR", dir.join("target"))
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "42\n");
+    }
+}
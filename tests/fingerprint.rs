@@ -0,0 +1,170 @@
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path, sync::Once};
+    use tempfile::TempDir;
+    use thag_rs::fingerprint::{
+        dynamic_key, fingerprint_path, hash_str, inputs, is_stale, normalize_source, read, save,
+    };
+    use thag_rs::BuildState;
+
+    // Set environment variables before running tests
+    fn set_up() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| unsafe {
+            std::env::set_var("TEST_ENV", "1");
+            std::env::set_var("VISUAL", "cat");
+            std::env::set_var("EDITOR", "cat");
+        });
+    }
+
+    // Helper function to create a built script in a temporary directory
+    fn create_build_state(dir: &Path) -> BuildState {
+        let source_path = dir.join("fingerprint_test.rs");
+        fs::write(&source_path, "fn main() {\n    println!(\"Hello\");\n}\n").unwrap();
+        let cargo_toml_path = dir.join("Cargo.toml");
+        fs::write(&cargo_toml_path, "[package]\nname = \"fingerprint_test\"\n").unwrap();
+        let target_path = dir.join("fingerprint_test");
+        fs::write(&target_path, "").unwrap();
+        let mut build_state = BuildState {
+            source_path,
+            cargo_toml_path,
+            target_path,
+            ..Default::default()
+        };
+        build_state.fingerprint = Some(inputs(&build_state).unwrap());
+        build_state
+    }
+
+    #[test]
+    fn test_fingerprint_normalize_source() {
+        set_up();
+        assert_eq!(
+            normalize_source("fn main() {}  \r\n// comment\t\r\n\r\n\n"),
+            "fn main() {}\n// comment"
+        );
+        assert_eq!(
+            normalize_source("fn main() {}\n"),
+            normalize_source("fn main() {}")
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_hash_str() {
+        set_up();
+        assert_eq!(hash_str("abc"), hash_str("abc"));
+        assert_ne!(hash_str("abc"), hash_str("abd"));
+        assert_eq!(hash_str("").len(), 32);
+    }
+
+    #[test]
+    fn test_fingerprint_path() {
+        set_up();
+        assert_eq!(
+            fingerprint_path(Path::new("/tmp/bins/my.script")),
+            Path::new("/tmp/bins/my.script.fingerprint")
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_save_and_is_stale() {
+        set_up();
+        let dir = TempDir::new().unwrap();
+        let mut build_state = create_build_state(dir.path());
+
+        // No fingerprint has been saved yet
+        assert!(is_stale(&build_state));
+
+        save(&build_state).unwrap();
+        let recorded = read(&build_state.target_path).unwrap();
+        assert_eq!(Some(&recorded.inputs), build_state.fingerprint.as_ref());
+        assert!(!is_stale(&build_state));
+
+        // Whitespace-only changes don't count
+        fs::write(
+            &build_state.source_path,
+            "fn main() {\r\n    println!(\"Hello\");   \r\n}",
+        )
+        .unwrap();
+        build_state.fingerprint = Some(inputs(&build_state).unwrap());
+        assert!(!is_stale(&build_state));
+
+        // A different features list does
+        build_state.features = Some("foo".to_string());
+        build_state.fingerprint = Some(inputs(&build_state).unwrap());
+        assert!(is_stale(&build_state));
+        build_state.features = None;
+        build_state.fingerprint = Some(inputs(&build_state).unwrap());
+        assert!(!is_stale(&build_state));
+
+        // As does a change to the generated manifest
+        fs::write(
+            &build_state.cargo_toml_path,
+            "[package]\nname = \"fingerprint_test\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        assert!(is_stale(&build_state));
+    }
+
+    #[test]
+    fn test_fingerprint_source_change() {
+        set_up();
+        let dir = TempDir::new().unwrap();
+        let mut build_state = create_build_state(dir.path());
+        save(&build_state).unwrap();
+
+        fs::write(
+            &build_state.source_path,
+            "fn main() {\n    println!(\"Goodbye\");\n}\n",
+        )
+        .unwrap();
+        build_state.fingerprint = Some(inputs(&build_state).unwrap());
+        assert!(is_stale(&build_state));
+    }
+
+    #[test]
+    fn test_fingerprint_dynamic_key() {
+        set_up();
+        let mut build_state = BuildState {
+            cargo_manifest: Some(
+                cargo_toml::Manifest::from_str("[package]\nname = \"temp\"\n").unwrap(),
+            ),
+            is_dynamic: true,
+            ..Default::default()
+        };
+        let source = "fn main() {\n    println!(\"{}\", 6 * 7);\n}\n";
+        let key = dynamic_key(&build_state, source).unwrap();
+        assert_eq!(key.len(), 32);
+
+        // Only a change that affects the build changes the key
+        assert_eq!(
+            dynamic_key(&build_state, &source.replace('\n', "  \n")).unwrap(),
+            key
+        );
+        assert_ne!(
+            dynamic_key(&build_state, &source.replace('6', "7")).unwrap(),
+            key
+        );
+        build_state.profile = Some("release".to_string());
+        assert_ne!(dynamic_key(&build_state, source).unwrap(), key);
+        build_state.profile = None;
+        build_state.cargo_manifest = Some(
+            cargo_toml::Manifest::from_str("[package]\nname = \"temp\"\nedition = \"2024\"\n")
+                .unwrap(),
+        );
+        assert_ne!(dynamic_key(&build_state, source).unwrap(), key);
+    }
+
+    #[test]
+    fn test_fingerprint_save_dynamic() {
+        set_up();
+        let dir = TempDir::new().unwrap();
+        let mut build_state = create_build_state(dir.path());
+        build_state.is_dynamic = true;
+        save(&build_state).unwrap();
+
+        // The source of a dynamic script is not recorded, as it is overwritten by the next one
+        let recorded = read(&build_state.target_path).unwrap();
+        assert_eq!(recorded.inputs, build_state.fingerprint.unwrap());
+        assert!(recorded.source_path.is_none());
+    }
+}
@@ -0,0 +1,154 @@
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path, sync::Once};
+    use tempfile::TempDir;
+    use thag_rs::inline::inline_crate;
+
+    // Set environment variables before running tests
+    fn set_up() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| unsafe {
+            std::env::set_var("TEST_ENV", "1");
+            std::env::set_var("VISUAL", "cat");
+            std::env::set_var("EDITOR", "cat");
+        });
+    }
+
+    fn write(dir: &Path, path: &str, content: &str) {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_inline_modules() {
+        set_up();
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        write(
+            dir,
+            "Cargo.toml",
+            "[package]\nname = \"greeter\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        );
+        write(
+            dir,
+            "src/main.rs",
+            "mod net;\n#[path = \"extra/util.rs\"]\npub(crate) mod util;\nfn main() {\n    println!(\"{}\", net::proto::ID);\n}\n",
+        );
+        write(dir, "src/net/mod.rs", "pub mod proto;\n");
+        write(
+            dir,
+            "src/net/proto.rs",
+            "pub const ID: u8 = 7;\nconst S: &str = \"not a mod;\";\n",
+        );
+        write(dir, "src/extra/util.rs", "pub fn f() {}\n");
+
+        let inlined = inline_crate(dir).unwrap();
+        assert_eq!(inlined.name, "greeter");
+        assert!(inlined.issues.is_empty(), "{:?}", inlined.issues);
+        let script = &inlined.script;
+        assert!(script.starts_with("/*[toml]\n[package]\nedition = \"2021\"\n*/\n"));
+        assert!(script.contains("mod net {\n// Inlined from src/net/proto.rs\npub mod proto {\n"));
+        assert!(script.contains("pub(crate) mod util {\npub fn f() {}\n}"));
+        assert!(!script.contains("#[path"));
+        assert!(!script.contains("mod net;"));
+        let (_, source) = script.split_once("*/").unwrap();
+        assert!(syn::parse_file(source).is_ok());
+    }
+
+    #[test]
+    fn test_inline_modules_nested_and_attributes() {
+        set_up();
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        write(
+            dir,
+            "Cargo.toml",
+            "[package]\nname = \"nested\"\nversion = \"0.1.0\"\n",
+        );
+        write(
+            dir,
+            "src/main.rs",
+            r#"mod a; mod b;
+mod outer {
+    /// The inner module
+    #[allow(dead_code)]
+    #[path = "in.rs"]
+    pub mod inner;
+}
+fn main() {
+    println!("{} {} {}", a::A, b::B, outer::inner::C);
+}
+"#,
+        );
+        write(dir, "src/a.rs", "pub const A: u8 = 1;\n");
+        write(dir, "src/b.rs", "pub const B: u8 = 2;");
+        write(dir, "src/outer/in.rs", "pub const C: u8 = 3;\n");
+
+        let inlined = inline_crate(dir).unwrap();
+        assert!(inlined.issues.is_empty(), "{:?}", inlined.issues);
+        let script = &inlined.script;
+        assert!(script.contains("mod a {\npub const A: u8 = 1;\n}"));
+        assert!(script.contains("mod b {\npub const B: u8 = 2;\n}"));
+        assert!(script.contains(
+            "    // Inlined from src/outer/in.rs\n    /// The inner module\n    #[allow(dead_code)]\n    pub mod inner {\npub const C: u8 = 3;\n    }"
+        ));
+        assert!(!script.contains("#[path"));
+        let (_, source) = script.split_once("*/").unwrap_or(("", script));
+        assert!(syn::parse_file(source).is_ok(), "{script}");
+    }
+
+    #[test]
+    fn test_inline_reports_unrepresentable() {
+        set_up();
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        write(
+            dir,
+            "Cargo.toml",
+            r#"[package]
+name = "tool"
+version.workspace = true
+edition = "2021"
+
+[dependencies]
+serde = { workspace = true }
+itoa = "1"
+local = { path = "local" }
+
+[build-dependencies]
+cc = "1"
+"#,
+        );
+        write(dir, "src/main.rs", "mod gone;\nfn main() {}\n");
+        write(dir, "src/bin/other.rs", "fn main() {}\n");
+        write(dir, "build.rs", "fn main() {}\n");
+        fs::create_dir_all(dir.join("local")).unwrap();
+
+        let inlined = inline_crate(dir).unwrap();
+        let issues = inlined.issues.join("\n");
+        assert!(issues.contains("multiple binaries"));
+        assert!(issues.contains("Build script build.rs"));
+        assert!(issues.contains("package.version is inherited from the workspace"));
+        assert!(issues.contains("Dependency serde"));
+        assert!(issues.contains("mod gone;"));
+
+        let script = &inlined.script;
+        assert!(script.contains("itoa = \"1\""));
+        assert!(!script.contains("serde"));
+        assert!(!script.contains("cc = "));
+        let local = dir.join("local").canonicalize().unwrap();
+        assert!(script.contains(&format!("path = \"{}\"", local.display())));
+        assert!(script.contains("mod gone;"));
+    }
+
+    #[test]
+    fn test_inline_workspace_is_error() {
+        set_up();
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        assert!(inline_crate(dir).is_err());
+        write(dir, "Cargo.toml", "[workspace]\nmembers = [\"a\"]\n");
+        assert!(inline_crate(dir).is_err());
+    }
+}
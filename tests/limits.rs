@@ -0,0 +1,112 @@
+#[cfg(test)]
+mod tests {
+    use std::{process::Command, sync::Once, time::Duration};
+    use thag_rs::errors::{ThagError, LIMIT_EXCEEDED_EXIT_CODE};
+    use thag_rs::limits::{parse_duration, parse_size, ResourceLimits};
+
+    // Set environment variables before running tests
+    fn set_up() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| unsafe {
+            std::env::set_var("TEST_ENV", "1");
+            std::env::set_var("VISUAL", "cat");
+            std::env::set_var("EDITOR", "cat");
+        });
+    }
+
+    #[test]
+    fn test_limits_parse_duration() {
+        set_up();
+        assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("3d").is_err());
+    }
+
+    #[test]
+    fn test_limits_parse_size() {
+        set_up();
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("4K"), Ok(4096));
+        assert_eq!(parse_size("512M"), Ok(512 * 1024 * 1024));
+        assert_eq!(parse_size("512mb"), Ok(512 * 1024 * 1024));
+        assert_eq!(parse_size("2G"), Ok(2 * 1024 * 1024 * 1024));
+        assert!(parse_size("0M").is_err());
+        assert!(parse_size("2T").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_limits_run_timeout() {
+        set_up();
+        let limits = ResourceLimits {
+            timeout: Some(Duration::from_millis(200)),
+            ..ResourceLimits::default()
+        };
+        let mut command = Command::new("sleep");
+        command.arg("5");
+        let (output, usage) = limits.run(&mut command, true).unwrap();
+        assert!(usage.timed_out);
+        let err = limits.check(output.status, &usage).unwrap_err();
+        assert!(matches!(err, ThagError::LimitExceeded(_)));
+        assert_eq!(err.exit_code(), LIMIT_EXCEEDED_EXIT_CODE);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_limits_run_within_limits() {
+        set_up();
+        let limits = ResourceLimits {
+            timeout: Some(Duration::from_secs(10)),
+            max_memory: Some(1024 * 1024 * 1024),
+            max_cpu: Some(10),
+        };
+        let mut command = Command::new("echo");
+        command.arg("hello");
+        let (output, usage) = limits.run(&mut command, true).unwrap();
+        assert!(!usage.timed_out);
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "hello\n");
+        assert!(limits.check(output.status, &usage).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_limits_check_signal_not_from_limit() {
+        set_up();
+        let limits = ResourceLimits {
+            timeout: Some(Duration::from_secs(10)),
+            max_memory: Some(1024 * 1024 * 1024),
+            max_cpu: Some(10),
+        };
+        for signal in ["ABRT", "SEGV", "KILL"] {
+            let mut command = Command::new("sh");
+            command.args(["-c", &format!("kill -{signal} $$")]);
+            let (output, usage) = limits.run(&mut command, true).unwrap();
+            assert!(!output.status.success());
+            assert!(
+                limits.check(output.status, &usage).is_ok(),
+                "SIG{signal} was put down to a limit"
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_limits_check_cpu_signal() {
+        set_up();
+        let limits = ResourceLimits {
+            max_cpu: Some(10),
+            ..ResourceLimits::default()
+        };
+        let mut command = Command::new("sh");
+        command.args(["-c", "kill -XCPU $$"]);
+        let (output, usage) = limits.run(&mut command, true).unwrap();
+        let err = limits.check(output.status, &usage).unwrap_err();
+        assert!(matches!(err, ThagError::LimitExceeded(_)));
+    }
+}
@@ -0,0 +1,141 @@
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use std::{path::Path, sync::Once};
+    use thag_rs::line_map::{LineMap, Position};
+
+    // Set environment variables before running tests
+    fn set_up() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| unsafe {
+            std::env::set_var("TEST_ENV", "1");
+            std::env::set_var("VISUAL", "cat");
+            std::env::set_var("EDITOR", "cat");
+        });
+    }
+
+    const SCRIPT: &str = r#"#!/usr/bin/env thag
+//! A snippet with an error
+#![allow(unused)]
+let x = 5;
+
+let y: String = x + 1; // "not // a comment"
+println!("{y}");
+"#;
+
+    const GENERATED: &str = r#"#![allow(unused_imports, unused_macros, unused_variables, dead_code)]
+#![allow(unused)]
+use std::error::Error;
+use std::io;
+use std::io::prelude::*;
+///Wrapped snippet in main method to make it a program.
+#[allow(clippy::unnecessary_wraps)]
+fn main() -> Result<(), Box<dyn Error>> {
+    {
+        let x = 5;
+        let y: String = x + 1;
+        println!("{y}");
+    }
+    Ok(())
+}
+"#;
+
+    fn line_map() -> LineMap {
+        set_up();
+        let mut line_map = LineMap::new(Path::new("snip.rs"), SCRIPT);
+        line_map.align(Path::new("/tmp/thag_rs/snip/snip.rs"), GENERATED);
+        line_map
+    }
+
+    #[test]
+    fn test_line_map_lines() {
+        let line_map = line_map();
+        assert_eq!(line_map.script_line(2), Some(3));
+        assert_eq!(line_map.script_line(10), Some(4));
+        assert_eq!(line_map.script_line(11), Some(6));
+        assert_eq!(line_map.script_line(12), Some(7));
+        // The wrapper's own lines
+        assert_eq!(line_map.script_line(8), None);
+        assert_eq!(line_map.script_line(14), None);
+    }
+
+    #[test]
+    fn test_line_map_position() {
+        let line_map = line_map();
+        // `x + 1`
+        assert_eq!(
+            line_map.script_position(Position {
+                line: 11,
+                column: 25
+            }),
+            Some(Position {
+                line: 6,
+                column: 17
+            })
+        );
+        assert_eq!(
+            line_map.script_position(Position {
+                line: 14,
+                column: 5
+            }),
+            None
+        );
+    }
+
+    #[test]
+    fn test_line_map_rewrite_diagnostic() {
+        let line_map = line_map();
+        let mut diagnostic = json!({
+            "message": "mismatched types",
+            "spans": [{
+                "file_name": "snip.rs",
+                "line_start": 11,
+                "column_start": 25,
+                "line_end": 11,
+                "column_end": 30,
+                "is_primary": true,
+                "text": [],
+                "expansion": null,
+            }],
+            "children": [{
+                "message": "expected due to this",
+                "spans": [{
+                    "file_name": "other.rs",
+                    "line_start": 11,
+                    "column_start": 16,
+                    "line_end": 11,
+                    "column_end": 22,
+                }],
+                "children": [],
+                "rendered": null,
+            }],
+            "rendered": "error[E0308]: mismatched types\n  --> snip.rs:11:25\n   |\n11 |         let y: String = x + 1;\n   |                         ^^^^^ expected `String`, found integer\n",
+        });
+        line_map.rewrite_diagnostic(&mut diagnostic);
+
+        let span = &diagnostic["spans"][0];
+        assert_eq!(span["file_name"], "snip.rs");
+        assert_eq!(span["line_start"], 6);
+        assert_eq!(span["column_start"], 17);
+        assert_eq!(span["column_end"], 22);
+        assert_eq!(
+            span["text"][0]["text"],
+            r#"let y: String = x + 1; // "not // a comment""#
+        );
+        // Not the generated source, so left alone
+        assert_eq!(diagnostic["children"][0]["spans"][0]["line_start"], 11);
+
+        let rendered = diagnostic["rendered"].as_str().unwrap();
+        assert!(rendered.contains("  --> snip.rs:6:17\n"));
+        assert!(rendered.contains("\n 6 |         let y"));
+    }
+
+    #[test]
+    fn test_line_map_rewrite_rendered_ansi() {
+        let line_map = line_map();
+        let rendered = "\u{1b}[0m \u{1b}[0m\u{1b}[0m\u{1b}[1m\u{1b}[38;5;12m--> \u{1b}[0m\u{1b}[0msnip.rs:12:20\u{1b}[0m\n\u{1b}[0m\u{1b}[1m\u{1b}[38;5;12m12\u{1b}[0m\u{1b}[0m \u{1b}[0m\u{1b}[0m\u{1b}[1m\u{1b}[38;5;12m|\u{1b}[0m";
+        let rewritten = line_map.rewrite_rendered(rendered);
+        assert!(rewritten.contains("snip.rs:7:"));
+        assert!(rewritten.contains("\u{1b}[38;5;12m 7\u{1b}[0m"));
+    }
+}
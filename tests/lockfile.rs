@@ -0,0 +1,113 @@
+#[cfg(test)]
+mod tests {
+    use cargo_toml::{Dependency, Manifest};
+    use semver::VersionReq;
+    use std::{collections::BTreeMap, io::Write, path::Path, sync::Once};
+    use tempfile::NamedTempFile;
+    use thag_rs::lockfile::{check, lock_path, pin_inferred, read_locked_versions};
+
+    // Set environment variables before running tests
+    fn set_up() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| unsafe {
+            std::env::set_var("TEST_ENV", "1");
+            std::env::set_var("VISUAL", "cat");
+            std::env::set_var("EDITOR", "cat");
+        });
+    }
+
+    const CARGO_LOCK: &str = r#"# This file is automatically @generated by Cargo.
+# It is not intended for manual editing.
+version = 4
+
+[[package]]
+name = "example"
+version = "0.0.1"
+dependencies = [
+ "serde",
+]
+
+[[package]]
+name = "serde"
+version = "1.0.210"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+
+    // Helper function to create a temporary lockfile
+    fn create_lockfile() -> NamedTempFile {
+        set_up();
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", CARGO_LOCK).unwrap();
+        temp_file
+    }
+
+    fn manifest_with_deps(deps: &str) -> Manifest {
+        let toml = format!(
+            r#"[package]
+name = "example"
+version = "0.0.1"
+edition = "2021"
+
+[dependencies]
+{deps}
+
+[[bin]]
+name = "example"
+path = "example.rs"
+"#
+        );
+        Manifest::from_str(&toml).unwrap()
+    }
+
+    #[test]
+    fn test_lockfile_lock_path() {
+        set_up();
+        let path = lock_path(Path::new("/some/scripts/hello.rs"));
+        assert_eq!(path, Path::new("/some/scripts/hello.thag.lock"));
+    }
+
+    #[test]
+    fn test_lockfile_read_locked_versions() {
+        let lockfile = create_lockfile();
+        let locked_versions = read_locked_versions(lockfile.path()).unwrap();
+        assert_eq!(locked_versions.len(), 2);
+        assert_eq!(locked_versions["serde"][0].to_string(), "1.0.210");
+    }
+
+    #[test]
+    fn test_lockfile_check_agrees() {
+        let lockfile = create_lockfile();
+        let locked_versions = read_locked_versions(lockfile.path()).unwrap();
+        let manifest = manifest_with_deps(r#"serde = "1.0""#);
+        assert!(check(&manifest, &locked_versions, lockfile.path()).is_ok());
+    }
+
+    #[test]
+    fn test_lockfile_check_disagrees() {
+        let lockfile = create_lockfile();
+        let locked_versions = read_locked_versions(lockfile.path()).unwrap();
+        let manifest = manifest_with_deps(
+            r#"serde = "1.0.215"
+regex = "1""#,
+        );
+        let err = check(&manifest, &locked_versions, lockfile.path())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("regex is not in the lockfile"));
+        assert!(err.contains("serde = \"^1.0.215\" does not match locked version 1.0.210"));
+        assert!(err.contains("--update-lock"));
+    }
+
+    #[test]
+    fn test_lockfile_pin_inferred() {
+        let lockfile = create_lockfile();
+        let locked_versions = read_locked_versions(lockfile.path()).unwrap();
+        let mut rs_dep_map = BTreeMap::new();
+        rs_dep_map.insert(
+            "serde".to_string(),
+            Dependency::Simple(VersionReq::parse("1.0.228").unwrap()),
+        );
+        pin_inferred(&["serde".to_string()], &mut rs_dep_map, &locked_versions);
+        assert_eq!(rs_dep_map["serde"].req().to_string(), "^1.0.210");
+    }
+}
@@ -0,0 +1,55 @@
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Once};
+    use thag_rs::lookup_cache::{cache_dir, get, put, CachedCrate};
+
+    // Set environment variables before running tests
+    fn set_up() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| unsafe {
+            std::env::set_var("TEST_ENV", "1");
+            std::env::set_var("VISUAL", "cat");
+            std::env::set_var("EDITOR", "cat");
+        });
+    }
+
+    #[test]
+    fn test_lookup_cache_put_get() {
+        set_up();
+        let crate_name = "thag_test_lookup_cache_put_get";
+        let cached = CachedCrate {
+            name: "thag-test-lookup-cache-put-get".to_string(),
+            version: Some("1.2.3".to_string()),
+            yanked: false,
+            prerelease: false,
+            features: Some(vec!["derive".to_string(), "std".to_string()]),
+            cached_at: 0,
+        };
+        put(crate_name, &cached);
+
+        let found = get(crate_name).expect("Lookup was not cached");
+        assert_eq!(found.name, cached.name);
+        assert_eq!(found.version.as_deref(), Some("1.2.3"));
+        assert_eq!(found.features, cached.features);
+        assert!(found.cached_at > 0);
+
+        let _ = fs::remove_file(cache_dir().join(format!("{crate_name}.toml")));
+    }
+
+    #[test]
+    fn test_lookup_cache_expired() {
+        set_up();
+        let crate_name = "thag_test_lookup_cache_expired";
+        fs::create_dir_all(cache_dir()).expect("Failed to create lookup cache dir");
+        let path = cache_dir().join(format!("{crate_name}.toml"));
+        fs::write(
+            &path,
+            "name = \"thag_test_lookup_cache_expired\"\nversion = \"0.1.0\"\nyanked = false\nprerelease = false\ncached_at = 1\n",
+        )
+        .expect("Failed to write lookup cache entry");
+
+        assert!(get(crate_name).is_none());
+
+        let _ = fs::remove_file(path);
+    }
+}
@@ -5,8 +5,12 @@ mod tests {
     use std::iter;
     use std::{collections::BTreeMap, path::PathBuf, str::FromStr, sync::Once, time::Instant};
     use thag_rs::code_utils::to_ast;
-    use thag_rs::manifest::{self, capture_dep, cargo_lookup, configure_default, extract, merge};
-    use thag_rs::{find_crates, find_metadata, BuildState};
+    use thag_rs::config::DependencyInference;
+    use thag_rs::manifest::{
+        self, capture_dep, cargo_lookup, configure_default, enable_feature, extract,
+        is_unreachable, merge, ScriptSettings,
+    };
+    use thag_rs::{find_crates, find_metadata, BuildState, Cli, V};
     use thag_styling::{ColorInitStrategy, TermAttributes};
 
     // Set environment variables before running tests
@@ -195,6 +199,23 @@ mod tests {
         assert!(result.is_none());
     }
 
+    #[test]
+    fn test_manifest_lookup_not_found_is_not_unreachable() {
+        set_up();
+        // The index answers 404 for the `foo_bar` variant of a crate named `foo-bar`, which
+        // must not be taken to mean that the index is unreachable.
+        let not_found = ureq::Error::Status(
+            404,
+            ureq::Response::new(404, "Not Found", "").expect("Failed to build response"),
+        );
+        assert!(!is_unreachable(&cargo_lookup::error::Error::Request(
+            Box::new(not_found)
+        )));
+
+        let io_error = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset");
+        assert!(is_unreachable(&cargo_lookup::error::Error::Io(io_error)));
+    }
+
     fn setup_build_state(source: &str) -> BuildState {
         let mut build_state = BuildState {
             source_path: PathBuf::from("dummy_test.rs"),
@@ -523,6 +544,69 @@ mod tests {
         assert!(dependencies.contains_key("bar"));
     }
 
+    #[test]
+    fn test_manifest_extract_frontmatter() {
+        set_up();
+        let source_code = r#"#!/usr/bin/env -S cargo +nightly -Zscript
+---cargo
+[dependencies]
+foo = "0.1"
+---
+/*[toml]
+[dependencies]
+bar = "0.2"
+*/
+fn main() {}
+"#;
+        let manifest = extract(source_code, Instant::now()).unwrap();
+
+        // The frontmatter takes precedence over a toml block
+        let dependencies = manifest.dependencies;
+        assert!(dependencies.contains_key("foo"));
+        assert!(!dependencies.contains_key("bar"));
+    }
+
+    #[test]
+    fn test_manifest_extract_edition() {
+        set_up();
+        let source_code = r#"
+/*[toml]
+[package]
+name = "edition_2024"
+edition = "2024"
+*/
+"#;
+        let manifest = extract(source_code, Instant::now()).unwrap();
+        assert_eq!(manifest::edition(&manifest), Edition::E2024);
+
+        // A package without an edition gets the default rather than cargo_toml's 2015
+        let source_code = r#"
+/*[toml]
+[package]
+name = "no_edition"
+*/
+"#;
+        let manifest = extract(source_code, Instant::now()).unwrap();
+        assert_eq!(manifest::edition(&manifest), Edition::E2021);
+        assert_eq!(manifest::parse_edition("2018"), Some(Edition::E2018));
+        assert_eq!(manifest::parse_edition("2023"), None);
+    }
+
+    #[test]
+    fn test_manifest_configure_default_edition() {
+        set_up();
+        let build_state = BuildState {
+            source_stem: "example".to_string(),
+            source_name: "example.rs".to_string(),
+            target_dir_path: std::path::PathBuf::from("/tmp"),
+            edition: Some(Edition::E2024),
+            ..Default::default()
+        };
+
+        let manifest = configure_default(&build_state).unwrap();
+        assert_eq!(manifest::edition(&manifest), Edition::E2024);
+    }
+
     #[test]
     fn test_manifest_target_specific_deps_not_duplicated() -> Result<(), Box<dyn std::error::Error>>
     {
@@ -578,4 +662,116 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_manifest_script_settings() {
+        set_up();
+        init_logger();
+        let rs_source = r#"/*[toml]
+[package.metadata.thag]
+infer = "minimal"
+unquote = true
+features = ["simd", "std"]
+verbosity = "quiet"
+*/
+fn main() {}
+"#;
+        let rs_manifest = extract(rs_source, Instant::now()).unwrap();
+        let settings = manifest::script_settings(&rs_manifest).unwrap().unwrap();
+        assert_eq!(
+            settings,
+            ScriptSettings {
+                infer: Some(DependencyInference::Min),
+                unquote: Some(true),
+                multimain: None,
+                features: Some(vec!["simd".to_string(), "std".to_string()]),
+                verbosity: Some(V::Quiet),
+            }
+        );
+
+        // Script settings fill in what the command line doesn't specify
+        let mut cli = Cli {
+            features: Some("fast".to_string()),
+            verbose: 1,
+            ..Default::default()
+        };
+        settings.apply(&mut cli);
+        assert_eq!(cli.infer, Some(DependencyInference::Min));
+        assert_eq!(cli.unquote, Some(true));
+        assert!(!cli.multimain);
+        assert_eq!(cli.features.as_deref(), Some("fast"));
+        assert_eq!((cli.verbose, cli.quiet), (1, 0));
+
+        let mut cli = Cli {
+            unquote: Some(false),
+            ..Default::default()
+        };
+        settings.apply(&mut cli);
+        assert_eq!(cli.unquote, Some(false));
+        assert_eq!(cli.features.as_deref(), Some("simd,std"));
+        assert_eq!(cli.quiet, 1);
+    }
+
+    #[test]
+    fn test_manifest_script_settings_invalid() {
+        set_up();
+        init_logger();
+        let rs_manifest = extract("fn main() {}", Instant::now()).unwrap();
+        assert_eq!(manifest::script_settings(&rs_manifest).unwrap(), None);
+
+        let rs_source = "/*[toml]\n[package.metadata.thag]\nunknown = 1\n*/\nfn main() {}\n";
+        let rs_manifest = extract(rs_source, Instant::now()).unwrap();
+        assert!(manifest::script_settings(&rs_manifest).is_err());
+    }
+
+    #[test]
+    fn test_manifest_merge_unnamed_package() {
+        set_up();
+        init_logger();
+        let rs_source = r#"/*[toml]
+[package.metadata.thag]
+multimain = true
+*/
+fn main() {}
+"#;
+        let mut build_state = BuildState {
+            source_stem: "unnamed_package".to_string(),
+            source_name: "unnamed_package.rs".to_string(),
+            rs_manifest: Some(extract(rs_source, Instant::now()).unwrap()),
+            ..Default::default()
+        };
+        merge(&mut build_state, rs_source).unwrap();
+        let package = build_state.cargo_manifest.unwrap().package.unwrap();
+        assert_eq!(package.name, "unnamed_package");
+    }
+
+    #[test]
+    fn test_manifest_enable_feature() {
+        set_up();
+        let mut manifest = Manifest::from_str(
+            r#"[package]
+name = "enable_feature"
+
+[dependencies]
+serde_json = "1.0"
+serde = { version = "1.0", features = ["derive"] }
+"#,
+        )
+        .unwrap();
+        enable_feature(&mut manifest, "serde_json", "preserve_order");
+        enable_feature(&mut manifest, "serde", "derive");
+        enable_feature(&mut manifest, "serde", "rc");
+        enable_feature(&mut manifest, "regex", "unicode");
+
+        let Some(Dependency::Detailed(serde_json)) = manifest.dependencies.get("serde_json") else {
+            panic!("serde_json should have become a detailed dependency");
+        };
+        assert_eq!(serde_json.features, ["preserve_order"]);
+        assert!(serde_json.version.is_some());
+        assert_eq!(
+            manifest.dependencies["serde"].req_features(),
+            ["derive", "rc"]
+        );
+        assert!(!manifest.dependencies.contains_key("regex"));
+    }
 }
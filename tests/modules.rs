@@ -0,0 +1,183 @@
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path, sync::Once};
+    use tempfile::TempDir;
+    use thag_rs::ast::{find_crates, find_metadata};
+    use thag_rs::code_utils::{remove_file_modules, to_ast};
+    use thag_rs::modules::{find_modules, infer_module_crates, mirror_modules};
+
+    // Set environment variables before running tests
+    fn set_up() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| unsafe {
+            std::env::set_var("TEST_ENV", "1");
+            std::env::set_var("VISUAL", "cat");
+            std::env::set_var("EDITOR", "cat");
+        });
+    }
+
+    fn write(dir: &Path, relative_path: &str, content: &str) {
+        let path = dir.join(relative_path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    // Helper function to create a multi-file script covering each module layout
+    fn create_multi_file_script() -> TempDir {
+        set_up();
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        write(
+            dir,
+            "script.rs",
+            r#"mod foo;
+mod bar;
+#[path = "other/thing.rs"]
+mod thing;
+mod inline {
+    mod nested;
+}
+println!("{}", foo::hello());
+"#,
+        );
+        write(
+            dir,
+            "foo.rs",
+            "mod child;\nuse regex::Regex;\npub fn hello() -> String { String::new() }\n",
+        );
+        write(dir, "foo/child.rs", "use serde_json::Value;\n");
+        write(dir, "bar/mod.rs", "pub mod baz;\n");
+        write(dir, "bar/baz.rs", "pub fn deep() {}\n");
+        write(
+            dir,
+            "other/thing.rs",
+            "#[path = \"sibling.rs\"]\nmod sibling;\n",
+        );
+        write(dir, "other/sibling.rs", "pub fn sibling() {}\n");
+        write(dir, "inline/nested.rs", "pub fn nested() {}\n");
+        temp_dir
+    }
+
+    #[test]
+    fn test_modules_find_modules() {
+        let temp_dir = create_multi_file_script();
+        let modules = find_modules(&temp_dir.path().join("script.rs"));
+        let mut relative_paths: Vec<String> = modules
+            .iter()
+            .map(|module| {
+                module
+                    .relative_path
+                    .as_ref()
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            })
+            .collect();
+        relative_paths.sort();
+        assert_eq!(
+            relative_paths,
+            vec![
+                "bar/baz.rs",
+                "bar/mod.rs",
+                "foo.rs",
+                "foo/child.rs",
+                "inline/nested.rs",
+                "other/sibling.rs",
+                "other/thing.rs",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_modules_find_modules_none() {
+        set_up();
+        let temp_dir = TempDir::new().unwrap();
+        write(temp_dir.path(), "script.rs", "println!(\"no modules\");\n");
+        assert!(find_modules(&temp_dir.path().join("script.rs")).is_empty());
+    }
+
+    #[test]
+    fn test_modules_infer_module_crates() {
+        let temp_dir = create_multi_file_script();
+        let script_path = temp_dir.path().join("script.rs");
+        let source = fs::read_to_string(&script_path).unwrap();
+        let ast = to_ast(&script_path.to_string_lossy(), &source).unwrap();
+        let mut crates_finder = find_crates(&ast);
+        let mut metadata_finder = find_metadata(&ast);
+
+        infer_module_crates(
+            &find_modules(&script_path),
+            &mut crates_finder,
+            &mut metadata_finder,
+        );
+        assert!(crates_finder.crates.contains(&"regex".to_string()));
+        assert!(crates_finder.crates.contains(&"serde_json".to_string()));
+        assert!(metadata_finder
+            .mods_to_exclude
+            .contains(&"child".to_string()));
+    }
+
+    #[test]
+    fn test_modules_mirror_modules() {
+        let temp_dir = create_multi_file_script();
+        let target_dir = TempDir::new().unwrap();
+        let modules = find_modules(&temp_dir.path().join("script.rs"));
+        mirror_modules(&modules, target_dir.path()).unwrap();
+        assert_eq!(
+            fs::read_to_string(target_dir.path().join("bar/baz.rs")).unwrap(),
+            "pub fn deep() {}\n"
+        );
+        assert!(target_dir.path().join("other/sibling.rs").exists());
+    }
+
+    #[test]
+    fn test_modules_mirror_modules_absolute_path() {
+        set_up();
+        let outside = TempDir::new().unwrap();
+        write(outside.path(), "shared.rs", "mod helper;\n");
+        write(outside.path(), "helper.rs", "pub fn help() {}\n");
+        write(outside.path(), "escaped.rs", "pub fn escaped() {}\n");
+        let temp_dir = TempDir::new().unwrap();
+        let script_dir = temp_dir.path().join("scripts");
+        let shared = outside.path().join("shared.rs");
+        write(
+            &script_dir,
+            "script.rs",
+            &format!(
+                "#[path = {:?}]\nmod shared;\n#[path = \"../escaped.rs\"]\nmod escaped;\nfn main() {{}}\n",
+                shared.display().to_string()
+            ),
+        );
+        fs::copy(
+            outside.path().join("escaped.rs"),
+            temp_dir.path().join("escaped.rs"),
+        )
+        .unwrap();
+
+        let modules = find_modules(&script_dir.join("script.rs"));
+        assert_eq!(modules.len(), 3);
+        // The module with an absolute path and the module it declares need no copy
+        assert!(modules[0].absolute && modules[1].absolute);
+        assert!(modules[0].relative_path.is_none());
+        // A relative path that escapes the script's directory can't be mirrored
+        assert!(!modules[2].absolute);
+        assert!(modules[2].relative_path.is_none());
+
+        let target_dir = TempDir::new().unwrap();
+        mirror_modules(&modules, target_dir.path()).unwrap();
+        assert_eq!(fs::read_dir(target_dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_modules_remove_file_modules() {
+        set_up();
+        let source = "{\n#[path = \"x.rs\"]\nmod foo;\nmod inline {}\nprintln!(\"hi\");\n}";
+        let mut ast = to_ast("snippet", source).unwrap();
+        let mod_decls = remove_file_modules(&mut ast);
+        assert!(mod_decls.contains("mod foo"));
+        assert!(mod_decls.contains("path"));
+        let remaining = quote::quote!(#ast).to_string();
+        assert!(!remaining.contains("mod foo"));
+        assert!(remaining.contains("mod inline"));
+    }
+}
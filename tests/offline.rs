@@ -0,0 +1,62 @@
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Once};
+    use tempfile::TempDir;
+    use thag_rs::offline::{local_package, parse_index_cache};
+
+    // Set environment variables before running tests
+    fn set_up() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| unsafe {
+            std::env::set_var("TEST_ENV", "1");
+            std::env::set_var("VISUAL", "cat");
+            std::env::set_var("EDITOR", "cat");
+        });
+    }
+
+    const RELEASE_1: &str = r#"{"name":"fakecrate","vers":"1.0.0","deps":[],"cksum":"","features":{"derive":[]},"yanked":false}"#;
+    const RELEASE_2: &str = r#"{"name":"fakecrate","vers":"1.1.0","deps":[],"cksum":"","features":{"derive":[],"std":[]},"yanked":false}"#;
+
+    // Helper function to build an index cache file in Cargo's format
+    fn index_cache_bytes() -> Vec<u8> {
+        let mut bytes = vec![3, 2, 0, 0, 0];
+        for part in ["etag-revision", "1.0.0", RELEASE_1, "1.1.0", RELEASE_2] {
+            bytes.extend_from_slice(part.as_bytes());
+            bytes.push(0);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_offline_parse_index_cache() {
+        set_up();
+        let index = parse_index_cache(&index_cache_bytes()).expect("Failed to parse index cache");
+        assert_eq!(index, format!("{RELEASE_1}\n{RELEASE_2}"));
+    }
+
+    #[test]
+    fn test_offline_parse_index_cache_empty() {
+        set_up();
+        assert!(parse_index_cache(&[3, 2, 0, 0, 0]).is_none());
+        assert!(parse_index_cache(&[]).is_none());
+    }
+
+    #[test]
+    fn test_offline_local_package_from_registry_cache() {
+        set_up();
+        let cargo_home = TempDir::new().expect("Failed to create temp dir");
+        let cache_dir = cargo_home
+            .path()
+            .join("registry/index/index.crates.io-0000000000000000/.cache/fa/ke");
+        fs::create_dir_all(&cache_dir).expect("Failed to create cache dir");
+        fs::write(cache_dir.join("fakecrate"), index_cache_bytes()).expect("Failed to write cache");
+        unsafe {
+            std::env::set_var("CARGO_HOME", cargo_home.path());
+        }
+
+        let package = local_package("fakecrate").expect("Crate not found in local index");
+        assert_eq!(package.name(), "fakecrate");
+        assert_eq!(package.releases().len(), 2);
+        assert!(local_package("no_such_fake_crate").is_none());
+    }
+}
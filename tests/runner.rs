@@ -0,0 +1,78 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Once;
+    use thag_rs::config::DependencyInference;
+    use thag_rs::Runner;
+
+    // Set environment variables before running tests
+    fn set_up() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| unsafe {
+            std::env::set_var("TEST_ENV", "1");
+            std::env::set_var("VISUAL", "cat");
+            std::env::set_var("EDITOR", "cat");
+        });
+    }
+
+    #[test]
+    fn test_runner_missing_source() {
+        set_up();
+        assert!(Runner::new().run().is_err());
+    }
+
+    #[test]
+    fn test_runner_invalid_options() {
+        set_up();
+        assert!(Runner::new().script("no_such_script.rs").run().is_err());
+        assert!(Runner::new().script("no_rs_suffix").run().is_err());
+        assert!(Runner::new()
+            .expression("1 + 1")
+            .edition("2027")
+            .run()
+            .is_err());
+    }
+
+    #[test]
+    fn test_runner_expression_capture_output() {
+        set_up();
+        let output = Runner::new()
+            .expression(
+                r#"{
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    println!("runner says {}", args.join(" "));
+    eprintln!("to stderr");
+    std::process::exit(3);
+}"#,
+            )
+            .args(["hello", "world"])
+            .infer(DependencyInference::None)
+            .force(true)
+            .capture_output(true)
+            .run()
+            .expect("Runner failed");
+        assert!(output.generated && output.built);
+        assert!(!output.success());
+        assert_eq!(output.exit_code(), Some(3));
+        assert_eq!(output.stdout.trim(), "runner says hello world");
+        assert_eq!(output.stderr.trim(), "to stderr");
+        assert!(output.cargo_toml_path.exists());
+        assert!(output.executable_path.exists());
+    }
+
+    #[test]
+    fn test_runner_expression_cached() {
+        set_up();
+        let runner = Runner::new()
+            .expression(r#"println!("runner cache test");"#)
+            .infer(DependencyInference::None)
+            .capture_output(true);
+        let output = runner.clone().force(true).run().expect("Runner failed");
+        assert!(output.generated && output.built);
+
+        // The second run finds the executable in the dynamic cache
+        let output = runner.run().expect("Runner failed");
+        assert!(!output.generated);
+        assert!(!output.built);
+        assert_eq!(output.stdout.trim(), "runner cache test");
+    }
+}
@@ -0,0 +1,105 @@
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Once};
+    use tempfile::TempDir;
+    use thag_rs::templates::{available, create, load, render, script_path, Placeholders};
+
+    // Set environment variables before running tests
+    fn set_up() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| unsafe {
+            std::env::set_var("TEST_ENV", "1");
+            std::env::set_var("VISUAL", "cat");
+            std::env::set_var("EDITOR", "cat");
+        });
+    }
+
+    #[test]
+    fn test_templates_built_in() {
+        set_up();
+        let kinds = available(None).unwrap();
+        for kind in ["plain", "clap", "tokio", "ratatui", "profiled", "styled"] {
+            assert!(kinds.contains(&kind.to_string()), "missing {kind}");
+            let template = load(kind, None).unwrap();
+            assert!(template.starts_with("/*[toml]"), "{kind}");
+            assert!(template.contains("//# Purpose:"), "{kind}");
+            assert!(template.contains("{{name}}"), "{kind}");
+        }
+        assert!(load("profiled", None)
+            .unwrap()
+            .contains("#[enable_profiling"));
+        let err = load("nope", None).unwrap_err().to_string();
+        assert!(err.contains("Unknown template `nope`"));
+        assert!(err.contains("clap, plain, profiled"));
+    }
+
+    #[test]
+    fn test_templates_user_dir() {
+        set_up();
+        let temp_dir = TempDir::new().unwrap();
+        let user_dir = temp_dir.path();
+        fs::write(user_dir.join("axum.rs"), "// {{name}} by {{author}}\n").unwrap();
+        fs::write(user_dir.join("plain.rs"), "// my plain {{name}}\n").unwrap();
+        fs::write(user_dir.join("notes.txt"), "").unwrap();
+
+        let kinds = available(Some(user_dir)).unwrap();
+        assert_eq!(
+            kinds,
+            ["axum", "clap", "plain", "profiled", "ratatui", "styled", "tokio"]
+        );
+        // A user template takes precedence over a built-in one
+        assert_eq!(
+            load("plain", Some(user_dir)).unwrap(),
+            "// my plain {{name}}\n"
+        );
+        assert!(load("clap", Some(user_dir))
+            .unwrap()
+            .contains("use clap::Parser;"));
+    }
+
+    #[test]
+    fn test_templates_render() {
+        set_up();
+        let placeholders = Placeholders {
+            name: "my_tool".to_string(),
+            author: "A. Writer".to_string(),
+            date: "2025-01-31".to_string(),
+        };
+        assert_eq!(
+            render(
+                "/// {{name}}: by {{author}}, {{date}}\n// {{other}} {{name}}",
+                &placeholders
+            ),
+            "/// my_tool: by A. Writer, 2025-01-31\n// {{other}} my_tool"
+        );
+    }
+
+    #[test]
+    fn test_templates_script_path() {
+        set_up();
+        assert_eq!(script_path("my_tool").unwrap().to_str(), Some("my_tool.rs"));
+        assert_eq!(
+            script_path("demo/my-tool.rs").unwrap().to_str(),
+            Some("demo/my-tool.rs")
+        );
+        assert!(script_path("1tool").is_err());
+        assert!(script_path("my tool").is_err());
+        assert!(script_path(".rs").is_err());
+    }
+
+    #[test]
+    fn test_templates_create() {
+        set_up();
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("scripts").join("hello_clap.rs");
+        create(&path, "clap", None, "A. Writer").unwrap();
+        let source = fs::read_to_string(&path).unwrap();
+        assert!(source.contains("/// hello_clap: a command-line tool"));
+        assert!(source.contains("Author: A. Writer, created "));
+        assert!(source.contains(r#"#[command(name = "hello_clap""#));
+        assert!(!source.contains("{{"));
+        // Won't overwrite
+        let err = create(&path, "plain", None, "A. Writer").unwrap_err();
+        assert!(err.to_string().contains("already exists"));
+    }
+}
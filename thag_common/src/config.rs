@@ -38,6 +38,8 @@ pub struct Config {
     pub dependencies: Dependencies, // New section
     /// Miscellaneous settings
     pub misc: Misc,
+    /// Default resource limits for running scripts
+    pub run: Run,
 }
 
 /// Result type alias for config operations
@@ -75,6 +77,8 @@ struct PartialConfig {
     dependencies: Option<Dependencies>,
     #[serde(default)]
     misc: Option<Misc>,
+    #[serde(default)]
+    run: Option<Run>,
 }
 
 impl Config {
@@ -187,6 +191,9 @@ impl Config {
         if let Some(misc) = partial.misc {
             self.misc = misc;
         }
+        if let Some(run) = partial.run {
+            self.run = run;
+        }
     }
 
     fn validate(&self) -> ConfigResult<()> {
@@ -195,6 +202,28 @@ impl Config {
             .validate()
             .map_err(|e| ConfigError::Generic(format!("Dependencies validation failed: {e}")))?;
 
+        // Validate Misc section
+        if let Some(ref edition) = self.misc.edition {
+            if !["2015", "2018", "2021", "2024"].contains(&edition.as_str()) {
+                return Err(ConfigError::Generic(format!(
+                    "Misc validation failed: unknown Rust edition {edition}"
+                )));
+            }
+        }
+
+        // Validate Run section
+        for (name, limit) in [
+            ("timeout_secs", self.run.timeout_secs),
+            ("max_memory_mb", self.run.max_memory_mb),
+            ("max_cpu_secs", self.run.max_cpu_secs),
+        ] {
+            if limit == Some(0) {
+                return Err(ConfigError::Generic(format!(
+                    "Run validation failed: {name} must be greater than 0"
+                )));
+            }
+        }
+
         // Add validation for other sections as needed
         Ok(())
     }
@@ -219,6 +248,12 @@ pub struct Dependencies {
     pub global_excluded_features: Vec<String>,
     /// How much `thag_rs` should intervene in inferring dependencies from code.
     pub inference_level: DependencyInference,
+    /// Directory of crates vendored by `cargo vendor`, consulted before the local registry index for offline lookups.
+    pub vendor_dir: Option<String>,
+    /// Hours for which crate lookups (versions and features) are cached between runs. 0 disables the cache.
+    pub lookup_cache_ttl_hours: u64,
+    /// Async runtime for snippets and loop expressions that use `.await`: "tokio", "smol" or "async-std".
+    pub async_runtime: AsyncRuntime,
     // /// `false` specifies a detailed dependency with `default-features = false`.
     // pub default_features: bool,
 }
@@ -233,6 +268,9 @@ impl Default for Dependencies {
             feature_overrides: HashMap::<String, FeatureOverride>::new(),
             global_excluded_features: vec![],
             inference_level: DependencyInference::Config,
+            vendor_dir: None,
+            lookup_cache_ttl_hours: 24,
+            async_runtime: AsyncRuntime::default(),
         }
     }
 }
@@ -487,6 +525,45 @@ pub struct FeatureOverride {
     pub default_features: Option<bool>,
 }
 
+/// Async runtime for wrapping snippets that use `.await` in an async main function
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    Serialize,
+    EnumString,
+    Display,
+    PartialEq,
+    Eq,
+    Documented,
+    DocumentedVariants,
+)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum AsyncRuntime {
+    /// The `tokio` multi-threaded runtime
+    #[default]
+    Tokio,
+    /// The `smol` runtime
+    Smol,
+    /// The `async-std` runtime
+    AsyncStd,
+}
+
+impl AsyncRuntime {
+    /// The name of the runtime's crate as it appears in code.
+    #[must_use]
+    pub const fn crate_name(self) -> &'static str {
+        match self {
+            Self::Tokio => "tokio",
+            Self::Smol => "smol",
+            Self::AsyncStd => "async_std",
+        }
+    }
+}
+
 /// Logging settings
 #[derive(Clone, Debug, Default, Deserialize, Serialize, Documented, DocumentedFields)]
 #[serde(default)]
@@ -625,6 +702,35 @@ pub struct Misc {
     /// Strip double quotes from around string literals returned by snippets
     #[serde(deserialize_with = "boolean")]
     pub unquote: bool,
+    /// Rust edition for scripts whose toml block doesn't specify one: "2015", "2018", "2021" or "2024".
+    /// Defaults to "2021".
+    pub edition: Option<String>,
+    /// Maximum total size in megabytes of the cached script executables. When a build takes the
+    /// cache over this size, the least recently run executables are evicted. Unlimited if not set.
+    pub max_cache_size_mb: Option<u64>,
+    /// Maximum number of executables built from expressions, loop filters and stdin snippets
+    /// that are cached by content hash, so that repeating one skips the build. When a build
+    /// takes the cache over this number, the least recently run are evicted. Defaults to 50.
+    pub max_dynamic_cache_entries: Option<usize>,
+    /// Author name substituted for `{{author}}` in scripts created by `--new`. Defaults to the
+    /// `user.name` configured for git, or failing that the login name.
+    pub author: Option<String>,
+    /// Directory of user templates for `--new`, each a `<kind>.rs` file selected by
+    /// `--template <kind>`. A user template takes precedence over a built-in one of the same kind.
+    pub templates_dir: Option<String>,
+}
+
+/// Default resource limits for running scripts, each overridden by the corresponding command-line
+/// option. A script that exceeds a limit is stopped. Unlimited if not set.
+#[derive(Clone, Debug, Default, Documented, DocumentedFields, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Run {
+    /// Wall-clock time in seconds that a script may run for, as with `--timeout`.
+    pub timeout_secs: Option<u64>,
+    /// Virtual memory in megabytes that a script may use, as with `--max-memory`. Unix only.
+    pub max_memory_mb: Option<u64>,
+    /// CPU time in seconds that a script may use, as with `--max-cpu`. Unix only.
+    pub max_cpu_secs: Option<u64>,
 }
 
 /// Custom deserialisation method for booleans, to accept current true/false or legacy "true"/"false".
@@ -22,6 +22,7 @@ use documented::{Documented, DocumentedVariants};
 use parking_lot::ReentrantMutex;
 use serde::{Deserialize, Serialize};
 use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::LazyLock;
 use std::{path::PathBuf, time::Instant};
 use strum::{Display, EnumIter, EnumString, IntoStaticStr};
@@ -195,13 +196,17 @@ impl OutputManager {
 
     /// Output a message whether or not it passes the verbosity filter.
     pub fn prtln(&self, message: &str) {
-        println!("{message}");
+        if messages_to_stderr() {
+            eprintln!("{message}");
+        } else {
+            println!("{message}");
+        }
     }
 
     /// Output a message if it passes the verbosity filter.
     pub fn vprtln(&self, verbosity: Verbosity, message: &str) {
         if verbosity as u8 <= self.verbosity() as u8 {
-            println!("{message}");
+            self.prtln(message);
         }
     }
 
@@ -229,6 +234,21 @@ impl OutputManager {
 pub static OUTPUT_MANAGER: LazyLock<ReentrantMutex<OutputManager>> =
     LazyLock::new(|| ReentrantMutex::new(OutputManager::new(V::N)));
 
+/// Whether user messages are written to stderr instead of stdout.
+static MESSAGES_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Write user messages to stderr instead of stdout for the rest of the current execution,
+/// e.g. to keep stdout free for machine-readable output.
+pub fn set_messages_to_stderr(to_stderr: bool) {
+    MESSAGES_TO_STDERR.store(to_stderr, Ordering::Relaxed);
+}
+
+/// Return whether user messages are written to stderr instead of stdout.
+#[must_use]
+pub fn messages_to_stderr() -> bool {
+    MESSAGES_TO_STDERR.load(Ordering::Relaxed)
+}
+
 /// Set the output verbosity for the current execution.
 /// # Panics
 /// Will panic in debug mode if the global verbosity value is not the value we just set.
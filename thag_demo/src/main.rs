@@ -12,8 +12,10 @@ use std::path::{Path, PathBuf};
 use std::process;
 use std::{env, fs, io};
 use thag_rs::{
-    builder::execute, configure_log, get_verbosity, re, set_global_verbosity, sprtln, svprtln,
-    themed_inquire_config, Cli, Role, Styleable, StyledPrint, V,
+    builder::execute,
+    cmd_args::{MessageFormat, OutputFormat},
+    configure_log, get_verbosity, re, set_global_verbosity, sprtln, svprtln, themed_inquire_config,
+    Cli, Role, Styleable, StyledPrint, V,
 };
 
 pub mod visualization;
@@ -1096,6 +1098,7 @@ fn create_demo_cli_with_args(
         multimain: false,
         timings: opts.timings,
         verbose: if verbose { 2 } else { 0 },
+        message_format: MessageFormat::Human,
         normal_verbosity: false,
         quiet: 0,
         generate: opts.generate,
@@ -1104,11 +1107,37 @@ fn create_demo_cli_with_args(
         check: opts.check,
         expand: false,
         unquote: None,
+        format: OutputFormat::Debug,
         config: false,
         infer: None,
         cargo: false,
         test_only: false,
+        watch: false,
+        lock: false,
+        update_lock: false,
+        offline: false,
+        release: false,
+        profile: None,
+        edition: None,
+        timeout: None,
+        max_memory: None,
+        max_cpu: None,
         clean: None,
+        cache: None,
+        older_than: None,
+        check_all: None,
+        build_all: None,
+        jobs: None,
+        report: None,
+        new: None,
+        template: None,
+        export: None,
+        inline: None,
+        field_separator: None,
+        csv: false,
+        jsonl: false,
+        null_data: false,
+        in_place: None,
     }
 }
 
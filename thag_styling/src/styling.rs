@@ -4122,12 +4122,18 @@ macro_rules! cprtln {
 /// Styled print line macro (replacement for `cprtln!`)
 /// Format: `sprtln!(style: Style, "Lorem ipsum dolor {} amet", content: &str);`
 /// Also accepts Role: `sprtln!(Role::Code, "Hello {}", "world");`
+///
+/// Prints to stderr instead of stdout if `set_messages_to_stderr(true)` has been called.
 #[macro_export]
 macro_rules! sprtln {
     ($style:expr, $($arg:tt)*) => {{
         let content = format!("{}", format_args!($($arg)*));
         let painted = $crate::styling::Styler::to_style(&$style).paint(content);
-        println!("{painted}");
+        if $crate::messages_to_stderr() {
+            eprintln!("{painted}");
+        } else {
+            println!("{painted}");
+        }
     }};
 }
 